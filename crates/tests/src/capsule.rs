@@ -24,13 +24,31 @@ pub fn run() {
     fs::create_dir_all(&tmp_dir).expect("create dir");
 
     // test cases
-    test_build(&tmp_dir, &bin_path, "rust-demo", "rust").expect("rust demo");
-    test_build(&tmp_dir, &bin_path, "c-demo", "c").expect("c demo");
+    test_build(&tmp_dir, &bin_path, "rust-demo", "rust", &[]).expect("rust demo");
+    for variant in ["lock", "type", "type-id"] {
+        test_build(
+            &tmp_dir,
+            &bin_path,
+            &format!("rust-{}-demo", variant),
+            "rust",
+            &["--variant", variant],
+        )
+        .expect("rust variant demo");
+    }
+    test_build(&tmp_dir, &bin_path, "c-demo", "c", &[]).expect("c demo");
     test_build_sharedlib(&tmp_dir, &bin_path, "c-sharedlib-demo", "c-sharedlib")
         .expect("c sharedlib demo");
-    test_build(&tmp_dir, &bin_path, "lua-demo", "lua").expect("lua demo");
-    test_build(&tmp_dir, &bin_path, "lua-embedded-demo", "lua-embedded")
-        .expect("lua embedded demo");
+    test_build_sharedlib(&tmp_dir, &bin_path, "rust-sharedlib-demo", "rust-sharedlib")
+        .expect("rust sharedlib demo");
+    test_build(&tmp_dir, &bin_path, "lua-demo", "lua", &[]).expect("lua demo");
+    test_build(
+        &tmp_dir,
+        &bin_path,
+        "lua-embedded-demo",
+        "lua-embedded",
+        &[],
+    )
+    .expect("lua embedded demo");
 
     // clean
     fs::remove_dir_all(&tmp_dir).expect("remove dir");
@@ -41,6 +59,7 @@ fn test_build<P: AsRef<Path>>(
     bin_path: &str,
     name: &str,
     template_type: &str,
+    extra_args: &[&str],
 ) -> Result<(), Error> {
     let mut contract_path = PathBuf::new();
     contract_path.push(&dir);
@@ -52,6 +71,7 @@ fn test_build<P: AsRef<Path>>(
         .arg(name)
         .arg("--template")
         .arg(template_type)
+        .args(extra_args)
         .output()?;
     if !output.status.success() {
         panic!(
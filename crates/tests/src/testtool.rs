@@ -32,11 +32,7 @@ fn sign_tx(tx: TransactionView, key: &Privkey) -> TransactionView {
     blake2b.update(&tx_hash.raw_data());
     // digest the first witness
     let witness = WitnessArgs::default();
-    let zero_lock: Bytes = {
-        let mut buf = Vec::new();
-        buf.resize(SIGNATURE_SIZE, 0);
-        buf.into()
-    };
+    let zero_lock: Bytes = vec![0u8; SIGNATURE_SIZE].into();
     let witness_for_digest = witness
         .clone()
         .as_builder()
@@ -139,6 +135,16 @@ fn test_sighash_all_unlock(hash_type: ScriptHashType) {
     context
         .verify_tx(&tx, MAX_CYCLES)
         .expect("pass verification");
+
+    // run with suspension
+    for chunk_cycles in [1_000, 100_000, MAX_CYCLES] {
+        context
+            .verify_tx_with_suspension(&tx, MAX_CYCLES, chunk_cycles)
+            .expect("pass verification in chunks");
+    }
+    context
+        .verify_tx_with_suspension(&tx, 100_000, 10_000)
+        .expect_err("exceeded max cycles");
 }
 
 fn test_load_header() {
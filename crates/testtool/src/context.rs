@@ -1,8 +1,12 @@
+use crate::report;
 use crate::tx_verifier::OutputsDataVerifier;
 use ckb_chain_spec::consensus::{ConsensusBuilder, TYPE_ID_CODE_HASH};
 use ckb_error::Error as CKBError;
 use ckb_mock_tx_types::{MockCellDep, MockInfo, MockInput, MockTransaction, ReprMockTransaction};
-use ckb_script::{TransactionScriptsVerifier, TxVerifyEnv};
+use ckb_script::{
+    ScriptError, TransactionScriptError, TransactionScriptsVerifier, TransactionSnapshot,
+    TxVerifyEnv, VerifyResult,
+};
 use ckb_traits::{CellDataProvider, ExtensionProvider, HeaderProvider};
 use ckb_types::{
     bytes::Bytes,
@@ -43,6 +47,15 @@ pub fn random_type_id_script() -> Script {
         .build()
 }
 
+/// Whether the verification stopped because the scripts ran out of cycles
+fn exceeded_max_cycles(err: &CKBError) -> bool {
+    matches!(
+        err.downcast_ref::<TransactionScriptError>()
+            .map(|err| err.script_error()),
+        Some(ScriptError::ExceededMaximumCycles(_))
+    )
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Message {
     pub id: Byte32,
@@ -102,6 +115,15 @@ impl Context {
         out_point
     }
 
+    /// Deploy a shared library loaded by contracts with `ckb_dlopen`,
+    /// return the cell dep to add to transactions and the data hash referencing the library
+    pub fn deploy_library(&mut self, data: Bytes) -> (CellDep, Byte32) {
+        let data_hash = CellOutput::calc_data_hash(&data);
+        let out_point = self.deploy_cell(data);
+        let cell_dep = CellDep::new_builder().out_point(out_point).build();
+        (cell_dep, data_hash)
+    }
+
     /// Insert a block header into context
     pub fn insert_header(&mut self, header: HeaderView) {
         self.headers.insert(header.hash(), header);
@@ -307,7 +329,7 @@ impl Context {
 
                 let sub_out_points =
                     OutPointVec::from_slice(dep_group_data).expect("Parsing dep group error!");
-                out_points.extend(sub_out_points.into_iter());
+                out_points.extend(sub_out_points);
             } else {
                 out_points.push(cell_dep.out_point());
             }
@@ -354,9 +376,7 @@ impl Context {
         self.captured_messages.lock().unwrap().clone()
     }
 
-    /// Verify the transaction in CKB-VM
-    pub fn verify_tx(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, CKBError> {
-        self.verify_tx_consensus(tx)?;
+    fn build_verifier(&self, tx: &TransactionView) -> TransactionScriptsVerifier<Context> {
         let resolved_tx = self.build_resolved_tx(tx);
         let consensus = ConsensusBuilder::default()
             .hardfork_switch(HardForks {
@@ -386,7 +406,102 @@ impl Context {
                 println!("[contract debug] {}", msg);
             });
         }
-        verifier.verify(max_cycles)
+        verifier
+    }
+
+    /// Verify the transaction in CKB-VM
+    pub fn verify_tx(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, CKBError> {
+        let result = self.run_verify_tx(tx, max_cycles);
+        report::record(tx, &result);
+        result
+    }
+
+    fn run_verify_tx(&self, tx: &TransactionView, max_cycles: u64) -> Result<Cycle, CKBError> {
+        self.verify_tx_consensus(tx)
+            .and_then(|_| self.build_verifier(tx).verify(max_cycles))
+    }
+
+    /// Verify the transaction in CKB-VM the way a CKB node verifies large transactions:
+    /// run at most `chunk_cycles` cycles, suspend the VM into a snapshot, then resume from
+    /// the snapshot until the verification completes or `max_cycles` is exhausted.
+    pub fn verify_tx_in_chunks(
+        &self,
+        tx: &TransactionView,
+        max_cycles: u64,
+        chunk_cycles: u64,
+    ) -> Result<Cycle, CKBError> {
+        let result = self.run_verify_tx_in_chunks(tx, max_cycles, chunk_cycles);
+        report::record(tx, &result);
+        result
+    }
+
+    fn run_verify_tx_in_chunks(
+        &self,
+        tx: &TransactionView,
+        max_cycles: u64,
+        chunk_cycles: u64,
+    ) -> Result<Cycle, CKBError> {
+        assert!(chunk_cycles > 0, "chunk_cycles must be greater than 0");
+        self.verify_tx_consensus(tx)?;
+        let verifier = self.build_verifier(tx);
+        let mut result = verifier.resumable_verify(chunk_cycles.min(max_cycles))?;
+        let mut executed_cycles = chunk_cycles;
+        loop {
+            let state = match result {
+                VerifyResult::Completed(cycles) => return Ok(cycles),
+                VerifyResult::Suspended(state) => state,
+            };
+            let snap = TransactionSnapshot::try_from(state)?;
+            if executed_cycles >= max_cycles {
+                // the remaining budget is too small for another chunk,
+                // finish the verification with max_cycles as the hard limit
+                return verifier.complete(&snap, max_cycles);
+            }
+            let limit_cycles = chunk_cycles.min(max_cycles - executed_cycles);
+            result = verifier.resume_from_snap(&snap, limit_cycles)?;
+            executed_cycles = executed_cycles.saturating_add(chunk_cycles);
+        }
+    }
+
+    /// Verify the transaction both in a single run and in chunks of `chunk_cycles` cycles,
+    /// panic if the two verifications disagree on the consumed cycles or on the error.
+    ///
+    /// Contracts near the cycles limit may be suspended and resumed several times on chain,
+    /// this helps to catch bugs that only show up when the VM state is restored from a snapshot.
+    /// Debug output of the scripts is printed (or captured) twice, the outcome is reported once.
+    pub fn verify_tx_with_suspension(
+        &self,
+        tx: &TransactionView,
+        max_cycles: u64,
+        chunk_cycles: u64,
+    ) -> Result<Cycle, CKBError> {
+        let single_shot = self.run_verify_tx(tx, max_cycles);
+        let chunked = self.run_verify_tx_in_chunks(tx, max_cycles, chunk_cycles);
+        report::record(tx, &single_shot);
+        match (&single_shot, &chunked) {
+            (Ok(cycles), Ok(chunked_cycles)) => assert_eq!(
+                cycles, chunked_cycles,
+                "chunked verification consumed different cycles (chunk_cycles: {})",
+                chunk_cycles
+            ),
+            (Err(err), Err(chunked_err)) => {
+                // the limit reported by ExceededMaximumCycles depends on where the VM stopped
+                let both_exceeded = exceeded_max_cycles(err) && exceeded_max_cycles(chunked_err);
+                let (err, chunked_err) = (err.to_string(), chunked_err.to_string());
+                assert!(
+                    both_exceeded || err == chunked_err,
+                    "chunked verification failed with error {} but single-shot failed with {} (chunk_cycles: {})",
+                    chunked_err,
+                    err,
+                    chunk_cycles
+                );
+            }
+            _ => panic!(
+                "chunked verification result {:?} mismatches single-shot result {:?} (chunk_cycles: {})",
+                chunked, single_shot, chunk_cycles
+            ),
+        }
+        single_shot
     }
 
     /// Dump the transaction in mock transaction format, so we can offload it to ckb debugger
@@ -80,6 +80,8 @@
 
 pub mod builtin;
 pub mod context;
+pub mod loader;
+pub mod report;
 mod tx_verifier;
 
 // re-exports
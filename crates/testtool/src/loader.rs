@@ -0,0 +1,516 @@
+//! Load contract binaries built by capsule.
+//!
+//! The loader locates the capsule project by searching `capsule.toml` from the tests crate
+//! upwards, and loads binaries from `build/debug` or `build/release` according to the
+//! `CAPSULE_TEST_ENV` environment variable set by `capsule test`.
+//!
+//! # Example
+//!
+//! ``` rust,no_run
+//! use ckb_testtool::loader::Loader;
+//!
+//! let contract_bin = Loader::default().load_binary("my-contract");
+//! ```
+
+use ckb_types::bytes::Bytes;
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+pub const TEST_ENV_VAR: &str = "CAPSULE_TEST_ENV";
+const CONFIG_FILE: &str = "capsule.toml";
+const CONTRACTS_DIR: &str = "contracts";
+const BUILD_DIR: &str = "build";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TestEnv {
+    Debug,
+    Release,
+}
+
+impl TestEnv {
+    /// Read the test env from `CAPSULE_TEST_ENV`, default is `Debug`
+    pub fn from_env() -> Result<Self, LoaderError> {
+        match env::var(TEST_ENV_VAR) {
+            Ok(val) => val
+                .parse()
+                .map_err(|_| LoaderError::InvalidTestEnv(val.to_string())),
+            Err(_) => Ok(TestEnv::Debug),
+        }
+    }
+
+    fn build_dir_name(&self) -> &'static str {
+        match self {
+            TestEnv::Debug => "debug",
+            TestEnv::Release => "release",
+        }
+    }
+}
+
+impl FromStr for TestEnv {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "debug" => Ok(TestEnv::Debug),
+            "release" => Ok(TestEnv::Release),
+            _ => Err("no match"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoaderError {
+    /// Can't find `capsule.toml` in the directory or its ancestors
+    ProjectNotFound(PathBuf),
+    /// `capsule.toml` can't be parsed
+    InvalidConfig(PathBuf, String),
+    /// `CAPSULE_TEST_ENV` is neither `debug` nor `release`
+    InvalidTestEnv(String),
+    /// The contract is not declared in `[[contracts]]`
+    UnknownContract {
+        name: String,
+        contracts: Vec<String>,
+    },
+    /// The contract is declared but not built
+    BinaryNotFound {
+        name: String,
+        path: PathBuf,
+    },
+    /// The binary is older than one of the contract sources
+    StaleBinary {
+        name: String,
+        path: PathBuf,
+        source: PathBuf,
+    },
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for LoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoaderError::ProjectNotFound(dir) => write!(
+                f,
+                "can't find {} in {:?} or any parent directory, is the tests crate inside a capsule project?",
+                CONFIG_FILE, dir
+            ),
+            LoaderError::InvalidConfig(path, err) => {
+                write!(f, "failed to parse {:?}: {}", path, err)
+            }
+            LoaderError::InvalidTestEnv(val) => write!(
+                f,
+                "invalid {}={:?}, expect \"debug\" or \"release\"",
+                TEST_ENV_VAR, val
+            ),
+            LoaderError::UnknownContract { name, contracts } => write!(
+                f,
+                "contract '{}' is not declared in {}, available contracts: [{}]",
+                name,
+                CONFIG_FILE,
+                contracts.join(", ")
+            ),
+            LoaderError::BinaryNotFound { name, path } => write!(
+                f,
+                "can't find the binary of contract '{}' at {:?}, please run `capsule build` first",
+                name, path
+            ),
+            LoaderError::StaleBinary { name, path, source } => write!(
+                f,
+                "the binary of contract '{}' at {:?} is older than {:?}, please run `capsule build` again",
+                name, path, source
+            ),
+            LoaderError::Io(path, err) => write!(f, "failed to read {:?}: {}", path, err),
+        }
+    }
+}
+
+impl std::error::Error for LoaderError {}
+
+#[derive(Deserialize)]
+struct ProjectConfig {
+    #[serde(default)]
+    contracts: Vec<ContractConfig>,
+    #[serde(default)]
+    rust: RustConfig,
+}
+
+#[derive(Default, Deserialize)]
+struct RustConfig {
+    workspace_dir: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct ContractConfig {
+    name: String,
+    template_type: String,
+    /// Workspace of a Rust contract, relative to the project dir
+    workspace: Option<PathBuf>,
+    #[serde(default)]
+    build: BuildConfig,
+}
+
+#[derive(Default, Deserialize)]
+struct BuildConfig {
+    output_name: Option<String>,
+}
+
+impl ContractConfig {
+    fn binary_name(&self) -> String {
+        if let Some(output_name) = self.build.output_name.as_ref() {
+            return output_name.clone();
+        }
+        match self.template_type.as_str() {
+            "RustSharedLib" | "CSharedLib" | "CClangSharedLib" => format!("{}.so", self.name),
+            _ => self.name.clone(),
+        }
+    }
+
+    /// Source files of the contract, Rust crates are located the same way as `capsule build`:
+    /// `<workspace>/contracts/<name>`, or `<workspace>/<name>` if the workspace is inside
+    /// the contracts directory.
+    fn sources(&self, project_path: &Path, default_workspace: &Path) -> Vec<PathBuf> {
+        let contracts_path = project_path.join(CONTRACTS_DIR);
+        match self.template_type.as_str() {
+            "Rust" | "RustSharedLib" => {
+                let workspace_dir = match self.workspace.as_ref() {
+                    Some(dir) => project_path.join(dir),
+                    None => default_workspace.to_path_buf(),
+                };
+                let contracts_dir = if workspace_dir.starts_with(&contracts_path) {
+                    workspace_dir
+                } else {
+                    workspace_dir.join(CONTRACTS_DIR)
+                };
+                vec![contracts_dir.join(&self.name)]
+            }
+            "C" | "CSharedLib" => vec![contracts_path
+                .join("c")
+                .join("src")
+                .join(format!("{}.c", self.name))],
+            "CClang" | "CClangSharedLib" => vec![contracts_path
+                .join("c-clang")
+                .join("src")
+                .join(format!("{}.c", self.name))],
+            "LuaEmbedded" => vec![contracts_path
+                .join("lua")
+                .join("src")
+                .join(format!("{}.c", self.name))],
+            // standalone Lua scripts are loaded at runtime, the binary is the Lua loader
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Contract binaries loader
+pub struct Loader {
+    project_path: PathBuf,
+    env: TestEnv,
+    contracts: Vec<ContractConfig>,
+    /// The default workspace of Rust contracts
+    workspace_dir: PathBuf,
+}
+
+impl Default for Loader {
+    /// Locate the project and read the test env, panic on errors
+    fn default() -> Self {
+        Self::new().unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl Loader {
+    /// Locate the project from the directory of the tests crate,
+    /// falling back to the current directory
+    pub fn new() -> Result<Self, LoaderError> {
+        let dir = match env::var_os("CARGO_MANIFEST_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => env::current_dir().map_err(|err| LoaderError::Io(PathBuf::from("."), err))?,
+        };
+        Self::with_env(find_project_path(&dir)?, TestEnv::from_env()?)
+    }
+
+    /// Load the project at `project_path`, which must contain a `capsule.toml`
+    pub fn with_env<P: AsRef<Path>>(project_path: P, env: TestEnv) -> Result<Self, LoaderError> {
+        let project_path = project_path.as_ref().to_path_buf();
+        let config_path = project_path.join(CONFIG_FILE);
+        let content = fs::read_to_string(&config_path)
+            .map_err(|err| LoaderError::Io(config_path.clone(), err))?;
+        let config: ProjectConfig = toml_edit::de::from_str(&content)
+            .map_err(|err| LoaderError::InvalidConfig(config_path.clone(), err.to_string()))?;
+        let workspaces = config
+            .contracts
+            .iter()
+            .filter_map(|c| c.workspace.as_ref())
+            .chain(config.rust.workspace_dir.as_ref());
+        for dir in workspaces {
+            if dir.is_absolute() {
+                return Err(LoaderError::InvalidConfig(
+                    config_path,
+                    format!(
+                        "invalid workspace dir {:?}, it must be relative to the project dir",
+                        dir
+                    ),
+                ));
+            }
+        }
+        let workspace_dir = match config.rust.workspace_dir.as_ref() {
+            Some(dir) => project_path.join(dir),
+            None => project_path.clone(),
+        };
+        Ok(Loader {
+            project_path,
+            env,
+            contracts: config.contracts,
+            workspace_dir,
+        })
+    }
+
+    pub fn project_path(&self) -> &Path {
+        &self.project_path
+    }
+
+    pub fn env(&self) -> TestEnv {
+        self.env
+    }
+
+    /// Path of the contract binary, check the contract is declared, built and up to date
+    pub fn binary_path(&self, name: &str) -> Result<PathBuf, LoaderError> {
+        let contract = self
+            .contracts
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| LoaderError::UnknownContract {
+                name: name.to_string(),
+                contracts: self.contracts.iter().map(|c| c.name.clone()).collect(),
+            })?;
+        let mut path = self.project_path.join(BUILD_DIR);
+        path.push(self.env.build_dir_name());
+        path.push(contract.binary_name());
+        let built_at = match fs::metadata(&path).and_then(|m| m.modified()) {
+            Ok(built_at) => built_at,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(LoaderError::BinaryNotFound {
+                    name: name.to_string(),
+                    path,
+                });
+            }
+            Err(err) => return Err(LoaderError::Io(path, err)),
+        };
+        for source in contract.sources(&self.project_path, &self.workspace_dir) {
+            if let Some(source) = find_newer_file(&source, built_at) {
+                return Err(LoaderError::StaleBinary {
+                    name: name.to_string(),
+                    path,
+                    source,
+                });
+            }
+        }
+        Ok(path)
+    }
+
+    pub fn try_load_binary(&self, name: &str) -> Result<Bytes, LoaderError> {
+        let path = self.binary_path(name)?;
+        fs::read(&path)
+            .map(Into::into)
+            .map_err(|err| LoaderError::Io(path, err))
+    }
+
+    /// Load the contract binary, panic on errors
+    pub fn load_binary(&self, name: &str) -> Bytes {
+        self.try_load_binary(name)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+fn find_project_path(dir: &Path) -> Result<PathBuf, LoaderError> {
+    dir.ancestors()
+        .find(|path| path.join(CONFIG_FILE).is_file())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| LoaderError::ProjectNotFound(dir.to_path_buf()))
+}
+
+/// Return a file under `path` which is modified after `time`,
+/// cargo `target` directories are skipped.
+fn find_newer_file(path: &Path, time: SystemTime) -> Option<PathBuf> {
+    let metadata = fs::metadata(path).ok()?;
+    if metadata.is_dir() {
+        if path
+            .file_name()
+            .map(|name| name == "target")
+            .unwrap_or(false)
+        {
+            return None;
+        }
+        fs::read_dir(path)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .find_map(|entry| find_newer_file(&entry.path(), time))
+    } else {
+        metadata
+            .modified()
+            .ok()
+            .filter(|modified| *modified > time)
+            .map(|_| path.to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::FileTime;
+    use std::time::Duration;
+
+    fn setup_project(name: &str, config: &str) -> PathBuf {
+        let mut path = env::temp_dir();
+        path.push(format!(
+            "ckb-testtool-loader-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("build/debug")).unwrap();
+        fs::create_dir_all(path.join("contracts/c/src")).unwrap();
+        fs::write(path.join(CONFIG_FILE), config).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_binary() {
+        let path = setup_project(
+            "load",
+            r#"
+version = "0.10.4"
+
+[[contracts]]
+name = "lib"
+template_type = "CSharedLib"
+"#,
+        );
+        fs::write(path.join("contracts/c/src/lib.c"), "int main() {}").unwrap();
+        let loader = Loader::with_env(&path, TestEnv::Debug).unwrap();
+
+        assert!(matches!(
+            loader.try_load_binary("unknown"),
+            Err(LoaderError::UnknownContract { .. })
+        ));
+        assert!(matches!(
+            loader.try_load_binary("lib"),
+            Err(LoaderError::BinaryNotFound { .. })
+        ));
+        fs::write(path.join("build/debug/lib.so"), "binary").unwrap();
+        assert_eq!(loader.load_binary("lib"), Bytes::from("binary"));
+
+        let built_at = fs::metadata(path.join("build/debug/lib.so"))
+            .and_then(|m| m.modified())
+            .unwrap();
+        filetime::set_file_mtime(
+            path.join("contracts/c/src/lib.c"),
+            FileTime::from_system_time(built_at + Duration::from_secs(1)),
+        )
+        .unwrap();
+        assert!(matches!(
+            loader.try_load_binary("lib"),
+            Err(LoaderError::StaleBinary { .. })
+        ));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_binary_name() {
+        let contract = ContractConfig {
+            name: "lib".to_string(),
+            template_type: "RustSharedLib".to_string(),
+            workspace: None,
+            build: BuildConfig::default(),
+        };
+        assert_eq!(contract.binary_name(), "lib.so");
+        let project = Path::new("/project");
+        assert_eq!(
+            contract.sources(project, project),
+            vec![PathBuf::from("/project/contracts/lib")]
+        );
+    }
+
+    #[test]
+    fn test_output_name() {
+        let path = setup_project(
+            "output-name",
+            r#"
+version = "0.10.5"
+
+[[contracts]]
+name = "lock"
+template_type = "C"
+
+[contracts.build]
+output_name = "lock-v2"
+"#,
+        );
+        let loader = Loader::with_env(&path, TestEnv::Debug).unwrap();
+        fs::write(path.join("build/debug/lock-v2"), "binary").unwrap();
+        assert_eq!(
+            loader.binary_path("lock").unwrap(),
+            path.join("build/debug/lock-v2")
+        );
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_rust_workspaces() {
+        let path = setup_project(
+            "workspaces",
+            r#"
+version = "0.10.5"
+
+[rust]
+workspace_dir = "contracts"
+
+[[contracts]]
+name = "a"
+template_type = "Rust"
+
+[[contracts]]
+name = "b"
+template_type = "Rust"
+workspace = "libs"
+"#,
+        );
+        let loader = Loader::with_env(&path, TestEnv::Debug).unwrap();
+        let sources = |name: &str| {
+            let contract = loader.contracts.iter().find(|c| c.name == name).unwrap();
+            contract.sources(&loader.project_path, &loader.workspace_dir)
+        };
+        assert_eq!(sources("a"), vec![path.join("contracts/a")]);
+        assert_eq!(sources("b"), vec![path.join("libs/contracts/b")]);
+
+        fs::create_dir_all(path.join("libs/contracts/b/src")).unwrap();
+        fs::write(path.join("build/debug/b"), "binary").unwrap();
+        let built_at = fs::metadata(path.join("build/debug/b"))
+            .and_then(|m| m.modified())
+            .unwrap();
+        fs::write(path.join("libs/contracts/b/src/main.rs"), "").unwrap();
+        filetime::set_file_mtime(
+            path.join("libs/contracts/b/src/main.rs"),
+            FileTime::from_system_time(built_at + Duration::from_secs(1)),
+        )
+        .unwrap();
+        assert!(matches!(
+            loader.try_load_binary("b"),
+            Err(LoaderError::StaleBinary { source, .. }) if source == path.join("libs/contracts/b/src/main.rs")
+        ));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_find_project_path() {
+        let path = setup_project("find", "");
+        let tests_dir = path.join("tests/src");
+        fs::create_dir_all(&tests_dir).unwrap();
+        assert_eq!(find_project_path(&tests_dir).unwrap(), path);
+        fs::remove_dir_all(path).unwrap();
+    }
+}
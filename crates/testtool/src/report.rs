@@ -0,0 +1,158 @@
+//! Record the outcome of each verified transaction.
+//!
+//! `capsule test` sets `CAPSULE_TEST_REPORT_FILE` to collect outcomes of transactions verified
+//! by `Context::verify_tx` and the chunked verifications, one JSON object per line. Nothing is
+//! recorded if the variable is unset.
+//!
+//! Outcomes are attributed to the test running in the current thread, libtest runs each test in
+//! a thread named after the test path. Transactions verified in other threads can't be
+//! attributed, so recording them panics.
+
+use ckb_error::Error as CKBError;
+use ckb_script::{ScriptError, TransactionScriptError};
+use ckb_types::core::{Cycle, TransactionView};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+
+pub const REPORT_FILE_ENV_VAR: &str = "CAPSULE_TEST_REPORT_FILE";
+
+/// Outcome of a transaction verification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxOutcome {
+    /// Name of the test which verifies the transaction
+    pub test: String,
+    /// Index of the transaction among the transactions verified by the test
+    pub index: usize,
+    pub tx_hash: String,
+    pub cycles: Option<Cycle>,
+    pub error: Option<String>,
+    /// Exit code returned by the failed script
+    pub exit_code: Option<i8>,
+}
+
+impl TxOutcome {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+struct Recorder {
+    file: File,
+    tx_counts: HashMap<String, usize>,
+}
+
+lazy_static! {
+    static ref RECORDER: Option<Mutex<Recorder>> = env::var_os(REPORT_FILE_ENV_VAR).map(|path| {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap_or_else(|err| panic!("failed to open {:?}: {}", path, err));
+        Mutex::new(Recorder {
+            file,
+            tx_counts: HashMap::default(),
+        })
+    });
+}
+
+/// Extract the exit code if the verification failed on a non-zero script exit code
+pub fn exit_code(err: &CKBError) -> Option<i8> {
+    let err: &TransactionScriptError = err.root_cause().downcast_ref()?;
+    match err.script_error() {
+        ScriptError::ValidationFailure(_, exit_code) => Some(*exit_code),
+        _ => None,
+    }
+}
+
+/// Return true if the name is a path like `tests::test_transfer`
+fn is_test_path(name: &str) -> bool {
+    name != "main"
+        && name.split("::").all(|segment| {
+            let mut chars = segment.chars();
+            chars
+                .next()
+                .map(|c| c.is_alphabetic() || c == '_')
+                .unwrap_or(false)
+                && chars.all(|c| c.is_alphanumeric() || c == '_')
+        })
+}
+
+/// Name of the test running in the current thread
+fn test_name() -> String {
+    match thread::current().name() {
+        // libtest runs each test in a thread named after the test
+        Some(name) if is_test_path(name) => name.to_string(),
+        name => panic!(
+            "can't tell which test verifies the transaction in thread {:?} for {}, \
+             verify transactions in the thread of the test instead of a spawned thread",
+            name.unwrap_or("<unnamed>"),
+            REPORT_FILE_ENV_VAR
+        ),
+    }
+}
+
+/// Record the verification result if `CAPSULE_TEST_REPORT_FILE` is set
+pub fn record(tx: &TransactionView, result: &Result<Cycle, CKBError>) {
+    let recorder = match RECORDER.as_ref() {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    let test = test_name();
+    let mut recorder = recorder.lock().unwrap_or_else(|err| err.into_inner());
+    let index = {
+        let count = recorder.tx_counts.entry(test.clone()).or_default();
+        *count += 1;
+        *count - 1
+    };
+    let outcome = TxOutcome {
+        test,
+        index,
+        tx_hash: format!("{:#x}", tx.hash()),
+        cycles: result.as_ref().ok().copied(),
+        error: result.as_ref().err().map(|err| err.to_string()),
+        exit_code: result.as_ref().err().and_then(exit_code),
+    };
+    let mut line = serde_json::to_string(&outcome).expect("serialize outcome");
+    line.push('\n');
+    recorder
+        .file
+        .write_all(line.as_bytes())
+        .expect("write outcome");
+}
+
+/// Read outcomes from a report file
+pub fn read_outcomes<P: AsRef<Path>>(path: P) -> io::Result<Vec<TxOutcome>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_test_name() {
+        assert_eq!(test_name(), "report::tests::test_test_name");
+        for name in ["tests::test_transfer", "test_1"] {
+            assert!(is_test_path(name), "{}", name);
+        }
+        for name in ["main", "worker-1", "", "tests::", "1st"] {
+            assert!(!is_test_path(name), "{}", name);
+        }
+        let spawned = thread::spawn(test_name).join();
+        assert!(spawned.is_err());
+    }
+}
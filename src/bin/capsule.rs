@@ -6,28 +6,45 @@ use std::process::exit;
 use std::str::FromStr;
 
 use anyhow::{anyhow, Result};
+use ckb_capsule::build_image::{default_image, update_pin, ImageKind};
+use ckb_capsule::builder::{build_contracts, rebuild_stale_contracts, BuildOptions};
 use ckb_capsule::checker::Checker;
-use ckb_capsule::config::{Contract, TemplateType};
-use ckb_capsule::config_manipulate::{append_contract, Document};
+use ckb_capsule::config::{ContainerRuntime, Contract, ContractVariant, TemplateType};
+use ckb_capsule::config_check::{self, Severity};
+use ckb_capsule::config_manipulate::{append_contract_config, Document};
 use ckb_capsule::debugger;
 use ckb_capsule::debugger::DebuggerArgs;
 use ckb_capsule::deployment::manage::{DeployOption, Manage as DeployManage};
-use ckb_capsule::generator::new_project;
+use ckb_capsule::generator::external::{self, ExternalTemplate, TemplateSource};
+use ckb_capsule::generator::{import, modify, new_project};
+use ckb_capsule::inspector::Inspection;
 use ckb_capsule::project_context::{
     read_config_file, write_config_file, BuildConfig, BuildEnv, Context, DeployEnv, CONFIG_FILE,
 };
 use ckb_capsule::recipe::get_recipe;
 use ckb_capsule::signal;
+use ckb_capsule::tester::report::ReportFormat;
 use ckb_capsule::tester::Tester;
+use ckb_capsule::upgrade;
+use ckb_capsule::verify_build::{parse_hash, verify_build, ExpectedHash};
 use ckb_capsule::version::Version;
 use ckb_capsule::wallet::{Wallet, DEFAULT_CKB_CLI_BIN_NAME, DEFAULT_CKB_RPC_URL};
 use ckb_sdk::{Address, HumanCapacity};
 use ckb_testtool::ckb_types::core::Capacity;
 
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
 const DEBUGGER_MAX_CYCLES: u64 = 70_000_000u64;
-const TEMPLATES_NAMES: &[&str] = &["rust", "c", "c-sharedlib", "lua", "lua-embedded"];
+const TEMPLATES_NAMES: &[&str] = &[
+    "rust",
+    "rust-sharedlib",
+    "c",
+    "c-sharedlib",
+    "c-clang",
+    "c-clang-sharedlib",
+    "lua",
+    "lua-embedded",
+];
 
 fn append_contract_to_config(context: &Context, contract: &Contract) -> Result<()> {
     println!("Rewrite capsule.toml");
@@ -35,7 +52,7 @@ fn append_contract_to_config(context: &Context, contract: &Contract) -> Result<(
     config_path.push(CONFIG_FILE);
     let config_content = read_config_file(&config_path)?;
     let mut doc = config_content.parse::<Document>()?;
-    append_contract(&mut doc, contract.name.to_string(), contract.template_type)?;
+    append_contract_config(&mut doc, contract)?;
     write_config_file(&config_path, doc.to_string())?;
     Ok(())
 }
@@ -61,6 +78,28 @@ fn group_contracts_by_type(contracts: Vec<Contract>) -> HashMap<TemplateType, Ve
     contracts_by_type
 }
 
+fn parse_variant(args: &ArgMatches, template_type: TemplateType) -> Result<ContractVariant> {
+    let variant: ContractVariant = args.value_of("variant").expect("variant").parse()?;
+    if variant != ContractVariant::Default && template_type != TemplateType::Rust {
+        return Err(anyhow!(
+            "the {} variant is only supported by the rust template",
+            variant.name()
+        ));
+    }
+    Ok(variant)
+}
+
+fn fetch_external_template(args: &ArgMatches) -> Result<Option<ExternalTemplate>> {
+    let source = match args.value_of("template-source") {
+        Some(source) => TemplateSource::new(source, args.value_of("template-ref"))?,
+        None => return Ok(None),
+    };
+    if args.occurrences_of("variant") > 0 {
+        return Err(anyhow!("--variant can't be used with an external template"));
+    }
+    Ok(Some(ExternalTemplate::fetch(&source)?))
+}
+
 fn get_last_args() -> (Vec<String>, Vec<String>) {
     let args: Vec<String> = env::args().collect();
     let mut iter = args.splitn(2, |n| n == "--");
@@ -88,6 +127,21 @@ fn run_cli() -> Result<()> {
             .possible_values(TEMPLATES_NAMES)
             .default_value(TEMPLATES_NAMES[0])
             .takes_value(true),
+        Arg::with_name("variant")
+            .long("variant")
+            .help("variant of the rust template, lock and type scripts come with tests")
+            .possible_values(&ContractVariant::NAMES)
+            .default_value(ContractVariant::NAMES[0])
+            .takes_value(true),
+        Arg::with_name("template-source")
+            .long("template-source")
+            .help("directory or git URL of an external template with a capsule-template.toml, overrides --template")
+            .takes_value(true),
+        Arg::with_name("template-ref")
+            .long("template-ref")
+            .help("branch, tag or commit of the git template source")
+            .requires("template-source")
+            .takes_value(true),
     ];
 
     let mut app = App::new("Capsule")
@@ -101,7 +155,24 @@ fn run_cli() -> Result<()> {
                 .help("CKB cli binary").default_value(DEFAULT_CKB_CLI_BIN_NAME).takes_value(true),
         ]).display_order(0))
         .subcommand(SubCommand::with_name("new").about("Create a new project").args(&contract_args).display_order(1))
-        .subcommand(SubCommand::with_name("new-contract").about("Create a new contract").args(&contract_args).display_order(2))
+        .subcommand(SubCommand::with_name("new-contract").about("Create a new contract").args(&contract_args).arg(
+            Arg::with_name("workspace").long("workspace").takes_value(true).help("Workspace dir of the rust contract relative to the project dir, default is `workspace_dir` in capsule.toml")
+        ).display_order(2))
+        .subcommand(SubCommand::with_name("import").about("Import an existing Rust contract crate").args(&[
+            Arg::with_name("path").help("path of the crate").index(1).required(true).takes_value(true),
+            Arg::with_name("link").long("link").help("Link the crate into the contracts dir instead of copying it"),
+            Arg::with_name("workspace").long("workspace").takes_value(true).help("Workspace dir of the contract relative to the project dir, default is `workspace_dir` in capsule.toml"),
+        ]).display_order(2))
+        .subcommand(SubCommand::with_name("upgrade").about("Upgrade a project created by an older version of capsule").arg(
+            Arg::with_name("dry-run").long("dry-run").help("Show the changes of each step without writing them")
+        ).display_order(2))
+        .subcommand(SubCommand::with_name("remove-contract").about("Remove a contract with its sources, build rules, binaries and generated tests").arg(
+            Arg::with_name("name").help("contract name").index(1).required(true).takes_value(true)
+        ).display_order(2))
+        .subcommand(SubCommand::with_name("rename-contract").about("Rename a contract with its sources, build rules and generated tests").args(&[
+            Arg::with_name("name").help("contract name").index(1).required(true).takes_value(true),
+            Arg::with_name("new-name").help("new contract name").index(2).required(true).takes_value(true),
+        ]).display_order(2))
         .subcommand(
             SubCommand::with_name("build")
                 .about("Build contracts")
@@ -111,6 +182,9 @@ fn run_cli() -> Result<()> {
                 .arg(Arg::with_name("no-remap").long("no-remap").help("Don't remap path prefixes"))
                 .arg(Arg::with_name("host").long("host").help("Docker runs in host mode"))
                 .arg(Arg::with_name("rustup-dir").long("rustup-dir").takes_value(true).help("Mount the directory to /root/.rustup in docker image"))
+                .arg(Arg::with_name("jobs").short("j").long("jobs").takes_value(true).default_value("1").help("Number of contracts to build concurrently"))
+                .arg(Arg::with_name("no-cache").long("no-cache").help("Rebuild contracts even if their sources, build options and toolchain are unchanged"))
+                .arg(Arg::with_name("build-mode").long("build-mode").possible_values(&["docker", "native"]).takes_value(true).help("Build C and Lua contracts in docker or with the local RISC-V toolchain, overrides `build_mode` in capsule.toml"))
                 .display_order(3))
         .subcommand(SubCommand::with_name("run").about("Run command in contract build image").usage("ckb_capsule run --name <name> 'echo list contract dir: && ls'")
         .args(&[Arg::with_name("name").short("n").long("name").required(true).takes_value(true).help("contract name"),
@@ -118,6 +192,21 @@ fn run_cli() -> Result<()> {
         .display_order(4))
         .subcommand(SubCommand::with_name("test").about("Run `cargo test` in the tests directory").arg(
             Arg::with_name("release").long("release").help("Test release mode contracts.").display_order(1)
+        ).arg(
+            Arg::with_name("differential").long("differential").conflicts_with("release")
+                .help("Test both debug and release mode contracts and compare the outcome of each transaction.").display_order(1)
+        ).arg(
+            Arg::with_name("no-build").long("no-build")
+                .help("Don't rebuild contracts whose sources changed since the last build.").display_order(1)
+        ).arg(
+            Arg::with_name("build-mode").long("build-mode").possible_values(&["docker", "native"]).takes_value(true)
+                .help("Rebuild C and Lua contracts in docker or with the local RISC-V toolchain.").display_order(1)
+        ).arg(
+            Arg::with_name("report").long("report").possible_values(&["json", "junit"]).takes_value(true).conflicts_with("differential")
+                .help("Write machine-readable test results in the format.").display_order(1)
+        ).arg(
+            Arg::with_name("report-file").long("report-file").takes_value(true).requires("report")
+                .help("Path of the test results file, default is build/test-report.{json,xml}").display_order(1)
         ).arg(
             Arg::with_name("testname")
                 .takes_value(true)
@@ -152,6 +241,53 @@ fn run_cli() -> Result<()> {
                         .help("CKB cli binary").default_value(DEFAULT_CKB_CLI_BIN_NAME).takes_value(true),
                 ]).display_order(6),
         )
+        .subcommand(
+            SubCommand::with_name("verify-build")
+                .about("Rebuild a contract in release mode and compare its data hash with the deployed one")
+                .args(&[
+                    Arg::with_name("name").help("contract name").index(1).required(true).takes_value(true),
+                    Arg::with_name("hash").long("hash").help("Expected data hash").takes_value(true),
+                    Arg::with_name("env").long("env").help("Read the expected data hash from the deployment snapshot of the environment.")
+                        .possible_values(&["dev", "production"]).default_value("dev").takes_value(true),
+                    Arg::with_name("snapshot").long("snapshot").conflicts_with("hash").takes_value(true)
+                        .help("Deployment snapshot file, default is the last snapshot in migrations/<env>"),
+                    Arg::with_name("cell").long("cell").conflicts_with("hash").takes_value(true)
+                        .help("Cell name in the deployment snapshot, default is the contract name"),
+                    Arg::with_name("allow-unpinned").long("allow-unpinned")
+                        .help("Verify the build even if the build image is not pinned by digest"),
+                ]).display_order(6),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Inspect the binary of a built contract")
+                .args(&[
+                    Arg::with_name("name").help("contract name").index(1).required(true).takes_value(true),
+                    Arg::with_name("release").long("release").help("Inspect the release mode binary."),
+                    Arg::with_name("symbols").long("symbols").help("Number of largest symbols to show").default_value("10").takes_value(true),
+                ]).display_order(7),
+        )
+        .subcommand(
+            SubCommand::with_name("image")
+                .about("Manage the build images pinned in capsule.toml")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").about("List the build images"))
+                .subcommand(
+                    SubCommand::with_name("update")
+                        .about("Pull the tags of build images and pin them to the pulled digests")
+                        .args(&[
+                            Arg::with_name("kind").help("Kinds of images to update, default is all the pinned images").possible_values(&["rust", "c", "lua", "debugger"]).multiple(true).index(1),
+                            Arg::with_name("image").long("image").takes_value(true).help("Pin this image instead, e.g. `nervos/ckb-riscv-gnu-toolchain:focal-20230214`"),
+                        ]),
+                )
+                .display_order(8),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Manage capsule.toml")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("check").about("Check capsule.toml and report the problems with their locations"))
+                .display_order(8),
+        )
         .subcommand(SubCommand::with_name("clean").about("Remove contracts targets and binaries").arg(Arg::with_name("name").short("n").long("name").multiple(true).takes_value(true).help("contract name"))
         .display_order(7))
         .subcommand(
@@ -234,7 +370,12 @@ fn run_cli() -> Result<()> {
     match matches.subcommand() {
         ("check", Some(args)) => {
             let ckb_cli_bin = args.value_of("ckb-cli").expect("ckb-cli");
-            Checker::build(ckb_cli_bin).print_report();
+            // use the runtime of the project in the current directory if there is one
+            let container_runtime = match Context::load() {
+                Ok(context) => context.config.container_runtime,
+                Err(_) => ContainerRuntime::from_env()?.unwrap_or_default(),
+            };
+            Checker::build(ckb_cli_bin, container_runtime).print_report();
         }
         ("new", Some(args)) => {
             let mut name = args
@@ -243,8 +384,12 @@ fn run_cli() -> Result<()> {
                 .trim()
                 .trim_end_matches('/')
                 .to_string();
-            let template_type: TemplateType =
-                args.value_of("template").expect("template").parse()?;
+            let external_template = fetch_external_template(args)?;
+            let template_type: TemplateType = match &external_template {
+                Some(template) => template.template_type(),
+                None => args.value_of("template").expect("template").parse()?,
+            };
+            let variant = parse_variant(args, template_type)?;
             let mut path = PathBuf::new();
             if let Some(index) = name.rfind('/') {
                 path.push(&name[..index]);
@@ -252,34 +397,97 @@ fn run_cli() -> Result<()> {
             } else {
                 path.push(env::current_dir()?);
             }
-            let project_path = new_project(name.to_string(), path)?;
+            // the example tests run the contract as a lock script
+            let example_tests = external_template.is_none()
+                && variant == ContractVariant::Default
+                && !template_type.is_sharedlib();
+            let project_path = new_project(name.to_string(), path, example_tests)?;
             let context = Context::load_from_path(project_path)?;
-            let c = Contract {
-                name,
-                template_type,
+            let c = match &external_template {
+                Some(template) => external::create_contract(&context, name, template)?,
+                None => {
+                    let c = Contract::new(name, template_type);
+                    get_recipe(context.clone(), c.template_type)?
+                        .create_contract(&c, variant, true, &signal, env_file)?;
+                    c
+                }
             };
-            get_recipe(context.clone(), c.template_type)?
-                .create_contract(&c, true, &signal, env_file)?;
             append_contract_to_config(&context, &c)?;
             println!("Done");
         }
         ("new-contract", Some(args)) => {
             let context = Context::load()?;
             let name = args.value_of("name").expect("name").trim().to_string();
+            if let Some(template) = fetch_external_template(args)? {
+                if args.is_present("workspace") {
+                    return Err(anyhow!(
+                        "--workspace can't be used with an external template"
+                    ));
+                }
+                let contract = external::create_contract(&context, name, &template)?;
+                append_contract_to_config(&context, &contract)?;
+                println!("Done");
+                return Ok(());
+            }
             let template_type: TemplateType =
                 args.value_of("template").expect("template").parse()?;
-            let contract = Contract {
-                name,
-                template_type,
-            };
+            let variant = parse_variant(args, template_type)?;
+            let mut contract = Contract::new(name, template_type);
+            if let Some(workspace) = args.value_of("workspace") {
+                if !matches!(
+                    template_type,
+                    TemplateType::Rust | TemplateType::RustSharedLib
+                ) {
+                    return Err(anyhow!("--workspace is only supported by rust contracts"));
+                }
+                contract.workspace = Some(PathBuf::from(workspace));
+            }
             let recipe = get_recipe(context.clone(), contract.template_type)?;
             if recipe.exists(&contract.name) {
                 return Err(anyhow!("contract '{}' is already exists", contract.name));
             }
-            recipe.create_contract(&contract, true, &signal, env_file)?;
+            recipe.create_contract(&contract, variant, true, &signal, env_file)?;
             append_contract_to_config(&context, &contract)?;
             println!("Done");
         }
+        ("import", Some(args)) => {
+            let context = Context::load()?;
+            let path = Path::new(args.value_of("path").expect("path"));
+            let workspace = args.value_of("workspace").map(PathBuf::from);
+            let contract =
+                import::import_contract(&context, path, args.is_present("link"), workspace)?;
+            append_contract_to_config(&context, &contract)?;
+            println!("Done");
+        }
+        ("upgrade", Some(args)) => {
+            let context = Context::load_from_path_unchecked(env::current_dir()?)?;
+            let plan = upgrade::plan(&context)?;
+            if plan.is_empty() {
+                println!("The project is up to date");
+                return Ok(());
+            }
+            let dry_run = args.is_present("dry-run");
+            plan.print(dry_run);
+            if dry_run {
+                println!("Dry run, no files are changed");
+            } else {
+                plan.apply(&context.project_path)?;
+                println!("Done");
+            }
+        }
+        ("remove-contract", Some(args)) => {
+            let context = Context::load()?;
+            let name = args.value_of("name").expect("name").trim();
+            modify::remove_contract(&context, name)?;
+            println!("Done");
+        }
+        ("rename-contract", Some(args)) => {
+            let context = Context::load()?;
+            let name = args.value_of("name").expect("name").trim();
+            let new_name = args.value_of("new-name").expect("new name").trim();
+            modify::rename_contract(&context, name, new_name)?;
+            println!("Done, please rebuild the contract");
+        }
         ("build", Some(args)) => {
             let mut context = Context::load()?;
             let build_names: Vec<&str> = args
@@ -306,9 +514,19 @@ fn run_cli() -> Result<()> {
                     Ok(value.to_string())
                 })
                 .transpose()?;
+            let jobs: usize = args
+                .value_of("jobs")
+                .expect("jobs")
+                .parse()
+                .ok()
+                .filter(|jobs| *jobs > 0)
+                .ok_or_else(|| anyhow!("jobs must be a positive number"))?;
             context.use_docker_host = args.is_present("host");
             context.docker_env_file = env_file;
             context.rustup_dir = rustup_dir;
+            if let Some(build_mode) = args.value_of("build-mode") {
+                context.config.build_mode = build_mode.parse()?;
+            }
             let build_config = BuildConfig {
                 build_env,
                 always_debug,
@@ -319,16 +537,18 @@ fn run_cli() -> Result<()> {
             if contracts.is_empty() {
                 println!("Nothing to do");
             } else {
-                for contract in contracts {
-                    println!("Building contract {}", contract.name);
-                    let recipe = get_recipe(context.clone(), contract.template_type)?;
-                    recipe.run_build(
-                        &contract,
-                        build_config,
-                        &signal,
-                        Option::Some(args_last.clone()),
-                    )?;
-                }
+                let options = BuildOptions {
+                    jobs,
+                    use_cache: !args.is_present("no-cache"),
+                };
+                build_contracts(
+                    &context,
+                    &contracts,
+                    build_config,
+                    &signal,
+                    &args_last,
+                    &options,
+                )?;
                 println!("Done");
             }
         }
@@ -364,20 +584,51 @@ fn run_cli() -> Result<()> {
             get_recipe(context, contract.template_type)?.run(&contract, cmd, &signal)?;
         }
         ("test", Some(args)) => {
-            let context = Context::load()?;
+            let mut context = Context::load()?;
+            context.docker_env_file = env_file;
+            if let Some(build_mode) = args.value_of("build-mode") {
+                context.config.build_mode = build_mode.parse()?;
+            }
             let build_env: BuildEnv = if args.is_present("release") {
                 BuildEnv::Release
             } else {
                 BuildEnv::Debug
             };
+            if !args.is_present("no-build") {
+                if args.is_present("differential") {
+                    rebuild_stale_contracts(&context, BuildEnv::Debug, &signal)?;
+                    rebuild_stale_contracts(&context, BuildEnv::Release, &signal)?;
+                } else {
+                    rebuild_stale_contracts(&context, build_env, &signal)?;
+                }
+            }
             let test_name = args.value_of("testname");
-            Tester::run(&context, build_env, test_name)?;
+            if args.is_present("differential") {
+                Tester::run_differential(&context, test_name)?;
+            } else if let Some(format) = args.value_of("report") {
+                let format: ReportFormat = format.parse()?;
+                let report_path = match args.value_of("report-file") {
+                    Some(path) => PathBuf::from(path),
+                    None => context
+                        .contracts_build_dir()
+                        .join(format!("test-report.{}", format.file_extension())),
+                };
+                Tester::run_with_output_report(
+                    &context,
+                    build_env,
+                    test_name,
+                    format,
+                    &report_path,
+                )?;
+            } else {
+                Tester::run(&context, build_env, test_name)?;
+            }
         }
         ("deploy", Some(args)) => {
             eprintln!("Warning: capsule deploy is deprecated in favor of ckb-cli deploy");
 
             let ckb_cli_bin = args.value_of("ckb-cli").expect("ckb-cli");
-            Checker::build(ckb_cli_bin).check_ckb_cli()?;
+            Checker::build(ckb_cli_bin, ContainerRuntime::default()).check_ckb_cli()?;
             let address = {
                 let address_hex = args.value_of("address").expect("address");
                 Address::from_str(address_hex).expect("parse address")
@@ -399,6 +650,138 @@ fn run_cli() -> Result<()> {
             let opt = DeployOption { migrate, tx_fee };
             DeployManage::new(migration_dir, context.load_deployment()?).deploy(wallet, opt)?;
         }
+        ("verify-build", Some(args)) => {
+            let mut context = Context::load()?;
+            context.docker_env_file = env_file;
+            let name = args.value_of("name").expect("name");
+            let contract = select_contracts(&context, &[name])
+                .pop()
+                .ok_or_else(|| anyhow!("can't find contract '{}'", name))?;
+            let expected = match args.value_of("hash") {
+                Some(hash) => ExpectedHash::Hash(parse_hash(hash)?),
+                None => ExpectedHash::Snapshot {
+                    env: args
+                        .value_of("env")
+                        .expect("deploy env")
+                        .parse()
+                        .map_err(|err: &str| anyhow!(err))?,
+                    path: args.value_of("snapshot").map(PathBuf::from),
+                    cell: args.value_of("cell").unwrap_or(name).to_string(),
+                },
+            };
+            let report = verify_build(
+                &context,
+                &contract,
+                expected,
+                args.is_present("allow-unpinned"),
+                &signal,
+            )?;
+            println!("{}", report);
+            if !report.is_match() {
+                return Err(anyhow!(
+                    "the data hash of contract '{}' doesn't match",
+                    contract.name
+                ));
+            }
+        }
+        ("image", Some(sub_matches)) => match sub_matches.subcommand() {
+            ("list", _) => {
+                let context = Context::load()?;
+                for kind in ImageKind::ALL {
+                    match context.config.images.get(kind) {
+                        Some(image) => println!("{:10} {}", kind.name(), image),
+                        None => match default_image(&context, kind)? {
+                            Some(image) => println!("{:10} {} (default)", kind.name(), image),
+                            None => println!("{:10} none", kind.name()),
+                        },
+                    }
+                }
+            }
+            ("update", Some(args)) => {
+                let context = Context::load()?;
+                let kinds: Vec<ImageKind> = match args.values_of("kind") {
+                    Some(values) => values.map(|v| v.parse()).collect::<Result<_>>()?,
+                    None => ImageKind::ALL
+                        .into_iter()
+                        .filter(|kind| context.config.images.get(*kind).is_some())
+                        .collect(),
+                };
+                if kinds.is_empty() {
+                    return Err(anyhow!(
+                        "no images are pinned in capsule.toml, please specify the kinds of images"
+                    ));
+                }
+                let image_arg = args.value_of("image");
+                if image_arg.is_some() && kinds.len() > 1 {
+                    return Err(anyhow!("--image requires a single kind of images"));
+                }
+                for kind in kinds {
+                    let image = match (image_arg, context.config.images.get(kind)) {
+                        (Some(image), _) => image.to_string(),
+                        (None, Some(image)) => image.clone(),
+                        (None, None) => default_image(&context, kind)?.ok_or_else(|| {
+                            anyhow!(
+                                "no image to pin for {}, please specify it with --image",
+                                kind.name()
+                            )
+                        })?,
+                    };
+                    let pinned = update_pin(&context, kind, &image)?;
+                    println!("Pinned {} image {}", kind.name(), pinned);
+                }
+            }
+            (command, _) => {
+                return Err(anyhow!("unknown subcommand '{}'", command));
+            }
+        },
+        ("config", Some(sub_matches)) => match sub_matches.subcommand() {
+            ("check", _) => {
+                let diagnostics = config_check::check_project(&env::current_dir()?)?;
+                let mut errors = 0;
+                for diagnostic in &diagnostics {
+                    match diagnostic.severity {
+                        Severity::Error => {
+                            errors += 1;
+                            println!("error: {}", diagnostic);
+                        }
+                        Severity::Warning => println!("warn: {}", diagnostic),
+                    }
+                }
+                if errors > 0 {
+                    return Err(anyhow!("{} has {} error(s)", CONFIG_FILE, errors));
+                }
+                if diagnostics.is_empty() {
+                    println!("{} is valid", CONFIG_FILE);
+                }
+            }
+            (command, _) => {
+                return Err(anyhow!("unknown subcommand '{}'", command));
+            }
+        },
+        ("inspect", Some(args)) => {
+            let context = Context::load()?;
+            let name = args.value_of("name").expect("name");
+            let contract = select_contracts(&context, &[name])
+                .pop()
+                .ok_or_else(|| anyhow!("can't find contract '{}'", name))?;
+            let build_env = if args.is_present("release") {
+                BuildEnv::Release
+            } else {
+                BuildEnv::Debug
+            };
+            let max_symbols: usize = args.value_of("symbols").expect("symbols").parse()?;
+            let binary_path = get_recipe(context.clone(), contract.template_type)?
+                .output_path(&contract, build_env);
+            if !binary_path.exists() {
+                return Err(anyhow!(
+                    "can't find the binary {:?}, please run `capsule build` first",
+                    binary_path
+                ));
+            }
+            let inspection = Inspection::parse(&fs::read(&binary_path)?, max_symbols)?;
+            println!("Contract: {} ({:?})", contract.name, binary_path);
+            println!("{}", inspection);
+        }
         ("debugger", Some(sub_matches)) => match sub_matches.subcommand() {
             ("gen-template", Some(args)) => {
                 let contract = args.value_of("name").expect("contract name");
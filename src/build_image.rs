@@ -0,0 +1,304 @@
+//! Pinned build images
+//!
+//! `[images]` of capsule.toml pins the build image of each template type by digest,
+//! so upgrading capsule or moving a tag doesn't change the toolchain silently.
+//! Builds check the pinned image before using it, and `capsule image update`
+//! moves the pins deliberately.
+
+use crate::config::{BuildMode, ContainerRuntime, Contract, ImagesConfig, TemplateType};
+use crate::config_manipulate::{set_build_image, Document};
+use crate::project_context::{read_config_file, write_config_file, Context, CONFIG_FILE};
+use crate::recipe::get_recipe;
+use crate::recipe::rust::DOCKER_IMAGE;
+use anyhow::{anyhow, bail, Error, Result};
+use std::fmt;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageKind {
+    Rust,
+    C,
+    Lua,
+    Debugger,
+}
+
+impl ImageKind {
+    pub const ALL: [ImageKind; 4] = [
+        ImageKind::Rust,
+        ImageKind::C,
+        ImageKind::Lua,
+        ImageKind::Debugger,
+    ];
+
+    /// Kind of the image building contracts of the template type, None if no image is used
+    pub fn for_template(template_type: TemplateType) -> Option<Self> {
+        match template_type {
+            TemplateType::Rust | TemplateType::RustSharedLib => Some(ImageKind::Rust),
+            TemplateType::C | TemplateType::CSharedLib => Some(ImageKind::C),
+            TemplateType::Lua | TemplateType::LuaEmbedded => Some(ImageKind::Lua),
+            TemplateType::CClang | TemplateType::CClangSharedLib => None,
+        }
+    }
+
+    /// Key in `[images]`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageKind::Rust => "rust",
+            ImageKind::C => "c",
+            ImageKind::Lua => "lua",
+            ImageKind::Debugger => "debugger",
+        }
+    }
+}
+
+impl FromStr for ImageKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ImageKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s.to_lowercase())
+            .ok_or_else(|| anyhow!("Unexpected image kind '{}'", s))
+    }
+}
+
+impl ImagesConfig {
+    pub fn get(&self, kind: ImageKind) -> Option<&String> {
+        match kind {
+            ImageKind::Rust => self.rust.as_ref(),
+            ImageKind::C => self.c.as_ref(),
+            ImageKind::Lua => self.lua.as_ref(),
+            ImageKind::Debugger => self.debugger.as_ref(),
+        }
+    }
+}
+
+/// Image reference in the form of `repository[:tag][@digest]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageRef {
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageRef {
+    /// Reference of the image by digest, or by tag if the image isn't pinned
+    fn exact(&self) -> String {
+        match &self.digest {
+            Some(digest) => format!("{}@{}", self.repository, digest),
+            None => self.to_string(),
+        }
+    }
+
+    /// Reference of the image by tag
+    fn tagged(&self) -> Option<String> {
+        self.tag
+            .as_ref()
+            .map(|tag| format!("{}:{}", self.repository, tag))
+    }
+}
+
+impl FromStr for ImageRef {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, digest) = match s.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (s, None),
+        };
+        // a colon before the last slash is the port of the registry
+        let (repository, tag) = match name.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag.to_string())),
+            _ => (name, None),
+        };
+        if repository.is_empty() || digest.as_deref().map_or(false, |d| !d.contains(':')) {
+            bail!("invalid image reference '{}'", s);
+        }
+        Ok(ImageRef {
+            repository: repository.to_string(),
+            tag,
+            digest,
+        })
+    }
+}
+
+impl fmt::Display for ImageRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Digests of the local image, None if the image isn't pulled
+fn repo_digests(runtime: ContainerRuntime, image: &str) -> Option<Vec<String>> {
+    let output = Command::new(runtime.program())
+        .args([
+            "image",
+            "inspect",
+            "--format",
+            "{{json .RepoDigests}}",
+            image,
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let digests: Vec<String> = serde_json::from_slice(&output.stdout).ok()?;
+    // `repository@digest`, the repository may be normalized by the runtime, e.g. `docker.io/`
+    Some(
+        digests
+            .into_iter()
+            .filter_map(|d| d.split_once('@').map(|(_, digest)| digest.to_string()))
+            .collect(),
+    )
+}
+
+fn pull(runtime: ContainerRuntime, image: &str) -> Result<()> {
+    println!("Pulling image {}", image);
+    let status = Command::new(runtime.program())
+        .args(["pull", image])
+        .status()?;
+    if !status.success() {
+        bail!("failed to pull image {}", image);
+    }
+    Ok(())
+}
+
+/// Check that the local image has the pinned digest, pull it if it's missing
+pub fn verify_image(runtime: ContainerRuntime, image: &str) -> Result<()> {
+    let image: ImageRef = image.parse()?;
+    let digest = match &image.digest {
+        Some(digest) => digest,
+        None => {
+            eprintln!(
+                "warn: build image {} is not pinned by digest, run `capsule image update` to pin it",
+                image
+            );
+            return Ok(());
+        }
+    };
+    let exact = image.exact();
+    if repo_digests(runtime, &exact).is_none() {
+        pull(runtime, &exact)?;
+    }
+    let digests = repo_digests(runtime, &exact)
+        .ok_or_else(|| anyhow!("can't find the image {} after pulling", exact))?;
+    if !digests.contains(digest) {
+        bail!(
+            "the local image {} has digests [{}], which don't match the pinned digest {}",
+            exact,
+            digests.join(", "),
+            digest
+        );
+    }
+    // the tag may have moved since the image was pinned
+    if let Some(tagged) = image.tagged() {
+        match repo_digests(runtime, &tagged) {
+            Some(tag_digests) if !tag_digests.contains(digest) => eprintln!(
+                "note: the local tag {} points to another image, run `capsule image update` to move the pin",
+                tagged
+            ),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Verify the images building contracts of the template types, each image is checked once
+pub fn verify_images(context: &Context, template_types: &[TemplateType]) -> Result<()> {
+    let native = context.config.build_mode == BuildMode::Native;
+    let kinds = template_types
+        .iter()
+        .filter_map(|t| ImageKind::for_template(*t))
+        // C and Lua contracts don't use images in native builds
+        .filter(|kind| !(native && matches!(kind, ImageKind::C | ImageKind::Lua)));
+    let mut verified = Vec::new();
+    for kind in kinds {
+        if let Some(image) = context.config.images.get(kind) {
+            if !verified.contains(&image) {
+                verify_image(context.config.container_runtime, image)?;
+                verified.push(image);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The image used if the kind isn't pinned
+pub fn default_image(context: &Context, kind: ImageKind) -> Result<Option<String>> {
+    let template_type = match kind {
+        ImageKind::Rust => TemplateType::Rust,
+        ImageKind::C => TemplateType::C,
+        ImageKind::Lua => TemplateType::Lua,
+        ImageKind::Debugger => return Ok(Some(DOCKER_IMAGE.to_string())),
+    };
+    let mut context = context.clone();
+    context.config.images = ImagesConfig::default();
+    context.config.build_mode = BuildMode::Docker;
+    // the image of contracts in the default workspace
+    let contract = Contract::new(String::new(), template_type);
+    Ok(get_recipe(context, template_type)?
+        .toolchain(&contract)?
+        .image)
+}
+
+/// Pull the tag of the image and pin it to the digest of the pulled image
+pub fn resolve_pin(runtime: ContainerRuntime, image: &str) -> Result<String> {
+    let mut image: ImageRef = image.parse()?;
+    let tagged = image.tagged().ok_or_else(|| {
+        anyhow!(
+            "image {} has no tag to update from, please specify the image with a tag",
+            image
+        )
+    })?;
+    pull(runtime, &tagged)?;
+    let digest = repo_digests(runtime, &tagged)
+        .and_then(|digests| digests.into_iter().next())
+        .ok_or_else(|| anyhow!("can't find the digest of image {}", tagged))?;
+    image.digest = Some(digest);
+    Ok(image.to_string())
+}
+
+/// Pin the image of the kind in capsule.toml, return the pinned image
+pub fn update_pin(context: &Context, kind: ImageKind, image: &str) -> Result<String> {
+    let pinned = resolve_pin(context.config.container_runtime, image)?;
+    let config_path = context.project_path.join(CONFIG_FILE);
+    let mut doc = read_config_file(&config_path)?.parse::<Document>()?;
+    set_build_image(&mut doc, kind.name(), &pinned)?;
+    write_config_file(&config_path, doc.to_string())?;
+    Ok(pinned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_image_ref() {
+        let image: ImageRef = "localhost:5000/nervos/toolchain:v1@sha256:aae8"
+            .parse()
+            .unwrap();
+        assert_eq!(image.repository, "localhost:5000/nervos/toolchain");
+        assert_eq!(image.tag.as_deref(), Some("v1"));
+        assert_eq!(image.digest.as_deref(), Some("sha256:aae8"));
+        assert_eq!(image.exact(), "localhost:5000/nervos/toolchain@sha256:aae8");
+        assert_eq!(
+            image.to_string(),
+            "localhost:5000/nervos/toolchain:v1@sha256:aae8"
+        );
+
+        let image: ImageRef = "localhost:5000/toolchain".parse().unwrap();
+        assert_eq!(image.tag, None);
+        assert_eq!(image.tagged(), None);
+        assert!("toolchain@aae8".parse::<ImageRef>().is_err());
+    }
+}
@@ -0,0 +1,408 @@
+//! Build contracts
+//!
+//! Contracts are built one after another, or concurrently with `jobs > 1`.
+//! The output of concurrent builds is captured and printed as a whole when
+//! each contract is done, with lines prefixed by the contract name.
+//! Built binaries are recorded in the manifest of the build directory.
+
+use crate::build_image::verify_images;
+use crate::config::Contract;
+use crate::fingerprint::{Fingerprint, Fingerprints};
+use crate::manifest::{data_hash, Artifact, Manifest};
+use crate::molecule;
+use crate::post_build::run_post_build;
+use crate::project_context::{BuildConfig, BuildEnv, Context};
+use crate::recipe::get_recipe;
+use crate::signal::Signal;
+use crate::util::cli::{self, Interrupted, Output};
+use crate::util::git;
+use anyhow::{anyhow, Error, Result};
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::Mutex;
+use std::thread;
+
+pub struct BuildOptions {
+    /// Number of contracts built concurrently
+    pub jobs: usize,
+    /// Skip contracts whose inputs are unchanged since the last build
+    pub use_cache: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            jobs: 1,
+            use_cache: true,
+        }
+    }
+}
+
+struct BuildState<'a> {
+    context: &'a Context,
+    build_config: BuildConfig,
+    signal: &'a Signal,
+    build_args: &'a [String],
+    use_cache: bool,
+    fingerprints: Mutex<Fingerprints>,
+    // held while building contracts of recipes which can't build concurrently
+    exclusive: Mutex<()>,
+}
+
+impl<'a> BuildState<'a> {
+    fn update_fingerprint(&self, name: &str, fingerprint: Option<Fingerprint>) -> Result<()> {
+        let mut fingerprints = self.fingerprints.lock().expect("lock");
+        match fingerprint {
+            Some(fingerprint) => fingerprints.insert(name.to_string(), fingerprint),
+            None => fingerprints.remove(name),
+        }
+        fingerprints.save(self.context, self.build_config.build_env)
+    }
+
+    /// Fingerprint of the contract, `None` if the contract is up to date and can be skipped
+    fn stale_fingerprint(&self, contract: &Contract) -> Result<Option<Fingerprint>> {
+        let recipe = get_recipe(self.context.clone(), contract.template_type)?;
+        let fingerprint = Fingerprint::compute(
+            self.context,
+            recipe.as_ref(),
+            contract,
+            &self.build_config,
+            self.build_args,
+        )?;
+        let output_path = recipe.output_path(contract, self.build_config.build_env);
+        let fresh = self.fingerprints.lock().expect("lock").is_fresh(
+            &contract.name,
+            &fingerprint,
+            &output_path,
+        );
+        if self.use_cache && fresh {
+            Ok(None)
+        } else {
+            Ok(Some(fingerprint))
+        }
+    }
+
+    fn build(&self, contract: &Contract, fingerprint: Fingerprint, output: Output) -> Result<()> {
+        let mut context = self.context.clone();
+        context.output = output;
+        let recipe = get_recipe(context.clone(), contract.template_type)?;
+        let output_path = recipe.output_path(contract, self.build_config.build_env);
+        context
+            .output
+            .println(&format!("Building contract {}", contract.name));
+        // forget the fingerprint first, the old binary may be overwritten by a failed build
+        self.update_fingerprint(&contract.name, None)?;
+        let _guard = if recipe.exclusive_build() {
+            Some(self.exclusive.lock().unwrap_or_else(|err| err.into_inner()))
+        } else {
+            None
+        };
+        recipe.run_build(
+            contract,
+            self.build_config,
+            self.signal,
+            Option::Some(self.build_args.to_vec()),
+        )?;
+        if let Some(post_build) = &fingerprint.post_build {
+            run_post_build(
+                &context,
+                post_build,
+                &output_path,
+                fingerprint.toolchain.image.as_deref(),
+                self.signal,
+            )?;
+        }
+        self.update_fingerprint(&contract.name, Some(fingerprint))
+    }
+
+    /// Build contracts from the queue until it's empty or Ctrl-C is pressed
+    fn work(&self, queue: &Mutex<VecDeque<(Contract, Fingerprint)>>) -> Vec<(String, Error)> {
+        let mut failures = Vec::new();
+        while self.signal.is_running() {
+            let (contract, fingerprint) = match queue.lock().expect("lock").pop_front() {
+                Some(job) => job,
+                None => break,
+            };
+            let output = Output::capture();
+            let result = self.build(&contract, fingerprint, output.clone());
+            cli::print_prefixed(&contract.name, &output.take());
+            if let Err(err) = result {
+                if !err.is::<Interrupted>() {
+                    cli::print_prefixed(&contract.name, format!("Error: {:#}", err).as_bytes());
+                }
+                failures.push((contract.name, err));
+            }
+        }
+        failures
+    }
+
+    fn build_concurrently(
+        &self,
+        contracts: Vec<(Contract, Fingerprint)>,
+        jobs: usize,
+    ) -> Result<()> {
+        let jobs = jobs.min(contracts.len());
+        let queue = Mutex::new(contracts.into_iter().collect::<VecDeque<_>>());
+        let failures: Vec<_> = thread::scope(|s| {
+            let workers: Vec<_> = (0..jobs).map(|_| s.spawn(|| self.work(&queue))).collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("build worker"))
+                .collect()
+        });
+        if !self.signal.is_running() {
+            exit(self.signal);
+        }
+        if failures.is_empty() {
+            return Ok(());
+        }
+        let names: Vec<_> = failures.iter().map(|(name, _)| name.as_str()).collect();
+        Err(anyhow!("failed to build contracts: {}", names.join(", ")))
+    }
+
+    /// Record the binaries of built contracts in the manifest
+    fn update_manifest(&self, contracts: &[Contract]) -> Result<()> {
+        let build_env = self.build_config.build_env;
+        let mut manifest = Manifest::load(self.context, build_env)?;
+        let git_commit = git::head_commit(&self.context.project_path).ok();
+        let dirty = git_commit.is_some() && git::is_dirty(&self.context.project_path)?;
+        let fingerprints = self.fingerprints.lock().expect("lock");
+        for contract in contracts {
+            let recipe = get_recipe(self.context.clone(), contract.template_type)?;
+            let binary_path = recipe.output_path(contract, build_env);
+            match fingerprints.get(&contract.name) {
+                Some(fingerprint) if binary_path.exists() => {
+                    // keep the commit of reused binaries
+                    let (git_commit, dirty) = match manifest.get(&contract.name) {
+                        Some(artifact)
+                            if artifact.data_hash == data_hash(&fs::read(&binary_path)?) =>
+                        {
+                            (artifact.git_commit.clone(), artifact.dirty)
+                        }
+                        _ => (git_commit.clone(), dirty),
+                    };
+                    manifest.insert(Artifact::new(
+                        self.context,
+                        contract,
+                        build_env,
+                        &binary_path,
+                        fingerprint.toolchain.clone(),
+                        git_commit,
+                        dirty,
+                    )?);
+                }
+                _ => manifest.remove(&contract.name),
+            }
+        }
+        manifest.save(self.context, build_env)
+    }
+}
+
+/// Build contracts and record their fingerprints
+pub fn build_contracts(
+    context: &Context,
+    contracts: &[Contract],
+    build_config: BuildConfig,
+    signal: &Signal,
+    build_args: &[String],
+    options: &BuildOptions,
+) -> Result<()> {
+    molecule::generate(context)?;
+    let state = BuildState {
+        context,
+        build_config,
+        signal,
+        build_args,
+        use_cache: options.use_cache,
+        fingerprints: Mutex::new(Fingerprints::load(context, build_config.build_env)?),
+        exclusive: Mutex::new(()),
+    };
+    let mut stale_contracts = Vec::new();
+    for contract in contracts {
+        match state.stale_fingerprint(contract)? {
+            Some(fingerprint) => stale_contracts.push((contract.clone(), fingerprint)),
+            None => println!("Contract {} is up to date", contract.name),
+        }
+    }
+    // only the images of contracts which are rebuilt are used
+    let template_types: Vec<_> = stale_contracts
+        .iter()
+        .map(|(c, _)| c.template_type)
+        .collect();
+    verify_images(context, &template_types)?;
+    let mut prepared = Vec::new();
+    for template_type in template_types {
+        if prepared.contains(&template_type) {
+            continue;
+        }
+        prepared.push(template_type);
+        match get_recipe(context.clone(), template_type)?.prepare_build(signal) {
+            Err(err) if err.is::<Interrupted>() => exit(signal),
+            result => result?,
+        }
+    }
+    let result = if options.jobs <= 1 || stale_contracts.len() <= 1 {
+        stale_contracts
+            .into_iter()
+            .try_for_each(|(contract, fingerprint)| {
+                match state.build(&contract, fingerprint, Output::Inherit) {
+                    Err(err) if err.is::<Interrupted>() => exit(signal),
+                    result => result,
+                }
+            })
+    } else {
+        state.build_concurrently(stale_contracts, options.jobs)
+    };
+    state.update_manifest(contracts)?;
+    result
+}
+
+/// Rebuild contracts whose inputs changed since the last build, with the same build config
+pub fn rebuild_stale_contracts(
+    context: &Context,
+    build_env: BuildEnv,
+    signal: &Signal,
+) -> Result<()> {
+    // generated code is part of the contract sources
+    molecule::generate(context)?;
+    let fingerprints = Fingerprints::load(context, build_env)?;
+    let mut stale_contracts = Vec::new();
+    for contract in &context.config.contracts {
+        let recipe = get_recipe(context.clone(), contract.template_type)?;
+        let (build_config, build_args) = match fingerprints.get(&contract.name) {
+            Some(fingerprint) => (
+                fingerprint.build_config(build_env),
+                fingerprint.build_args.clone(),
+            ),
+            None => (
+                BuildConfig {
+                    build_env,
+                    always_debug: false,
+                    remap: true,
+                },
+                Vec::new(),
+            ),
+        };
+        let fingerprint = Fingerprint::compute(
+            context,
+            recipe.as_ref(),
+            contract,
+            &build_config,
+            &build_args,
+        )?;
+        let output_path = recipe.output_path(contract, build_env);
+        if !fingerprints.is_fresh(&contract.name, &fingerprint, &output_path) {
+            stale_contracts.push((contract.clone(), build_config, build_args));
+        }
+    }
+    if stale_contracts.is_empty() {
+        return Ok(());
+    }
+    println!(
+        "Rebuild stale contracts: {}",
+        stale_contracts
+            .iter()
+            .map(|(c, _, _)| c.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let options = BuildOptions {
+        use_cache: false,
+        ..Default::default()
+    };
+    for (contract, build_config, build_args) in stale_contracts {
+        build_contracts(
+            context,
+            &[contract],
+            build_config,
+            signal,
+            &build_args,
+            &options,
+        )?;
+    }
+    Ok(())
+}
+
+fn exit(signal: &Signal) -> ! {
+    println!("Exiting...");
+    signal.exit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TemplateType;
+
+    #[test]
+    fn test_stale_fingerprint() {
+        let project_path =
+            std::env::temp_dir().join(format!("capsule-builder-{}", std::process::id()));
+        let c_dir = project_path.join("contracts").join("c");
+        for dir in [
+            "src/utils",
+            "deps/ckb-c-stdlib",
+            "build/release",
+            "molecule",
+        ] {
+            fs::create_dir_all(c_dir.join(dir)).unwrap();
+        }
+        let files = [
+            "src/lock.c",
+            "src/utils/hash.h",
+            "deps/ckb-c-stdlib/ckb_syscalls.h",
+            "build/blockchain.h",
+        ];
+        for file in files {
+            fs::write(c_dir.join(file), "").unwrap();
+        }
+        fs::write(c_dir.join("Makefile"), "").unwrap();
+        fs::create_dir_all(project_path.join("molecule")).unwrap();
+        let header = project_path.join("molecule").join("types.h");
+        fs::write(&header, "").unwrap();
+        let config = "[molecule]\nc = \"molecule\"\n";
+        let context =
+            Context::from_config(&project_path, toml_edit::de::from_str(config).unwrap()).unwrap();
+        let contract = Contract::new("lock".to_string(), TemplateType::C);
+        let signal = Signal::detached();
+        let state = BuildState {
+            context: &context,
+            build_config: BuildConfig {
+                build_env: BuildEnv::Release,
+                always_debug: false,
+                remap: false,
+            },
+            signal: &signal,
+            build_args: &[],
+            use_cache: true,
+            fingerprints: Mutex::new(Fingerprints::default()),
+            exclusive: Mutex::new(()),
+        };
+        let fingerprint = state.stale_fingerprint(&contract).unwrap().unwrap();
+        state
+            .update_fingerprint(&contract.name, Some(fingerprint))
+            .unwrap();
+        // the binary doesn't exist
+        assert!(state.stale_fingerprint(&contract).unwrap().is_some());
+        let recipe = get_recipe(context.clone(), TemplateType::C).unwrap();
+        fs::write(recipe.output_path(&contract, BuildEnv::Release), "binary").unwrap();
+        assert!(state.stale_fingerprint(&contract).unwrap().is_none());
+
+        // build outputs are not sources
+        fs::write(c_dir.join("build/blockchain.h"), "// generated").unwrap();
+        assert!(state.stale_fingerprint(&contract).unwrap().is_none());
+        for header in [
+            c_dir.join("src/utils/hash.h"),
+            c_dir.join("deps/ckb-c-stdlib/ckb_syscalls.h"),
+            header,
+        ] {
+            fs::write(&header, "// changed").unwrap();
+            let fingerprint = state.stale_fingerprint(&contract).unwrap();
+            assert!(fingerprint.is_some(), "{:?}", header);
+            state
+                .update_fingerprint(&contract.name, fingerprint)
+                .unwrap();
+            assert!(state.stale_fingerprint(&contract).unwrap().is_none());
+        }
+        fs::remove_dir_all(&project_path).unwrap();
+    }
+}
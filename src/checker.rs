@@ -1,5 +1,10 @@
+use crate::config::ContainerRuntime;
+use crate::molecule::moleculec_version;
+use crate::native_toolchain::NativeToolchain;
+use crate::util::docker::CONTAINER_RUNTIME_ENV;
 use anyhow::{anyhow, bail, Result};
 use std::fmt;
+use std::path::Path;
 use std::process::Command;
 
 struct BinDep {
@@ -50,16 +55,19 @@ impl BinDep {
 
 pub struct Checker {
     cargo: BinDep,
-    docker: BinDep,
+    container_runtime: ContainerRuntime,
+    container: BinDep,
     cross: BinDep,
     ckb_cli: BinDep,
+    native_toolchain: Option<NativeToolchain>,
+    moleculec: Option<String>,
 }
 
 impl Checker {
-    pub fn build(ckb_cli_bin: &str) -> Self {
-        let [cargo, docker, cross, ckb_cli] = [
+    pub fn build(ckb_cli_bin: &str, container_runtime: ContainerRuntime) -> Self {
+        let [cargo, container, cross, ckb_cli] = [
             ("cargo", "version", None, None),
-            ("docker", "version", None, None),
+            (container_runtime.program(), "version", None, None),
             ("cross-util", "--version", None, None),
             (
                 ckb_cli_bin,
@@ -73,9 +81,12 @@ impl Checker {
         });
         Checker {
             cargo,
-            docker,
+            container_runtime,
+            container,
             cross,
             ckb_cli,
+            native_toolchain: NativeToolchain::detect(),
+            moleculec: moleculec_version(Path::new("moleculec")),
         }
     }
 
@@ -106,10 +117,9 @@ impl Checker {
 
     pub fn print_report(&self) {
         println!("------------------------------");
-        for (bin_dep, help_message) in [&self.cargo, &self.docker, &self.cross].into_iter().zip(
+        for (bin_dep, help_message) in [&self.cargo, &self.cross].into_iter().zip(
             [
                 "Please install rust (https://www.rust-lang.org/tools/install)",
-                "Please install docker",
                 "Please install cross (https://github.com/cross-rs/cross)",
             ]
             .into_iter(),
@@ -121,6 +131,46 @@ impl Checker {
             }
         }
 
+        let container = &self.container;
+        if container.installed {
+            let mode = if self.container_runtime.is_rootless() {
+                "rootless"
+            } else {
+                "rootful"
+            };
+            println!(
+                "{:10} installed - Build images run with {} ({})",
+                container.program, container.program, mode
+            );
+        } else {
+            println!(
+                "{:10} not found - Please install {}, or set the container runtime with `container_runtime` in capsule.toml or {}",
+                container.program, container.program, CONTAINER_RUNTIME_ENV
+            );
+        }
+
+        match &self.native_toolchain {
+            Some(toolchain) => println!(
+                "{:10} installed {} - C and Lua contracts can be built natively",
+                "riscv-cc", toolchain.version
+            ),
+            None => println!(
+                "{:10} not found - C and Lua contracts can only be built in docker",
+                "riscv-cc"
+            ),
+        }
+
+        match &self.moleculec {
+            Some(version) => println!(
+                "{:10} installed {} - Molecule schemas can be compiled",
+                "moleculec", version
+            ),
+            None => println!(
+                "{:10} not found - Please install with `cargo install moleculec` to compile molecule schemas",
+                "moleculec"
+            ),
+        }
+
         let ckb_cli_dep = &self.ckb_cli;
         if ckb_cli_dep.installed {
             match &ckb_cli_dep.version {
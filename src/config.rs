@@ -1,15 +1,19 @@
 use anyhow::{anyhow, Error};
 use ckb_testtool::{ckb_jsonrpc_types::Script, ckb_types::H256};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::str::FromStr;
 
 // contracts config
-#[derive(PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
 pub enum TemplateType {
     Rust,
+    RustSharedLib,
     C,
     CSharedLib,
+    CClang,
+    CClangSharedLib,
     Lua,
     LuaEmbedded,
 }
@@ -20,8 +24,11 @@ impl FromStr for TemplateType {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let template_type = match s.to_lowercase().as_str() {
             "rust" => TemplateType::Rust,
+            "rust-sharedlib" => TemplateType::RustSharedLib,
             "c" => TemplateType::C,
             "c-sharedlib" => TemplateType::CSharedLib,
+            "c-clang" => TemplateType::CClang,
+            "c-clang-sharedlib" => TemplateType::CClangSharedLib,
             "lua" => TemplateType::Lua,
             "lua-embedded" => TemplateType::LuaEmbedded,
             _ => {
@@ -33,18 +40,267 @@ impl FromStr for TemplateType {
     }
 }
 
+impl TemplateType {
+    /// Shared libraries are loaded by other contracts with `ckb_dlopen`
+    pub fn is_sharedlib(&self) -> bool {
+        matches!(
+            self,
+            TemplateType::RustSharedLib | TemplateType::CSharedLib | TemplateType::CClangSharedLib
+        )
+    }
+}
+
+/// Variants of the Rust contract template
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ContractVariant {
+    /// Checks the script args only
+    #[default]
+    Default,
+    /// Lock script verifying a secp256k1 signature in the witness
+    Lock,
+    /// Type script dispatching on creation, update and destruction
+    Type,
+    /// Type script following the Type ID rules
+    TypeId,
+    /// Lock script calling a shared library loaded with `ckb_dlopen`
+    SharedLibLoader,
+}
+
+impl ContractVariant {
+    pub const NAMES: [&'static str; 5] = ["default", "lock", "type", "type-id", "sharedlib-loader"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContractVariant::Default => "default",
+            ContractVariant::Lock => "lock",
+            ContractVariant::Type => "type",
+            ContractVariant::TypeId => "type-id",
+            ContractVariant::SharedLibLoader => "sharedlib-loader",
+        }
+    }
+}
+
+impl FromStr for ContractVariant {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let variant = match s.to_lowercase().as_str() {
+            "default" => ContractVariant::Default,
+            "lock" => ContractVariant::Lock,
+            "type" => ContractVariant::Type,
+            "type-id" => ContractVariant::TypeId,
+            "sharedlib-loader" => ContractVariant::SharedLibLoader,
+            _ => {
+                return Err(anyhow!("Unexpected contract variant '{}'", s));
+            }
+        };
+
+        Ok(variant)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Contract {
     pub name: String,
     pub template_type: TemplateType,
+    /// Workspace dir of a Rust contract relative to the project dir, default is
+    /// `workspace_dir` of `[rust]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_build: Option<PostBuildConfig>,
+    #[serde(default, skip_serializing_if = "BuildSettings::is_empty")]
+    pub build: BuildSettings,
+}
+
+impl Contract {
+    pub fn new(name: String, template_type: TemplateType) -> Self {
+        Contract {
+            name,
+            template_type,
+            workspace: None,
+            post_build: None,
+            build: BuildSettings::default(),
+        }
+    }
+
+    /// File name of the binary in the build dir
+    pub fn output_name(&self, default: String) -> String {
+        self.build.output_name.clone().unwrap_or(default)
+    }
+}
+
+/// Build settings of a contract, in `[contracts.build]`
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildSettings {
+    /// Cargo features, Rust only
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Cargo profile of release builds, default is `release`, Rust only
+    pub profile: Option<String>,
+    /// Target triple, default is `riscv64imac-unknown-none-elf`, Rust only
+    pub target: Option<String>,
+    /// Appended to `RUSTFLAGS`, Rust only
+    #[serde(default)]
+    pub rustflags: Vec<String>,
+    /// Extra C flags, passed to the Makefile in `EXTRA_CFLAGS`, C and Lua only
+    #[serde(default)]
+    pub cflags: Vec<String>,
+    /// Environment variables of the build command, also passed to make as variables
+    /// for C and Lua contracts
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Extra arguments of the build command, before the ones passed after `--`
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// File name of the binary, default is the name of the contract
+    pub output_name: Option<String>,
+}
+
+impl BuildSettings {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Variables passed on the make command line
+    pub fn make_variables(&self) -> Vec<String> {
+        let mut variables = Vec::new();
+        if !self.cflags.is_empty() {
+            variables.push(format!("EXTRA_CFLAGS={}", self.cflags.join(" ")));
+        }
+        variables.extend(self.env.iter().map(|(k, v)| format!("{}={}", k, v)));
+        variables
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StripMode {
+    #[default]
+    None,
+    /// Remove debug sections
+    Debug,
+    /// Remove debug sections and the symbol table
+    All,
+}
+
+/// Steps run on the contract binary after it's built
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PostBuildConfig {
+    /// Also run the steps on debug builds, by default they only run on release builds
+    #[serde(default)]
+    pub debug: bool,
+    #[serde(default)]
+    pub strip: StripMode,
+    /// Keep the removed debug info in `<binary>.debug`, which is loaded by the debugger
+    #[serde(default)]
+    pub split_debug: bool,
+    #[serde(default)]
+    pub remove_sections: Vec<String>,
+    /// Extra arguments passed to objcopy
+    #[serde(default)]
+    pub objcopy_args: Vec<String>,
+    /// objcopy command, default is `riscv64-unknown-linux-gnu-objcopy`
+    pub objcopy: Option<String>,
+    /// Extra commands run after objcopy, the path of the binary is passed in `$BINARY`
+    #[serde(default)]
+    pub commands: Vec<String>,
+}
+
+/// How C and Lua contracts are built
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildMode {
+    /// Build in the docker image of the Makefile
+    #[default]
+    Docker,
+    /// Build with the RISC-V toolchain installed on the host
+    Native,
+}
+
+impl FromStr for BuildMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "docker" => Ok(BuildMode::Docker),
+            "native" => Ok(BuildMode::Native),
+            _ => Err(anyhow!("Unexpected build mode '{}'", s)),
+        }
+    }
+}
+
+/// Program running the build images
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerRuntime {
+    #[default]
+    Docker,
+    Podman,
+    Nerdctl,
+}
+
+impl FromStr for ContainerRuntime {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "docker" => Ok(ContainerRuntime::Docker),
+            "podman" => Ok(ContainerRuntime::Podman),
+            "nerdctl" => Ok(ContainerRuntime::Nerdctl),
+            _ => Err(anyhow!("Unexpected container runtime '{}'", s)),
+        }
+    }
+}
+
+/// Build images of the project, in `[images]`.
+/// An image is pinned by a digest, e.g. `repository:tag@sha256:<digest>`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImagesConfig {
+    /// Image of Rust contracts, default is the image in Cross.toml
+    pub rust: Option<String>,
+    /// Image of C contracts, default is `BUILDER_DOCKER` of the Makefile
+    pub c: Option<String>,
+    /// Image of Lua contracts, default is `LUA_BUILDER_IMAGE` of the Makefile
+    pub lua: Option<String>,
+    /// Image running ckb-debugger and gdb
+    pub debugger: Option<String>,
 }
 
 #[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct RustConfig {
-    pub workspace_dir: Option<PathBuf>, // relative path of workspace dir, default is the project dir
+    /// Default workspace of Rust contracts relative to the project dir, default is the project dir.
+    /// Contracts are crates in `contracts/` of the workspace, or in the workspace itself
+    /// if it's inside `contracts/` of the project.
+    pub workspace_dir: Option<PathBuf>,
+}
+
+/// Molecule schemas compiled by `capsule build`, paths are relative to the project dir
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MoleculeConfig {
+    /// Directory of the `.mol` files, default is `schemas`
+    pub schemas_dir: Option<PathBuf>,
+    /// Schemas relative to the schemas dir, default is all `.mol` files in it
+    #[serde(default)]
+    pub schemas: Vec<PathBuf>,
+    /// Output directory of the no_std Rust modules used by contracts
+    pub rust: Option<PathBuf>,
+    /// Output directory of the std Rust modules used by the tests crate
+    pub rust_std: Option<PathBuf>,
+    /// Output directory of the C headers
+    pub c: Option<PathBuf>,
+    /// Path of moleculec, default is `moleculec` in `PATH`
+    pub moleculec: Option<PathBuf>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(default)]
     pub version: String,
@@ -53,6 +309,15 @@ pub struct Config {
     pub deployment: Option<PathBuf>, // path of deployment config file
     #[serde(default)]
     pub rust: RustConfig,
+    #[serde(default)]
+    pub build_mode: BuildMode,
+    /// Overridden by the `CAPSULE_CONTAINER_RUNTIME` environment variable
+    #[serde(default)]
+    pub container_runtime: ContainerRuntime,
+    #[serde(default)]
+    pub images: ImagesConfig,
+    #[serde(default)]
+    pub molecule: MoleculeConfig,
 }
 
 // Deployment
@@ -83,3 +348,26 @@ pub struct DepGroup {
     pub name: String,
     pub cells: Vec<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_settings() {
+        let mut contract = Contract::new("lock".to_string(), TemplateType::C);
+        assert!(contract.build.is_empty());
+        assert!(contract.build.make_variables().is_empty());
+        assert_eq!(contract.output_name("lock".to_string()), "lock");
+
+        contract.build.cflags = vec!["-O2".to_string(), "-DDEBUG=1".to_string()];
+        contract.build.env.insert("B".to_string(), "2".to_string());
+        contract.build.env.insert("A".to_string(), "1".to_string());
+        contract.build.output_name = Some("lock-v2".to_string());
+        assert_eq!(
+            contract.build.make_variables(),
+            vec!["EXTRA_CFLAGS=-O2 -DDEBUG=1", "A=1", "B=2"]
+        );
+        assert_eq!(contract.output_name("lock".to_string()), "lock-v2");
+    }
+}
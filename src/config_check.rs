@@ -0,0 +1,529 @@
+//! Check capsule.toml
+//!
+//! The config is validated when the project is loaded: errors stop the command and
+//! warnings are printed. Each problem is reported with the line and column of
+//! capsule.toml, `capsule config check` prints all of them.
+
+use crate::config::{Config, TemplateType};
+use crate::project_context::{read_config_file, Context, CARGO_CONFIG_FILE, CONFIG_FILE};
+use crate::recipe::get_recipe;
+use anyhow::Result;
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_spanned::Spanned;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: PathBuf,
+    /// 1-based line
+    pub line: usize,
+    /// 1-based column
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.path.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+/// Locations of the values which are checked, the rest of the config is ignored
+#[derive(Deserialize)]
+struct Locations {
+    #[serde(default)]
+    contracts: Vec<ContractLocation>,
+    deployment: Option<Spanned<PathBuf>>,
+    #[serde(default)]
+    rust: RustLocation,
+}
+
+#[derive(Deserialize)]
+struct ContractLocation {
+    name: Spanned<String>,
+    workspace: Option<Spanned<PathBuf>>,
+}
+
+#[derive(Default, Deserialize)]
+struct RustLocation {
+    workspace_dir: Option<Spanned<PathBuf>>,
+}
+
+/// Line and column of the byte offset
+fn position(content: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(content.len());
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
+struct Reporter<'a> {
+    path: &'a Path,
+    content: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Reporter<'a> {
+    fn report(&mut self, severity: Severity, offset: usize, message: String) {
+        let (line, column) = position(self.content, offset);
+        self.diagnostics.push(Diagnostic {
+            severity,
+            path: self.path.to_path_buf(),
+            line,
+            column,
+            message,
+        });
+    }
+}
+
+/// Keys of a table in capsule.toml
+enum Keys {
+    /// Any key, or a value which is not a table
+    Any,
+    Table(&'static [(&'static str, Keys)]),
+}
+
+const BUILD_KEYS: Keys = Keys::Table(&[
+    ("features", Keys::Any),
+    ("profile", Keys::Any),
+    ("target", Keys::Any),
+    ("rustflags", Keys::Any),
+    ("cflags", Keys::Any),
+    ("env", Keys::Any),
+    ("args", Keys::Any),
+    ("output_name", Keys::Any),
+]);
+
+const POST_BUILD_KEYS: Keys = Keys::Table(&[
+    ("debug", Keys::Any),
+    ("strip", Keys::Any),
+    ("split_debug", Keys::Any),
+    ("remove_sections", Keys::Any),
+    ("objcopy_args", Keys::Any),
+    ("objcopy", Keys::Any),
+    ("commands", Keys::Any),
+]);
+
+/// Keys of `Config`, unknown keys are rejected by serde one at a time,
+/// so they are collected from the document first
+const CONFIG_KEYS: Keys = Keys::Table(&[
+    ("version", Keys::Any),
+    (
+        "contracts",
+        Keys::Table(&[
+            ("name", Keys::Any),
+            ("template_type", Keys::Any),
+            ("workspace", Keys::Any),
+            ("post_build", POST_BUILD_KEYS),
+            ("build", BUILD_KEYS),
+        ]),
+    ),
+    ("deployment", Keys::Any),
+    ("rust", Keys::Table(&[("workspace_dir", Keys::Any)])),
+    ("build_mode", Keys::Any),
+    ("container_runtime", Keys::Any),
+    (
+        "images",
+        Keys::Table(&[
+            ("rust", Keys::Any),
+            ("c", Keys::Any),
+            ("lua", Keys::Any),
+            ("debugger", Keys::Any),
+        ]),
+    ),
+    (
+        "molecule",
+        Keys::Table(&[
+            ("schemas_dir", Keys::Any),
+            ("schemas", Keys::Any),
+            ("rust", Keys::Any),
+            ("rust_std", Keys::Any),
+            ("c", Keys::Any),
+            ("moleculec", Keys::Any),
+        ]),
+    ),
+]);
+
+/// Tables and arrays of the document with the locations of keys
+enum Node {
+    Table(Vec<(Spanned<String>, Node)>),
+    Array(Vec<Node>),
+    Value,
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+struct NodeVisitor;
+
+impl<'de> Visitor<'de> for NodeVisitor {
+    type Value = Node;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a TOML value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(key) = map.next_key()? {
+            entries.push((key, map.next_value()?));
+        }
+        Ok(Node::Table(entries))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+        let mut elements = Vec::new();
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(Node::Array(elements))
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<Node, E> {
+        Ok(Node::Value)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<Node, E> {
+        Ok(Node::Value)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<Node, E> {
+        Ok(Node::Value)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<Node, E> {
+        Ok(Node::Value)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<Node, E> {
+        Ok(Node::Value)
+    }
+}
+
+/// Report the keys of the node which are not in the known keys
+fn check_keys(reporter: &mut Reporter, node: &Node, keys: &Keys, table: &str) {
+    let known = match keys {
+        Keys::Any => return,
+        Keys::Table(known) => known,
+    };
+    match node {
+        Node::Table(entries) => {
+            for (key, value) in entries {
+                let name = key.get_ref();
+                let path = if table.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", table, name)
+                };
+                match known.iter().find(|(known, _)| known == name) {
+                    Some((_, keys)) => check_keys(reporter, value, keys, &path),
+                    None => reporter.report(
+                        Severity::Error,
+                        key.span().start,
+                        format!("unknown key `{}`", path),
+                    ),
+                }
+            }
+        }
+        // arrays of tables, e.g. `[[contracts]]`
+        Node::Array(elements) => {
+            for element in elements {
+                check_keys(reporter, element, keys, table);
+            }
+        }
+        Node::Value => {}
+    }
+}
+
+fn parse_error(path: &Path, content: &str, err: toml_edit::de::Error) -> Diagnostic {
+    let (line, column) = err
+        .span()
+        .map(|span| position(content, span.start))
+        .unwrap_or((1, 1));
+    Diagnostic {
+        severity: Severity::Error,
+        path: path.to_path_buf(),
+        line,
+        column,
+        message: err
+            .message()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+/// Parse the config, parse errors and all the unknown keys are reported as diagnostics
+pub fn parse_config(path: &Path, content: &str) -> Result<Config, Vec<Diagnostic>> {
+    let document: Node =
+        toml_edit::de::from_str(content).map_err(|err| vec![parse_error(path, content, err)])?;
+    let mut reporter = Reporter {
+        path,
+        content,
+        diagnostics: Vec::new(),
+    };
+    check_keys(&mut reporter, &document, &CONFIG_KEYS, "");
+    if !reporter.diagnostics.is_empty() {
+        return Err(reporter.diagnostics);
+    }
+    toml_edit::de::from_str(content).map_err(|err| vec![parse_error(path, content, err)])
+}
+
+fn check_duplicates(reporter: &mut Reporter, locations: &Locations) {
+    let mut defined: HashMap<&str, usize> = HashMap::new();
+    for contract in &locations.contracts {
+        let name = contract.name.get_ref();
+        let offset = contract.name.span().start;
+        match defined.get(name.as_str()) {
+            Some(first) => {
+                let (line, _) = position(reporter.content, *first);
+                reporter.report(
+                    Severity::Error,
+                    offset,
+                    format!(
+                        "duplicate contract name '{}', it's already defined at line {}",
+                        name, line
+                    ),
+                );
+            }
+            None => {
+                defined.insert(name, offset);
+            }
+        }
+    }
+}
+
+/// Check the parsed config of the project
+pub fn check_config(context: &Context, path: &Path, content: &str) -> Vec<Diagnostic> {
+    let mut reporter = Reporter {
+        path,
+        content,
+        diagnostics: Vec::new(),
+    };
+    let locations: Locations = match toml_edit::de::from_str(content) {
+        Ok(locations) => locations,
+        // the config is parsed, so the same content can't fail here
+        Err(_) => return reporter.diagnostics,
+    };
+    check_duplicates(&mut reporter, &locations);
+    if let Some(workspace_dir) = locations.rust.workspace_dir.as_ref() {
+        let offset = workspace_dir.span().start;
+        match context.workspace_dir() {
+            Ok(path) if !path.is_dir() => reporter.report(
+                Severity::Error,
+                offset,
+                format!(
+                    "`workspace_dir` {:?} is not a directory",
+                    workspace_dir.get_ref()
+                ),
+            ),
+            Ok(_) => {}
+            Err(err) => reporter.report(Severity::Error, offset, err.to_string()),
+        }
+    }
+    for (contract, location) in context.config.contracts.iter().zip(&locations.contracts) {
+        let recipe = match get_recipe(context.clone(), contract.template_type) {
+            Ok(recipe) => recipe,
+            Err(_) => continue,
+        };
+        if let Some(workspace) = location.workspace.as_ref() {
+            let offset = workspace.span().start;
+            if !matches!(
+                contract.template_type,
+                TemplateType::Rust | TemplateType::RustSharedLib
+            ) {
+                reporter.report(
+                    Severity::Warning,
+                    offset,
+                    format!(
+                        "`workspace` of contract '{}' is ignored, it's only used by Rust contracts",
+                        contract.name
+                    ),
+                );
+            } else {
+                match context.contract_workspace_dir(contract) {
+                    Ok(dir) if !dir.join(CARGO_CONFIG_FILE).exists() => reporter.report(
+                        Severity::Error,
+                        offset,
+                        format!(
+                            "can't find the Cargo.toml of the workspace {:?}",
+                            workspace.get_ref()
+                        ),
+                    ),
+                    Ok(_) => {}
+                    Err(err) => reporter.report(Severity::Error, offset, err.to_string()),
+                }
+            }
+        }
+        if !recipe.exists(&contract.name) {
+            reporter.report(
+                Severity::Warning,
+                location.name.span().start,
+                format!("can't find the sources of contract '{}'", contract.name),
+            );
+        }
+    }
+    if let Some(deployment) = locations.deployment.as_ref() {
+        if !context.project_path.join(deployment.get_ref()).exists() {
+            reporter.report(
+                Severity::Warning,
+                deployment.span().start,
+                format!("can't find the deployment file {:?}", deployment.get_ref()),
+            );
+        }
+    }
+    let mut diagnostics = reporter.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    diagnostics
+}
+
+/// Check capsule.toml of the project, the diagnostics are empty if the config is valid
+pub fn check_project(project_path: &Path) -> Result<Vec<Diagnostic>> {
+    let path = project_path.join(CONFIG_FILE);
+    let content = read_config_file(&path)?;
+    let config = match parse_config(&path, &content) {
+        Ok(config) => config,
+        Err(diagnostics) => return Ok(diagnostics),
+    };
+    let context = Context::from_config(project_path, config)?;
+    Ok(check_config(&context, &path, &content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let content = "a = 1\n\nb = \"ü\"c";
+        assert_eq!(position(content, 0), (1, 1));
+        assert_eq!(position(content, 6), (2, 1));
+        assert_eq!(position(content, 7), (3, 1));
+        assert_eq!(position(content, content.len() - 1), (3, 8));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let path = Path::new(CONFIG_FILE);
+        let errors = parse_config(path, "version = \"0.10.5\"\ndeploymnet = \"d.toml\"\n")
+            .err()
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (2, 1));
+        assert!(
+            errors[0].message.contains("deploymnet"),
+            "{}",
+            errors[0].message
+        );
+        let errors = parse_config(path, "version = \"0.10.5\"\n[[contracts]\n")
+            .err()
+            .unwrap();
+        assert_eq!(errors[0].line, 2);
+        let errors = parse_config(path, "version = \"0.10.5\"\n[[contracts]]\nname = \"a\"\ntemplate_type = \"Rust\"\nbuild = { rustflag = [], env = { A = \"1\" } }\n\n[images]\nrsut = \"image\"\n")
+            .err()
+            .unwrap();
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "capsule.toml:5:11: unknown key `contracts.build.rustflag`",
+                "capsule.toml:8:1: unknown key `images.rsut`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_known_keys() {
+        let content = r#"
+version = "0.10.5"
+deployment = "deployment.toml"
+build_mode = "docker"
+container_runtime = "podman"
+
+[[contracts]]
+name = "lock"
+template_type = "Rust"
+workspace = "onchain"
+
+[contracts.build]
+features = ["log"]
+profile = "release"
+target = "riscv64imac-unknown-none-elf"
+rustflags = ["-C", "target-feature=+zba"]
+cflags = ["-O2"]
+env = { LOG = "1" }
+args = ["--locked"]
+output_name = "lock-v1"
+
+[contracts.post_build]
+debug = true
+strip = "all"
+split_debug = true
+remove_sections = [".comment"]
+objcopy_args = ["-v"]
+objcopy = "objcopy"
+commands = ["ls"]
+
+[rust]
+workspace_dir = "onchain"
+
+[images]
+rust = "rust"
+c = "c"
+lua = "lua"
+debugger = "debugger"
+
+[molecule]
+schemas_dir = "schemas"
+schemas = ["types.mol"]
+rust = "types/src"
+rust_std = "tests/src/types"
+c = "c/types"
+moleculec = "moleculec"
+"#;
+        assert!(parse_config(Path::new(CONFIG_FILE), content).is_ok());
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let content = "[[contracts]]\nname = \"a\"\n\n[[contracts]]\nname = \"b\"\n\n[[contracts]]\nname = \"a\"\n";
+        let locations: Locations = toml_edit::de::from_str(content).unwrap();
+        let mut reporter = Reporter {
+            path: Path::new(CONFIG_FILE),
+            content,
+            diagnostics: Vec::new(),
+        };
+        check_duplicates(&mut reporter, &locations);
+        assert_eq!(reporter.diagnostics.len(), 1);
+        let diagnostic = &reporter.diagnostics[0];
+        assert_eq!((diagnostic.line, diagnostic.column), (8, 8));
+        assert_eq!(
+            diagnostic.to_string(),
+            "capsule.toml:8:8: duplicate contract name 'a', it's already defined at line 2"
+        );
+    }
+}
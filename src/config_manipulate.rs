@@ -4,27 +4,78 @@ use crate::config::{Contract, TemplateType};
 use anyhow::{anyhow, Result};
 use serde::Serialize;
 pub use toml_edit::Document;
-use toml_edit::{array, ser::ValueSerializer, value};
+use toml_edit::{array, ser::ValueSerializer, table, value, Array, Value};
 
 pub fn append_contract(
     doc: &mut Document,
     name: String,
     template_type: TemplateType,
 ) -> Result<()> {
+    append_contract_config(doc, &Contract::new(name, template_type))
+}
+
+/// Append the contract with its settings to `[[contracts]]`
+pub fn append_contract_config(doc: &mut Document, contract: &Contract) -> Result<()> {
     let contracts = doc["contracts"]
         .or_insert(array())
         .as_array_of_tables_mut()
         .ok_or(anyhow!("no 'contracts' section"))?;
     // Why doesn't toml_edit provide a to_value function?
-    let t = Contract {
-        name,
-        template_type,
-    }
-    .serialize(ValueSerializer::new())?;
+    let t = contract.serialize(ValueSerializer::new())?;
     contracts.push(value(t).into_table().unwrap());
     Ok(())
 }
 
+/// Remove the contract from `[[contracts]]`, return false if it's not found
+pub fn remove_contract(doc: &mut Document, name: &str) -> Result<bool> {
+    let contracts = match doc.get_mut("contracts") {
+        Some(contracts) => contracts
+            .as_array_of_tables_mut()
+            .ok_or(anyhow!("'contracts' is not an array of tables"))?,
+        None => return Ok(false),
+    };
+    let len = contracts.len();
+    contracts.retain(|t| t.get("name").and_then(|n| n.as_str()) != Some(name));
+    Ok(contracts.len() != len)
+}
+
+/// Rename the contract in `[[contracts]]`, return false if it's not found
+pub fn rename_contract(doc: &mut Document, name: &str, new_name: &str) -> Result<bool> {
+    let contracts = match doc.get_mut("contracts") {
+        Some(contracts) => contracts
+            .as_array_of_tables_mut()
+            .ok_or(anyhow!("'contracts' is not an array of tables"))?,
+        None => return Ok(false),
+    };
+    let mut found = false;
+    for t in contracts.iter_mut() {
+        if let Some(v) = t.get_mut("name").and_then(|n| n.as_value_mut()) {
+            if v.as_str() == Some(name) {
+                replace_string(v, new_name);
+                found = true;
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Replace the string value, keeping the comments and whitespace around it
+fn replace_string(v: &mut Value, s: &str) {
+    let decor = v.decor().clone();
+    *v = s.into();
+    *v.decor_mut() = decor;
+}
+
+/// Set the build image of `[images]`
+pub fn set_build_image(doc: &mut Document, kind: &str, image: &str) -> Result<()> {
+    let images = doc["images"]
+        .or_insert(table())
+        .as_table_mut()
+        .ok_or(anyhow!("'images' is not a table"))?;
+    images[kind] = value(image);
+    Ok(())
+}
+
 pub fn append_cargo_workspace_member(doc: &mut Document, name: String) -> Result<()> {
     let workspace = doc["workspace"]
         .as_table_mut()
@@ -36,9 +87,43 @@ pub fn append_cargo_workspace_member(doc: &mut Document, name: String) -> Result
     Ok(())
 }
 
+fn cargo_workspace_members(doc: &mut Document) -> Result<&mut Array> {
+    doc.get_mut("workspace")
+        .and_then(|workspace| workspace.as_table_like_mut())
+        .ok_or(anyhow!("no 'workspace' section"))?
+        .get_mut("members")
+        .and_then(|members| members.as_array_mut())
+        .ok_or(anyhow!("no 'members' section"))
+}
+
+/// Remove the member from the workspace, return false if it's not found
+pub fn remove_cargo_workspace_member(doc: &mut Document, name: &str) -> Result<bool> {
+    let members = cargo_workspace_members(doc)?;
+    let len = members.len();
+    members.retain(|member| member.as_str() != Some(name));
+    Ok(members.len() != len)
+}
+
+/// Rename the member of the workspace, return false if it's not found
+pub fn rename_cargo_workspace_member(
+    doc: &mut Document,
+    name: &str,
+    new_name: &str,
+) -> Result<bool> {
+    let members = cargo_workspace_members(doc)?;
+    let mut found = false;
+    for member in members.iter_mut() {
+        if member.as_str() == Some(name) {
+            replace_string(member, new_name);
+            found = true;
+        }
+    }
+    Ok(found)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::append_contract;
+    use super::*;
 
     #[test]
     fn test_append_contract() {
@@ -69,4 +154,62 @@ template_type = "Rust"
 "#
         )
     }
+
+    #[test]
+    fn test_remove_and_rename_contract() {
+        let mut doc: Document = r#"
+version = "0.9.1"
+
+# the lock
+[[contracts]]
+name = "a"   # renamed
+template_type = "Rust"
+
+[[contracts]]
+name = "b"
+template_type = "C"
+"#
+        .parse()
+        .unwrap();
+        assert!(rename_contract(&mut doc, "a", "c").unwrap());
+        assert!(!rename_contract(&mut doc, "x", "y").unwrap());
+        assert!(remove_contract(&mut doc, "b").unwrap());
+        assert!(!remove_contract(&mut doc, "b").unwrap());
+        assert_eq!(
+            doc.to_string(),
+            r#"
+version = "0.9.1"
+
+# the lock
+[[contracts]]
+name = "c"   # renamed
+template_type = "Rust"
+"#
+        );
+    }
+
+    #[test]
+    fn test_workspace_members() {
+        let mut doc: Document = r#"[workspace]
+members = [
+    "tests",
+    "contracts/a",
+    "contracts/b", # the library
+]
+"#
+        .parse()
+        .unwrap();
+        assert!(rename_cargo_workspace_member(&mut doc, "contracts/b", "contracts/c").unwrap());
+        assert!(remove_cargo_workspace_member(&mut doc, "contracts/a").unwrap());
+        assert!(!remove_cargo_workspace_member(&mut doc, "contracts/a").unwrap());
+        assert_eq!(
+            doc.to_string(),
+            r#"[workspace]
+members = [
+    "tests",
+    "contracts/c", # the library
+]
+"#
+        );
+    }
 }
@@ -1,4 +1,6 @@
+use crate::build_image::verify_image;
 use crate::generator::TEMPLATES;
+use crate::post_build::debug_file_path;
 use crate::project_context::{BuildEnv, Context};
 use crate::recipe::rust::DOCKER_IMAGE;
 use crate::signal::Signal;
@@ -64,10 +66,17 @@ pub fn start_debugger(context: &Context, signal: &Signal, args: DebuggerArgs) ->
         "ckb-debugger --script-group-type {} --cell-index {} --cell-type {} --tx-file {} --max-cycles {} --mode {} --gdb-listen 127.0.0.1:{}",
         script_group_type, cell_index, cell_type, container_template_path, max_cycles, mode, listen_port
     );
+    let image = match &context.config.images.debugger {
+        Some(image) => {
+            verify_image(context.config.container_runtime, image)?;
+            image.clone()
+        }
+        None => DOCKER_IMAGE.to_string(),
+    };
     println!("GDB server is started!");
     DockerCommand::with_context(
         context,
-        DOCKER_IMAGE.to_string(),
+        image.clone(),
         project_path.clone(),
         docker_env_file.clone(),
     )
@@ -81,20 +90,23 @@ pub fn start_debugger(context: &Context, signal: &Signal, args: DebuggerArgs) ->
             BuildEnv::Debug => format!("build/debug/{}", contract_name),
             BuildEnv::Release => format!("build/release/{}", contract_name),
         };
+        // load the debug info split by post-build steps
+        let debug_file = debug_file_path(Path::new(&contract_path));
+        let symbol_file = if context.project_path.join(&debug_file).exists() {
+            format!(" -ex 'symbol-file {}'", debug_file.to_string_lossy())
+        } else {
+            String::new()
+        };
         // start gdb client
         let cmd = format!(
-            "RUST_GDB=riscv64-unknown-elf-gdb rust-gdb -ex 'target remote :{port}' -ex 'file {contract_path}' -ex 'cd contracts/{contract}'",
+            "RUST_GDB=riscv64-unknown-elf-gdb rust-gdb -ex 'target remote :{port}' -ex 'file {contract_path}'{symbol_file} -ex 'cd contracts/{contract}'",
             port=listen_port,
             contract=contract_name,
-            contract_path=contract_path
+            contract_path=contract_path,
+            symbol_file=symbol_file
         );
-        let docker_cmd = DockerCommand::with_context(
-            context,
-            DOCKER_IMAGE.to_string(),
-            project_path,
-            docker_env_file,
-        )
-        .host_network(true);
+        let docker_cmd = DockerCommand::with_context(context, image, project_path, docker_env_file)
+            .host_network(true);
 
         // Prepare a specific docker environment for GDB client then enable this
         //
@@ -108,7 +120,7 @@ pub fn start_debugger(context: &Context, signal: &Signal, args: DebuggerArgs) ->
 
         docker_cmd.run(cmd, signal)?;
 
-        return DockerCommand::stop(DEBUG_SERVER_NAME);
+        return DockerCommand::stop(context.config.container_runtime, DEBUG_SERVER_NAME);
     }
     Ok(())
 }
@@ -119,7 +131,7 @@ struct TemplateContext {
 }
 
 pub fn build_template(contract_name: String) -> Result<String> {
-    let context = TeraContext::from_serialize(&TemplateContext {
+    let context = TeraContext::from_serialize(TemplateContext {
         name: contract_name,
     })?;
     let content = TEMPLATES.render("debugger/template.json", &context)?;
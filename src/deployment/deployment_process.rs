@@ -76,11 +76,7 @@ impl DeploymentProcess {
         }
         // collect cells if inputs_cells is empty, type_id requires at least one input
         if cell.enable_type_id && inputs_cells.is_empty() {
-            inputs_cells.extend(
-                self.wallet
-                    .collect_live_cells(Capacity::shannons(1))
-                    .into_iter(),
-            );
+            inputs_cells.extend(self.wallet.collect_live_cells(Capacity::shannons(1)));
             self.wallet
                 .lock_out_points(inputs_cells.iter().map(|c| c.out_point()));
         }
@@ -7,10 +7,33 @@ use chrono::prelude::*;
 use ckb_testtool::ckb_types::core::{Capacity, TransactionView};
 use std::fs;
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const CURRENT_SNAPSHOT: &str = "current.json";
 
+/// Read a deployment snapshot
+pub fn read_snapshot<P: AsRef<Path>>(path: P) -> Result<DeploymentRecipe> {
+    let mut buf = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut buf)?;
+    let recipe = serde_json::from_slice(&buf)?;
+    Ok(recipe)
+}
+
+/// Path of the last completed snapshot in the migration dir
+pub fn last_snapshot_path(migration_dir: &Path) -> Result<Option<PathBuf>> {
+    if !migration_dir.exists() {
+        return Ok(None);
+    }
+    let mut file_names: Vec<_> = fs::read_dir(migration_dir)?
+        .map(|d| d.map(|d| d.file_name()))
+        .collect::<Result<_, _>>()?;
+    file_names.retain(|name| name != CURRENT_SNAPSHOT);
+    Ok(file_names
+        .into_iter()
+        .max()
+        .map(|name| migration_dir.join(name)))
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct DeployOption {
     pub migrate: bool,
@@ -70,10 +93,7 @@ impl Manage {
     fn load_snapshot(&self, snapshot_name: String) -> Result<DeploymentRecipe> {
         let mut path = self.migration_dir.clone();
         path.push(snapshot_name);
-        let mut buf = Vec::new();
-        fs::File::open(path)?.read_to_end(&mut buf)?;
-        let recipe = serde_json::from_slice(&buf)?;
-        Ok(recipe)
+        read_snapshot(path)
     }
 
     fn collect_migration_live_cells(&self, wallet: &Wallet) -> Result<Vec<(String, LiveCell)>> {
@@ -0,0 +1,197 @@
+//! Fingerprints of contracts sources
+//!
+//! Each successful build records the hash of the contract sources, the build config
+//! and the toolchain in `build/<env>/.fingerprints.json`, so we can tell which binaries
+//! are stale and which can be reused.
+
+use crate::config::{BuildSettings, Contract, PostBuildConfig};
+use crate::project_context::{BuildConfig, BuildEnv, Context};
+use crate::recipe::{Recipe, Toolchain};
+use anyhow::{Context as _, Result};
+use ckb_testtool::ckb_hash::new_blake2b;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FINGERPRINTS_FILE: &str = ".fingerprints.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// blake2b hash of the contract sources
+    pub sources: String,
+    pub always_debug: bool,
+    pub remap: bool,
+    /// extra arguments passed to the build command
+    #[serde(default)]
+    pub build_args: Vec<String>,
+    #[serde(default)]
+    pub toolchain: Toolchain,
+    #[serde(default)]
+    pub post_build: Option<PostBuildConfig>,
+    #[serde(default)]
+    pub build_settings: BuildSettings,
+    /// effective `RUSTFLAGS`, including the ones of the environment
+    #[serde(default)]
+    pub rustflags: Vec<String>,
+}
+
+impl Fingerprint {
+    /// Compute the fingerprint of the inputs of a contract build
+    pub fn compute(
+        context: &Context,
+        recipe: &dyn Recipe,
+        contract: &Contract,
+        config: &BuildConfig,
+        build_args: &[String],
+    ) -> Result<Self> {
+        let sources = hash_sources(&context.project_path, &recipe.source_paths(contract)?)?;
+        Ok(Fingerprint {
+            sources,
+            always_debug: config.always_debug,
+            remap: config.remap,
+            build_args: build_args.to_vec(),
+            toolchain: recipe.toolchain(contract)?,
+            post_build: contract
+                .post_build
+                .clone()
+                .filter(|post_build| post_build.applies_to(config.build_env)),
+            build_settings: contract.build.clone(),
+            rustflags: recipe.rustflags(contract)?,
+        })
+    }
+
+    /// Build config used by the last build
+    pub fn build_config(&self, build_env: BuildEnv) -> BuildConfig {
+        BuildConfig {
+            build_env,
+            always_debug: self.always_debug,
+            remap: self.remap,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Fingerprints {
+    #[serde(default)]
+    contracts: BTreeMap<String, Fingerprint>,
+}
+
+impl Fingerprints {
+    fn path(context: &Context, build_env: BuildEnv) -> PathBuf {
+        context
+            .contracts_build_path(build_env)
+            .join(FINGERPRINTS_FILE)
+    }
+
+    pub fn load(context: &Context, build_env: BuildEnv) -> Result<Self> {
+        let path = Self::path(context, build_env);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read(&path)?;
+        serde_json::from_slice(&content).with_context(|| format!("failed to parse {:?}", path))
+    }
+
+    pub fn save(&self, context: &Context, build_env: BuildEnv) -> Result<()> {
+        let path = Self::path(context, build_env);
+        fs::create_dir_all(path.parent().expect("build dir"))?;
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Fingerprint> {
+        self.contracts.get(name)
+    }
+
+    pub fn insert(&mut self, name: String, fingerprint: Fingerprint) {
+        self.contracts.insert(name, fingerprint);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.contracts.remove(name);
+    }
+
+    /// Return true if the contract was built from the same inputs and the binary still exists
+    pub fn is_fresh(&self, name: &str, fingerprint: &Fingerprint, output_path: &Path) -> bool {
+        output_path.exists() && self.get(name) == Some(fingerprint)
+    }
+}
+
+/// Hash the files and directories, `target` and `.git` directories are skipped.
+/// Missing paths are hashed as empty, so adding them later changes the hash.
+pub fn hash_sources(base: &Path, paths: &[PathBuf]) -> Result<String> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_files(path, &mut files)?;
+    }
+    files.sort();
+    files.dedup();
+    let mut hasher = new_blake2b();
+    for file in files {
+        let rel_path = file.strip_prefix(base).unwrap_or(&file);
+        let content = fs::read(&file).with_context(|| format!("failed to read {:?}", file))?;
+        hasher.update(rel_path.to_string_lossy().as_bytes());
+        hasher.update(&(content.len() as u64).to_le_bytes());
+        hasher.update(&content);
+    }
+    let mut hash = [0u8; 32];
+    hasher.finalize(&mut hash);
+    Ok(faster_hex::hex_string(&hash))
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if path.is_dir() {
+        if path
+            .file_name()
+            .map(|name| name == "target" || name == ".git")
+            .unwrap_or(false)
+        {
+            return Ok(());
+        }
+        for entry in fs::read_dir(path)? {
+            collect_files(&entry?.path(), files)?;
+        }
+    } else if path.is_file() {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TemplateType;
+    use crate::recipe::get_recipe;
+
+    #[test]
+    fn test_rustflags_changes() {
+        let project_path =
+            std::env::temp_dir().join(format!("capsule-fingerprint-{}", std::process::id()));
+        fs::create_dir_all(&project_path).unwrap();
+        let context =
+            Context::from_config(&project_path, toml_edit::de::from_str("").unwrap()).unwrap();
+        let recipe = get_recipe(context.clone(), TemplateType::Rust).unwrap();
+        let contract = Contract::new("lock".to_string(), TemplateType::Rust);
+        let config = BuildConfig {
+            build_env: BuildEnv::Release,
+            always_debug: false,
+            remap: false,
+        };
+        let output_path = project_path.join("lock");
+        fs::write(&output_path, "binary").unwrap();
+        let compute = || Fingerprint::compute(&context, &*recipe, &contract, &config, &[]).unwrap();
+
+        std::env::remove_var("RUSTFLAGS");
+        let mut fingerprints = Fingerprints::default();
+        fingerprints.insert(contract.name.clone(), compute());
+        assert!(fingerprints.is_fresh(&contract.name, &compute(), &output_path));
+
+        std::env::set_var("RUSTFLAGS", "-C target-feature=+zba");
+        let fingerprint = compute();
+        std::env::remove_var("RUSTFLAGS");
+        assert_eq!(fingerprint.rustflags, vec!["-C", "target-feature=+zba"]);
+        assert!(!fingerprints.is_fresh(&contract.name, &fingerprint, &output_path));
+        fs::remove_dir_all(project_path).unwrap();
+    }
+}
@@ -0,0 +1,383 @@
+//! External templates
+//!
+//! Contracts can be created from a template in a local directory or a git repository
+//! instead of the built-in ones. The template is described by `capsule-template.toml`
+//! in its root directory:
+//!
+//! ```toml
+//! # capsule version the template is written for
+//! capsule_version = "0.10.0"
+//! # template type of the created contract
+//! template_type = "Rust"
+//!
+//! [[files]]
+//! src = "contract/Cargo.toml"
+//! dest = "contracts/{{ name }}/Cargo.toml"
+//!
+//! [[files]]
+//! src = "contract/src/main.rs"
+//! dest = "contracts/{{ name }}/src/main.rs"
+//!
+//! [[files]]
+//! src = "tests/mod.rs"
+//! dest = "tests/src/lib.rs"
+//! # append to the existing file instead of creating it
+//! append = true
+//! ```
+//!
+//! Files and destination paths are rendered with Tera, with the same context as the
+//! built-in templates: `name`, `version` and `path` of the project.
+
+use crate::config::{Contract, TemplateType};
+use crate::project_context::Context;
+use crate::recipe::get_recipe;
+use crate::util::git;
+use crate::version::Version;
+use anyhow::{anyhow, bail, Context as ErrorContext, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use tera::Tera;
+
+pub const MANIFEST_FILE: &str = "capsule-template.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateManifest {
+    /// Checked with the current capsule version as the `version` of capsule.toml
+    pub capsule_version: String,
+    pub template_type: TemplateType,
+    #[serde(default)]
+    pub files: Vec<TemplateFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateFile {
+    /// Path in the template directory
+    pub src: PathBuf,
+    /// Path in the project directory, rendered with the template context
+    pub dest: String,
+    /// Append to the file, e.g. the build rules of a Makefile
+    #[serde(default)]
+    pub append: bool,
+    /// Copy the file as is, e.g. a binary file
+    #[serde(default)]
+    pub raw: bool,
+}
+
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    name: &'a str,
+    version: String,
+    path: &'a Path,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateSource {
+    Local(PathBuf),
+    Git {
+        url: String,
+        reference: Option<String>,
+    },
+}
+
+impl TemplateSource {
+    /// A git URL or a local directory, the reference is a branch, tag or commit of the repository
+    pub fn new(source: &str, reference: Option<&str>) -> Result<Self> {
+        let is_git_url = ["https://", "http://", "ssh://", "git://", "git@"]
+            .iter()
+            .any(|prefix| source.starts_with(prefix))
+            || source.ends_with(".git");
+        if is_git_url {
+            return Ok(TemplateSource::Git {
+                url: source.to_string(),
+                reference: reference.map(|r| r.to_string()),
+            });
+        }
+        if reference.is_some() {
+            bail!("a reference can only be used with a git template source");
+        }
+        Ok(TemplateSource::Local(PathBuf::from(source)))
+    }
+}
+
+pub struct ExternalTemplate {
+    dir: PathBuf,
+    manifest: TemplateManifest,
+    /// Temporary checkout of a git template, removed on drop
+    checkout: Option<PathBuf>,
+}
+
+impl ExternalTemplate {
+    /// Read the template, git templates are cloned into a temporary directory
+    pub fn fetch(source: &TemplateSource) -> Result<Self> {
+        let (dir, checkout) = match source {
+            TemplateSource::Local(dir) => (dir.clone(), None),
+            TemplateSource::Git { url, reference } => {
+                let dir =
+                    std::env::temp_dir().join(format!("capsule-template-{}", std::process::id()));
+                if dir.exists() {
+                    fs::remove_dir_all(&dir)?;
+                }
+                println!("Fetching template {}", url);
+                git::clone(url, &dir)?;
+                if let Some(reference) = reference {
+                    git::checkout(&dir, reference)?;
+                }
+                (dir.clone(), Some(dir))
+            }
+        };
+        match Self::read_manifest(&dir) {
+            Ok(manifest) => Ok(ExternalTemplate {
+                dir,
+                manifest,
+                checkout,
+            }),
+            Err(err) => {
+                if let Some(checkout) = checkout {
+                    let _ = fs::remove_dir_all(checkout);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn read_manifest(dir: &Path) -> Result<TemplateManifest> {
+        let path = dir.join(MANIFEST_FILE);
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("can't read {:?}", path))?;
+        let manifest: TemplateManifest =
+            toml_edit::de::from_str(&content).with_context(|| format!("invalid {:?}", path))?;
+        let required: Version = manifest.capsule_version.parse()?;
+        let current = Version::current();
+        if !current.is_compatible(&required) {
+            bail!(
+                "the template requires capsule {}, which is not compatible with the current version {}",
+                manifest.capsule_version,
+                current.to_string()
+            );
+        }
+        Ok(manifest)
+    }
+
+    pub fn template_type(&self) -> TemplateType {
+        self.manifest.template_type
+    }
+
+    /// Render the files of the template in memory, nothing is written. The paths are
+    /// checked and the created files must not exist.
+    pub fn render(&self, project_path: &Path, name: &str) -> Result<Vec<RenderedFile>> {
+        let context = tera::Context::from_serialize(TemplateContext {
+            name,
+            version: Version::current().to_string(),
+            path: project_path,
+        })?;
+        let mut rendered: Vec<RenderedFile> = Vec::new();
+        for file in &self.manifest.files {
+            let dest = Tera::one_off(&file.dest, &context, false)?;
+            let path = project_path.join(checked_relative_path(&dest)?);
+            let src_path = self
+                .dir
+                .join(checked_relative_path(&file.src.to_string_lossy())?);
+            let content = if file.raw {
+                fs::read(&src_path).with_context(|| format!("can't read {:?}", src_path))?
+            } else {
+                let content = fs::read_to_string(&src_path)
+                    .with_context(|| format!("can't read {:?}", src_path))?;
+                Tera::one_off(&content, &context, false)
+                    .with_context(|| format!("failed to render {:?}", src_path))?
+                    .into_bytes()
+            };
+            let created_before = rendered.iter().any(|f| !f.append && f.path == path);
+            if !file.append && (path.exists() || created_before) {
+                bail!("{:?} already exists", path);
+            }
+            rendered.push(RenderedFile {
+                dest,
+                path,
+                content,
+                append: file.append,
+            });
+        }
+        Ok(rendered)
+    }
+
+    /// Write the rendered files, the files appended to must exist or be created
+    /// by the template before
+    pub fn write(&self, files: &[RenderedFile]) -> Result<()> {
+        for (index, file) in files.iter().enumerate() {
+            let created_before = files[..index]
+                .iter()
+                .any(|f| !f.append && f.path == file.path);
+            if file.append && !file.path.exists() && !created_before {
+                bail!("can't append to {:?}, the file doesn't exist", file.path);
+            }
+        }
+        for file in files {
+            if file.append {
+                fs::OpenOptions::new()
+                    .append(true)
+                    .open(&file.path)
+                    .with_context(|| format!("can't append to {:?}", file.path))?
+                    .write_all(&file.content)?;
+                println!("Rewrite {}", file.dest);
+            } else {
+                if let Some(parent) = file.path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&file.path, &file.content)?;
+                println!("Created {}", file.dest);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A file of the template rendered for the contract
+pub struct RenderedFile {
+    /// Rendered destination in the manifest, which is printed
+    dest: String,
+    path: PathBuf,
+    content: Vec<u8>,
+    append: bool,
+}
+
+impl Drop for ExternalTemplate {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.checkout {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// Paths of the manifest must stay in the template and the project directories
+fn checked_relative_path(path: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(path);
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(anyhow!(
+            "invalid path {:?} in {}, only relative paths inside the directory are allowed",
+            path,
+            MANIFEST_FILE
+        ));
+    }
+    Ok(path)
+}
+
+/// Create the contract from the template, the contract is not added to capsule.toml
+pub fn create_contract(
+    context: &Context,
+    name: String,
+    template: &ExternalTemplate,
+) -> Result<Contract> {
+    let contract = Contract::new(name, template.template_type());
+    let recipe = get_recipe(context.clone(), contract.template_type)?;
+    if recipe.exists(&contract.name) {
+        bail!("contract '{}' is already exists", contract.name);
+    }
+    // render the files first, so nothing is changed if the template is broken
+    let files = template.render(&context.project_path, &contract.name)?;
+    println!("New contract {:?}", &contract.name);
+    recipe.prepare_contract(&contract)?;
+    if let Err(err) = template.write(&files) {
+        // unregister the contract, files are only written if all of them can be
+        let _ = recipe.remove_contract(&contract);
+        return Err(err);
+    }
+    Ok(contract)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_source() {
+        assert_eq!(
+            TemplateSource::new("templates/lock", None).unwrap(),
+            TemplateSource::Local(PathBuf::from("templates/lock"))
+        );
+        assert_eq!(
+            TemplateSource::new("https://example.com/templates.git", Some("v1")).unwrap(),
+            TemplateSource::Git {
+                url: "https://example.com/templates.git".to_string(),
+                reference: Some("v1".to_string())
+            }
+        );
+        assert!(TemplateSource::new("templates/lock", Some("v1")).is_err());
+        assert!(checked_relative_path("contracts/a/src/main.rs").is_ok());
+        assert!(checked_relative_path("../a").is_err());
+        assert!(checked_relative_path("/etc/passwd").is_err());
+    }
+
+    fn setup_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("capsule-external-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_render() {
+        let manifest = format!(
+            r#"
+capsule_version = "{}"
+template_type = "Rust"
+
+[[files]]
+src = "main.rs"
+dest = "contracts/{{{{ name }}}}/src/main.rs"
+
+[[files]]
+src = "logo.bin"
+dest = "contracts/{{{{ name }}}}/logo.bin"
+raw = true
+
+[[files]]
+src = "tests.rs"
+dest = "tests/src/lib.rs"
+append = true
+"#,
+            Version::current().to_string()
+        );
+        let template_dir = setup_dir(
+            "template",
+            &[
+                (MANIFEST_FILE, manifest.as_bytes()),
+                ("main.rs", b"// {{ name }}\n"),
+                ("logo.bin", b"{{ raw }}\xff"),
+                ("tests.rs", b"mod {{ name }};\n"),
+            ],
+        );
+        let project_path = setup_dir("project", &[("tests/src/lib.rs", b"mod a;\n")]);
+        let template =
+            ExternalTemplate::fetch(&TemplateSource::Local(template_dir.clone())).unwrap();
+
+        let files = template.render(&project_path, "lock").unwrap();
+        assert!(!project_path.join("contracts").exists());
+        template.write(&files).unwrap();
+        let read = |path: &str| fs::read(project_path.join(path)).unwrap();
+        assert_eq!(read("contracts/lock/src/main.rs"), b"// lock\n");
+        assert_eq!(read("contracts/lock/logo.bin"), b"{{ raw }}\xff");
+        assert_eq!(read("tests/src/lib.rs"), b"mod a;\nmod lock;\n");
+
+        // the created files already exist
+        assert!(template.render(&project_path, "lock").is_err());
+        // the file appended to doesn't exist
+        fs::remove_file(project_path.join("tests/src/lib.rs")).unwrap();
+        let files = template.render(&project_path, "other").unwrap();
+        assert!(template.write(&files).is_err());
+        assert!(!project_path.join("contracts/other").exists());
+        fs::remove_dir_all(template_dir).unwrap();
+        fs::remove_dir_all(project_path).unwrap();
+    }
+}
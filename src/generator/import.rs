@@ -0,0 +1,316 @@
+//! Import contracts
+//!
+//! An existing Rust contract crate is copied or linked into the contracts dir of a
+//! workspace, then registered to the workspace like a contract created by `new-contract`.
+//! The name of the contract is the package name of the crate.
+
+use crate::config::{Contract, TemplateType};
+use crate::config_manipulate::Document;
+use crate::project_context::{read_config_file, Context, CARGO_CONFIG_FILE};
+use crate::recipe::rust::{normalize_path, Rust, RUST_TARGET};
+use crate::recipe::Recipe;
+use anyhow::{anyhow, bail, Context as ErrorContext, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files of the crate which are not copied
+const SKIPPED_FILES: &[&str] = &["target", "Cargo.lock", ".git"];
+/// Cargo config of the crate, which is ignored in the workspace
+const CARGO_DIR_CONFIG_FILES: &[&str] = &[".cargo/config.toml", ".cargo/config"];
+const TOOLCHAIN_FILE: &str = "rust-toolchain";
+
+/// Main file of the binary named after the package, None if the crate is a library.
+/// The contract is built by `cargo build -p <name>` and loaded by its name, so the binary
+/// must be named after the package.
+fn bin_path(crate_path: &Path, name: &str, doc: &Document) -> Result<Option<PathBuf>> {
+    let bins = match doc.get("bin").and_then(|bins| bins.as_array_of_tables()) {
+        Some(bins) if !bins.is_empty() => bins,
+        _ => {
+            let path = crate_path.join("src/main.rs");
+            return Ok(path.exists().then_some(path));
+        }
+    };
+    let bin_name = |bin: &toml_edit::Table| {
+        bin.get("name")
+            .and_then(|name| name.as_str())
+            .map(str::to_string)
+    };
+    let bin = match bins.iter().find(|bin| bin_name(bin).as_deref() == Some(name)) {
+        Some(bin) => bin,
+        None => bail!(
+            "the binaries of {:?} are named [{}], one of them must be named after the package '{}' to be built as a contract",
+            crate_path,
+            bins.iter().filter_map(bin_name).collect::<Vec<_>>().join(", "),
+            name
+        ),
+    };
+    Ok(Some(
+        crate_path.join(
+            bin.get("path")
+                .and_then(|path| path.as_str())
+                .unwrap_or("src/main.rs"),
+        ),
+    ))
+}
+
+/// Dependencies of the crate by a path outside the crate, which can't be found once the
+/// crate is moved into the project
+fn external_path_dependencies(crate_path: &Path, doc: &Document) -> Vec<(String, String)> {
+    let mut tables = ["dependencies", "build-dependencies", "dev-dependencies"]
+        .iter()
+        .map(|key| doc.get(key))
+        .collect::<Vec<_>>();
+    if let Some(targets) = doc
+        .get("target")
+        .and_then(|targets| targets.as_table_like())
+    {
+        for (_, target) in targets.iter() {
+            for key in ["dependencies", "build-dependencies", "dev-dependencies"] {
+                tables.push(target.get(key));
+            }
+        }
+    }
+    tables
+        .into_iter()
+        .flatten()
+        .filter_map(|table| table.as_table_like())
+        .flat_map(|table| table.iter())
+        .filter_map(|(name, dep)| {
+            let path = dep.get("path").and_then(|path| path.as_str())?;
+            (!normalize_path(&crate_path.join(path)).starts_with(crate_path))
+                .then(|| (name.to_string(), path.to_string()))
+        })
+        .collect()
+}
+
+/// Check the crate can be built as a contract, return the package name
+fn check_crate(context: &Context, crate_path: &Path, doc: &Document) -> Result<String> {
+    let name = doc
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(|name| name.as_str())
+        .ok_or_else(|| anyhow!("can't find the package name in {:?}", crate_path))?
+        .to_string();
+    let bin_path = bin_path(crate_path, &name, doc)?
+        .ok_or_else(|| anyhow!("'{}' is not a binary crate, can't find src/main.rs", name))?;
+    let main = fs::read_to_string(&bin_path)
+        .with_context(|| format!("can't read the main file {:?}", bin_path))?;
+    for attribute in ["no_std", "no_main"] {
+        if !main.contains(attribute) {
+            bail!(
+                "{:?} doesn't declare `#![{}]`, contracts must be built without the standard library",
+                bin_path,
+                attribute
+            );
+        }
+    }
+    let has_ckb_std = ["dependencies", "target"]
+        .iter()
+        .filter_map(|key| doc.get(key))
+        .any(|item| item.to_string().contains("ckb-std"));
+    if !has_ckb_std {
+        println!("warn: '{}' doesn't depend on ckb-std", name);
+    }
+    for file in CARGO_DIR_CONFIG_FILES {
+        let path = crate_path.join(file);
+        if !path.exists() {
+            continue;
+        }
+        let config = read_config_file(&path)?.parse::<Document>()?;
+        let target = config
+            .get("build")
+            .and_then(|build| build.get("target"))
+            .and_then(|target| target.as_str());
+        if let Some(target) = target.filter(|target| !target.starts_with("riscv64")) {
+            bail!(
+                "{:?} builds for {}, contracts must be built for a RISC-V target, e.g. {}",
+                path,
+                target,
+                RUST_TARGET
+            );
+        }
+        if let Some(target) = target.filter(|target| *target != RUST_TARGET) {
+            println!(
+                "warn: {:?} builds for {}, set `target` in `[contracts.build]` of capsule.toml to keep it",
+                path, target
+            );
+        }
+        println!(
+            "warn: {:?} is ignored in the workspace, move the settings to `[contracts.build]` of capsule.toml",
+            path
+        );
+    }
+    if let Some((dep, path)) = external_path_dependencies(crate_path, doc).first() {
+        bail!(
+            "'{}' depends on '{}' by the path {:?} outside the crate, which can't be found once the crate is imported, \
+             please import or move '{}' into the project and depend on it from there",
+            name,
+            dep,
+            path,
+            dep
+        );
+    }
+    let toolchain = |path: PathBuf| {
+        fs::read_to_string(path)
+            .ok()
+            .map(|toolchain| toolchain.trim().to_string())
+    };
+    if let Some(crate_toolchain) = toolchain(crate_path.join(TOOLCHAIN_FILE)) {
+        if toolchain(context.project_path.join(TOOLCHAIN_FILE)).as_ref() != Some(&crate_toolchain) {
+            println!(
+                "warn: '{}' is pinned to the {} toolchain, the contract is built with the toolchain of the project",
+                name, crate_toolchain
+            );
+        }
+    }
+    Ok(name)
+}
+
+fn copy_dir(src: &Path, dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let path = entry.path();
+        if SKIPPED_FILES
+            .iter()
+            .any(|skipped| entry.file_name() == *skipped)
+        {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_dir(&path, &dest.join(entry.file_name()))?;
+        } else {
+            fs::copy(&path, dest.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the sections which are only valid in the workspace root
+fn strip_workspace_sections(manifest_path: &Path) -> Result<()> {
+    let mut doc = read_config_file(manifest_path)?.parse::<Document>()?;
+    let mut rewrite = false;
+    for key in ["workspace", "profile"] {
+        if doc.remove(key).is_some() {
+            println!(
+                "warn: removed `[{}]` of the crate, the settings of the workspace are used",
+                key
+            );
+            rewrite = true;
+        }
+    }
+    if rewrite {
+        fs::write(manifest_path, doc.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn link_dir(src: &Path, dest: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(src, dest)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn link_dir(_src: &Path, _dest: &Path) -> Result<()> {
+    bail!("linking crates is only supported on unix, please copy it instead");
+}
+
+/// Import the crate into the workspace, default is the default workspace of the project.
+/// The contract is not added to capsule.toml
+pub fn import_contract(
+    context: &Context,
+    crate_path: &Path,
+    link: bool,
+    workspace: Option<PathBuf>,
+) -> Result<Contract> {
+    let crate_path = crate_path
+        .canonicalize()
+        .with_context(|| format!("can't find the crate {:?}", crate_path))?;
+    let manifest_path = crate_path.join(CARGO_CONFIG_FILE);
+    let doc = read_config_file(&manifest_path)?.parse::<Document>()?;
+    let name = check_crate(context, &crate_path, &doc)?;
+    let mut contract = Contract::new(name, TemplateType::Rust);
+    contract.workspace = workspace;
+    let recipe = Rust::new(context.clone());
+    if recipe.exists(&contract.name)
+        || context
+            .config
+            .contracts
+            .iter()
+            .any(|c| c.name == contract.name)
+    {
+        bail!("contract '{}' is already exists", contract.name);
+    }
+    println!("Import contract {:?}", &contract.name);
+    let dest = recipe.contract_path(&contract)?;
+    let relative_dest = dest.strip_prefix(&context.project_path).unwrap_or(&dest);
+    recipe.workspace_manifest(&contract)?;
+    fs::create_dir_all(dest.parent().expect("contracts dir"))?;
+    if link {
+        if doc.contains_key("workspace") {
+            bail!(
+                "{:?} defines a `[workspace]`, which can't be a member of the project workspace, please copy it instead",
+                manifest_path
+            );
+        }
+        link_dir(&crate_path, &dest)?;
+        println!("Linked {} to {:?}", relative_dest.display(), crate_path);
+        println!("warn: the build image may not be able to access the crate outside the project");
+    } else {
+        copy_dir(&crate_path, &dest)?;
+        strip_workspace_sections(&dest.join(CARGO_CONFIG_FILE))?;
+        println!("Copied {:?} to {}", crate_path, relative_dest.display());
+    }
+    recipe.prepare_contract(&contract)?;
+    recipe.create_tests_stub(&contract)?;
+    Ok(contract)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bin_path() {
+        let doc: Document =
+            "[package]\nname = \"a\"\n\n[[bin]]\nname = \"a\"\npath = \"src/entry.rs\"\n"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            bin_path(Path::new("a"), "a", &doc).unwrap(),
+            Some(PathBuf::from("a/src/entry.rs"))
+        );
+        let doc: Document = "[package]\nname = \"a\"\n\n[[bin]]\nname = \"b\"\n"
+            .parse()
+            .unwrap();
+        assert!(bin_path(Path::new("a"), "a", &doc).is_err());
+        let doc: Document = "[package]\nname = \"a\"\n".parse().unwrap();
+        assert_eq!(
+            bin_path(Path::new("/nonexistent/a"), "a", &doc).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_external_path_dependencies() {
+        let doc: Document = r#"
+[dependencies]
+ckb-std = "0.15"
+common = { path = "../common" }
+inner = { path = "crates/inner" }
+
+[target.'cfg(target_arch = "riscv64")'.dependencies]
+hash = { path = "/libs/hash" }
+"#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            external_path_dependencies(Path::new("/crates/a"), &doc),
+            vec![
+                ("common".to_string(), "../common".to_string()),
+                ("hash".to_string(), "/libs/hash".to_string())
+            ]
+        );
+    }
+}
@@ -1,3 +1,8 @@
+pub mod external;
+pub mod import;
+pub mod modify;
+
+use crate::project_context::TESTS_DIR;
 use crate::util::git;
 use crate::version::Version;
 use anyhow::{bail, Context as ErrorContext, Result};
@@ -33,6 +38,8 @@ struct CreateProject {
     name: String,
     path: PathBuf,
     version: String,
+    /// Generate the example tests of the default contract template
+    example_tests: bool,
 }
 
 #[derive(Serialize)]
@@ -40,7 +47,11 @@ pub struct CreateContract {
     pub name: String,
 }
 
-fn gen_project_layout<P: AsRef<Path>>(name: String, project_path: P) -> Result<()> {
+fn gen_project_layout<P: AsRef<Path>>(
+    name: String,
+    project_path: P,
+    example_tests: bool,
+) -> Result<()> {
     let project_path = {
         let mut path = PathBuf::new();
         path.push(project_path);
@@ -48,7 +59,7 @@ fn gen_project_layout<P: AsRef<Path>>(name: String, project_path: P) -> Result<(
     };
     fs::create_dir(&project_path)
         .with_context(|| format!("directory exists {:?}", &project_path))?;
-    for f in &["contracts", "build"] {
+    for f in &["contracts", "build", "schemas"] {
         let mut dir_path = PathBuf::new();
         dir_path.push(&project_path);
         dir_path.push(f);
@@ -57,10 +68,11 @@ fn gen_project_layout<P: AsRef<Path>>(name: String, project_path: P) -> Result<(
         fs::File::create(&dir_path)?;
     }
     // generate files
-    let context = Context::from_serialize(&CreateProject {
+    let context = Context::from_serialize(CreateProject {
         name,
         path: project_path.clone(),
         version: Version::current().to_string(),
+        example_tests,
     })?;
     for (f, template_name) in &[
         ("capsule.toml", None),
@@ -80,12 +92,14 @@ fn gen_project_layout<P: AsRef<Path>>(name: String, project_path: P) -> Result<(
     Ok(())
 }
 
-fn gen_project_test<P: AsRef<Path>>(name: String, project_path: P) -> Result<()> {
-    const DEFAULT_TESTS_DIR: &str = "tests";
-
+fn gen_project_test<P: AsRef<Path>>(
+    name: String,
+    project_path: P,
+    example_tests: bool,
+) -> Result<()> {
     let project_path = project_path.as_ref().to_str().expect("path");
     let output = std::process::Command::new("cargo")
-        .args(["new", DEFAULT_TESTS_DIR, "--lib", "--vcs", "none"])
+        .args(["new", TESTS_DIR, "--lib", "--vcs", "none"])
         .current_dir(project_path)
         .output()?;
     if !output.status.success() {
@@ -97,18 +111,22 @@ fn gen_project_test<P: AsRef<Path>>(name: String, project_path: P) -> Result<()>
         path
     };
     // initialize tests code
-    let context = Context::from_serialize(&CreateProject {
+    let context = Context::from_serialize(CreateProject {
         name,
         path: project_path.clone(),
         version: Version::current().to_string(),
+        example_tests,
     })?;
     let mut tests_path = project_path;
-    tests_path.push(DEFAULT_TESTS_DIR);
+    tests_path.push(TESTS_DIR);
     for (f, template_name) in &[
         ("src/lib.rs", None),
         ("src/tests.rs", None),
         ("Cargo.toml", Some("Cargo-manifest.toml")),
     ] {
+        if *f == "src/tests.rs" && !example_tests {
+            continue;
+        }
         log::trace!("[modify test file] {}", f);
         let template_path = format!("rust/tests/{}", template_name.unwrap_or(f));
         let content = TEMPLATES.render(&template_path, &context)?;
@@ -119,20 +137,20 @@ fn gen_project_test<P: AsRef<Path>>(name: String, project_path: P) -> Result<()>
     Ok(())
 }
 
-// create a new project
-pub fn new_project<P: AsRef<Path>>(name: String, path: P) -> Result<PathBuf> {
+// create a new project, the example tests are for the default contract template
+pub fn new_project<P: AsRef<Path>>(name: String, path: P, example_tests: bool) -> Result<PathBuf> {
     let mut project_path: PathBuf = PathBuf::new();
     project_path.push(path);
     project_path.push(&name);
     // generate layouts
     println!("New project {:?}", &name);
-    gen_project_layout(name.clone(), &project_path)?;
+    gen_project_layout(name.clone(), &project_path, example_tests)?;
     println!("Created {:?}", &project_path);
     // generate contract
     let mut contracts_path = project_path.clone();
     contracts_path.push("contracts");
     // generate contract tests
     println!("Created tests");
-    gen_project_test(name, &project_path)?;
+    gen_project_test(name, &project_path, example_tests)?;
     Ok(project_path)
 }
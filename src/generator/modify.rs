@@ -0,0 +1,257 @@
+//! Remove and rename contracts
+//!
+//! Undo what creating the contract did: the entry of capsule.toml, the sources and
+//! build rules of the recipe, the build outputs and the generated tests, which are
+//! `tests/src/<name>_tests.rs` and `load_binary("<name>")` in the tests crate.
+
+use crate::config::{CellLocation, Contract};
+use crate::config_manipulate::{self, Document};
+use crate::fingerprint::Fingerprints;
+use crate::manifest::Manifest;
+use crate::project_context::{
+    read_config_file, write_config_file, BuildEnv, Context, CONFIG_FILE, TESTS_DIR,
+};
+use crate::recipe::{get_recipe, Recipe};
+use anyhow::{anyhow, bail, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn find_contract(context: &Context, name: &str) -> Result<Contract> {
+    context
+        .config
+        .contracts
+        .iter()
+        .find(|c| c.name == name)
+        .cloned()
+        .ok_or_else(|| anyhow!("can't find contract '{}' in {}", name, CONFIG_FILE))
+}
+
+fn rewrite_capsule_config(
+    context: &Context,
+    f: impl FnOnce(&mut Document) -> Result<bool>,
+) -> Result<()> {
+    println!("Rewrite {}", CONFIG_FILE);
+    let config_path = context.project_path.join(CONFIG_FILE);
+    let mut doc = read_config_file(&config_path)?.parse::<Document>()?;
+    if !f(&mut doc)? {
+        bail!("can't find the contract in {}", CONFIG_FILE);
+    }
+    write_config_file(&config_path, doc.to_string())
+}
+
+/// Remove the binaries and the build records of the contract, they are rebuilt after renaming
+fn remove_outputs(context: &Context, recipe: &dyn Recipe, contract: &Contract) -> Result<()> {
+    for build_env in [BuildEnv::Debug, BuildEnv::Release] {
+        let output_path = recipe.output_path(contract, build_env);
+        let debug_path = PathBuf::from(format!("{}.debug", output_path.display()));
+        for path in [output_path, debug_path] {
+            if path.exists() {
+                fs::remove_file(&path)?;
+                println!("Removed {:?}", path);
+            }
+        }
+        let mut fingerprints = Fingerprints::load(context, build_env)?;
+        if fingerprints.get(&contract.name).is_some() {
+            fingerprints.remove(&contract.name);
+            fingerprints.save(context, build_env)?;
+        }
+        let mut manifest = Manifest::load(context, build_env)?;
+        if manifest.get(&contract.name).is_some() {
+            manifest.remove(&contract.name);
+            manifest.save(context, build_env)?;
+        }
+    }
+    Ok(())
+}
+
+/// The deployment is written by hand, only warn about the cells deploying the contract
+fn check_deployment(context: &Context, recipe: &dyn Recipe, contract: &Contract) {
+    let deployment = match context.load_deployment() {
+        Ok(deployment) => deployment,
+        Err(_) => return,
+    };
+    let output_path = recipe.output_path(contract, BuildEnv::Release);
+    for cell in deployment.cells {
+        if let CellLocation::File { file } = &cell.location {
+            if Path::new(file).file_name() == output_path.file_name() {
+                println!(
+                    "warn: cell '{}' of the deployment is deployed from {:?}, please update it manually",
+                    cell.name, file
+                );
+            }
+        }
+    }
+}
+
+/// Module of the tests generated for the contract
+fn tests_module(name: &str) -> String {
+    format!("{}_tests", name.replace('-', "_"))
+}
+
+fn load_binary(name: &str) -> String {
+    format!("load_binary(\"{}\")", name)
+}
+
+/// Remove the declaration of the module, with the `#[cfg(test)]` before it
+fn remove_module(lib: &str, module: &str) -> String {
+    let declaration = format!("mod {};", module);
+    let lines: Vec<&str> = lib.lines().collect();
+    let mut kept: Vec<&str> = Vec::with_capacity(lines.len());
+    for line in lines {
+        if line.trim() == declaration {
+            if kept.last().map(|l| l.trim()) == Some("#[cfg(test)]") {
+                kept.pop();
+            }
+            if kept.last().map(|l| l.trim().is_empty()) == Some(true) {
+                kept.pop();
+            }
+            continue;
+        }
+        kept.push(line);
+    }
+    let mut content = kept.join("\n");
+    if lib.ends_with('\n') {
+        content.push('\n');
+    }
+    content
+}
+
+fn rename_module(lib: &str, module: &str, new_module: &str) -> String {
+    let declaration = format!("mod {};", module);
+    let mut content: String = lib
+        .lines()
+        .map(|line| {
+            if line.trim() == declaration {
+                line.replace(&declaration, &format!("mod {};", new_module))
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if lib.ends_with('\n') {
+        content.push('\n');
+    }
+    content
+}
+
+/// Rust sources of the tests crate
+fn test_sources(tests_src: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if !tests_src.is_dir() {
+        return Ok(files);
+    }
+    for entry in fs::read_dir(tests_src)? {
+        let path = entry?.path();
+        if path.extension().map(|ext| ext == "rs").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Remove or rename the tests generated for the contract
+fn rewrite_tests(context: &Context, contract: &Contract, new_name: Option<&str>) -> Result<()> {
+    let name = contract.name.as_str();
+    let tests_src = context.contract_tests_dir(contract)?.join("src");
+    let lib_path = tests_src.join("lib.rs");
+    let module = tests_module(name);
+    let module_path = tests_src.join(format!("{}.rs", module));
+    if module_path.exists() {
+        let lib = fs::read_to_string(&lib_path)?;
+        match new_name {
+            Some(new_name) => {
+                let new_module = tests_module(new_name);
+                fs::rename(&module_path, tests_src.join(format!("{}.rs", new_module)))?;
+                fs::write(&lib_path, rename_module(&lib, &module, &new_module))?;
+                println!(
+                    "Moved {dir}/src/{}.rs to {dir}/src/{}.rs",
+                    module,
+                    new_module,
+                    dir = TESTS_DIR
+                );
+            }
+            None => {
+                fs::remove_file(&module_path)?;
+                fs::write(&lib_path, remove_module(&lib, &module))?;
+                println!("Removed {}/src/{}.rs", TESTS_DIR, module);
+            }
+        }
+    }
+    // other tests loading the contract, in the tests crates of all the workspaces
+    let reference = load_binary(name);
+    let mut sources = Vec::new();
+    for workspace_dir in context.workspace_dirs()? {
+        sources.extend(test_sources(&workspace_dir.join(TESTS_DIR).join("src"))?);
+    }
+    for path in sources {
+        let content = fs::read_to_string(&path)?;
+        if !content.contains(&reference) {
+            continue;
+        }
+        match new_name {
+            Some(new_name) => {
+                fs::write(&path, content.replace(&reference, &load_binary(new_name)))?;
+                println!("Rewrite {:?}", path);
+            }
+            None => println!(
+                "warn: {:?} still loads the contract '{}', please update the tests manually",
+                path, name
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Remove the contract from the project
+pub fn remove_contract(context: &Context, name: &str) -> Result<()> {
+    let contract = find_contract(context, name)?;
+    let recipe = get_recipe(context.clone(), contract.template_type)?;
+    println!("Remove contract {:?}", name);
+    recipe.remove_contract(&contract)?;
+    remove_outputs(context, recipe.as_ref(), &contract)?;
+    rewrite_tests(context, &contract, None)?;
+    check_deployment(context, recipe.as_ref(), &contract);
+    rewrite_capsule_config(context, |doc| config_manipulate::remove_contract(doc, name))
+}
+
+/// Rename the contract, the binaries have to be rebuilt
+pub fn rename_contract(context: &Context, name: &str, new_name: &str) -> Result<()> {
+    let contract = find_contract(context, name)?;
+    if context.config.contracts.iter().any(|c| c.name == new_name) {
+        bail!("contract '{}' is already exists", new_name);
+    }
+    let recipe = get_recipe(context.clone(), contract.template_type)?;
+    if recipe.exists(new_name) {
+        bail!("contract '{}' is already exists", new_name);
+    }
+    println!("Rename contract {:?} to {:?}", name, new_name);
+    recipe.rename_contract(&contract, new_name)?;
+    remove_outputs(context, recipe.as_ref(), &contract)?;
+    rewrite_tests(context, &contract, Some(new_name))?;
+    check_deployment(context, recipe.as_ref(), &contract);
+    rewrite_capsule_config(context, |doc| {
+        config_manipulate::rename_contract(doc, name, new_name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_modules() {
+        let lib =
+            "mod tests;\n\n#[cfg(test)]\nmod my_lock_tests;\n\n#[cfg(test)]\nmod other_tests;\n";
+        assert_eq!(
+            remove_module(lib, "my_lock_tests"),
+            "mod tests;\n\n#[cfg(test)]\nmod other_tests;\n"
+        );
+        assert_eq!(
+            rename_module(lib, "my_lock_tests", "lock_tests"),
+            "mod tests;\n\n#[cfg(test)]\nmod lock_tests;\n\n#[cfg(test)]\nmod other_tests;\n"
+        );
+        assert_eq!(tests_module("my-lock"), "my_lock_tests");
+    }
+}
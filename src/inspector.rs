@@ -0,0 +1,374 @@
+//! Inspect contract binaries
+//!
+//! Report the layout of a RISC-V ELF contract, the instruction set extensions it uses,
+//! and the capacity needed to deploy it.
+
+use anyhow::{anyhow, Result};
+use ckb_sdk::HumanCapacity;
+use ckb_testtool::ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, ScriptHashType},
+    packed::{CellOutput, Script},
+    prelude::*,
+};
+use ckb_vm::decoder::build_decoder;
+use ckb_vm::instructions::{a, b, extract_opcode, instruction_length, insts};
+use ckb_vm::machine::VERSION2;
+use ckb_vm::memory::sparse::SparseMemory;
+use ckb_vm::{Memory, ISA_A, ISA_B, ISA_IMC, ISA_MOP, RISCV_MAX_MEMORY};
+use goblin::elf::{section_header::SHF_EXECINSTR, sym, Elf};
+use std::fmt;
+
+/// Args size of the default secp256k1_blake160 lock
+const LOCK_ARGS_SIZE: usize = 20;
+const TYPE_ID_ARGS_SIZE: usize = 32;
+
+pub struct SectionInfo {
+    pub name: String,
+    pub size: u64,
+}
+
+pub struct SymbolInfo {
+    pub name: String,
+    pub size: u64,
+}
+
+/// Instructions found in the executable sections
+#[derive(Default)]
+pub struct IsaUsage {
+    pub instructions: usize,
+    /// RVC instructions
+    pub compressed: usize,
+    /// Bit manipulation instructions, available since VM version 1
+    pub b: usize,
+    /// Atomic instructions, available since VM version 2
+    pub a: usize,
+    /// Instruction sequences fused by the VM since version 1
+    pub mop: Option<usize>,
+}
+
+pub struct Inspection {
+    pub size: usize,
+    pub entry: u64,
+    /// The binary has no symbol table
+    pub stripped: bool,
+    pub has_debug_info: bool,
+    /// Sections sorted by size in descending order
+    pub sections: Vec<SectionInfo>,
+    /// Largest symbols in descending order
+    pub symbols: Vec<SymbolInfo>,
+    pub isa: IsaUsage,
+    pub capacity: Capacity,
+    pub capacity_with_type_id: Capacity,
+}
+
+impl Inspection {
+    pub fn parse(binary: &[u8], max_symbols: usize) -> Result<Self> {
+        let elf = Elf::parse(binary).map_err(|err| anyhow!("invalid ELF binary: {}", err))?;
+        if elf.header.e_machine != goblin::elf::header::EM_RISCV {
+            return Err(anyhow!("not a RISC-V binary"));
+        }
+
+        let mut sections = Vec::new();
+        let mut isa = IsaUsage {
+            mop: Some(0),
+            ..Default::default()
+        };
+        for header in &elf.section_headers {
+            let name = elf
+                .shdr_strtab
+                .get(header.sh_name)
+                .and_then(|name| name.ok())
+                .unwrap_or_default();
+            if name.is_empty() || header.sh_size == 0 {
+                continue;
+            }
+            sections.push(SectionInfo {
+                name: name.to_string(),
+                size: header.sh_size,
+            });
+            if header.sh_flags & u64::from(SHF_EXECINSTR) != 0 {
+                let code = header
+                    .file_range()
+                    .and_then(|range| binary.get(range))
+                    .ok_or_else(|| anyhow!("invalid section {}", name))?;
+                scan_instructions(code, &mut isa);
+                isa.mop = match (isa.mop, count_fused_instructions(header.sh_addr, code)) {
+                    (Some(total), Some(count)) => Some(total + count),
+                    _ => None,
+                };
+            }
+        }
+        sections.sort_by(|a, b| b.size.cmp(&a.size));
+
+        let mut symbols: Vec<_> = elf
+            .syms
+            .iter()
+            .filter(|s| s.st_size > 0 && matches!(s.st_type(), sym::STT_FUNC | sym::STT_OBJECT))
+            .filter_map(|s| {
+                let name = elf.strtab.get(s.st_name)?.ok()?;
+                Some(SymbolInfo {
+                    name: format!("{:#}", rustc_demangle::demangle(name)),
+                    size: s.st_size,
+                })
+            })
+            .collect();
+        symbols.sort_by(|a, b| b.size.cmp(&a.size));
+        symbols.truncate(max_symbols);
+
+        Ok(Inspection {
+            size: binary.len(),
+            entry: elf.entry,
+            stripped: elf.syms.is_empty(),
+            has_debug_info: sections.iter().any(|s| s.name.starts_with(".debug_")),
+            sections,
+            symbols,
+            isa,
+            capacity: deploy_capacity(binary.len(), false)?,
+            capacity_with_type_id: deploy_capacity(binary.len(), true)?,
+        })
+    }
+
+    /// The minimal VM version able to run the binary
+    pub fn min_vm_version(&self) -> u32 {
+        if self.isa.a > 0 {
+            2
+        } else if self.isa.b > 0 {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Size: {} bytes", self.size)?;
+        writeln!(f, "Entry point: {:#x}", self.entry)?;
+        writeln!(
+            f,
+            "Stripped: {}, debug info: {}",
+            yes_or_no(self.stripped),
+            yes_or_no(self.has_debug_info)
+        )?;
+        writeln!(f, "Sections:")?;
+        for section in &self.sections {
+            writeln!(f, "  {:<24} {:>10}", section.name, section.size)?;
+        }
+        if !self.symbols.is_empty() {
+            writeln!(f, "Largest symbols:")?;
+            for symbol in &self.symbols {
+                writeln!(f, "  {:>10}  {}", symbol.size, symbol.name)?;
+            }
+        }
+        writeln!(f, "Instructions: {}", self.isa.instructions)?;
+        writeln!(f, "  C (compressed): {}", self.isa.compressed)?;
+        writeln!(f, "  B (bit manipulation): {}", self.isa.b)?;
+        writeln!(f, "  A (atomic): {}", self.isa.a)?;
+        match self.isa.mop {
+            Some(mop) => writeln!(f, "  MOP (fused sequences): {}", mop)?,
+            None => writeln!(f, "  MOP (fused sequences): unknown")?,
+        }
+        let hash_type = match self.min_vm_version() {
+            0 => "data, data1, data2 or type",
+            1 => "data1, data2 or type",
+            _ => "data2 or type",
+        };
+        writeln!(
+            f,
+            "Required VM version: {} (hash type: {})",
+            self.min_vm_version(),
+            hash_type
+        )?;
+        writeln!(
+            f,
+            "Deploy capacity: {} CKB",
+            HumanCapacity(self.capacity.as_u64())
+        )?;
+        write!(
+            f,
+            "Deploy capacity with type id: {} CKB",
+            HumanCapacity(self.capacity_with_type_id.as_u64())
+        )
+    }
+}
+
+fn yes_or_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Count instructions by extensions, RVC instructions are 2 bytes and others are 4 bytes
+fn scan_instructions(code: &[u8], isa: &mut IsaUsage) {
+    let mut offset = 0;
+    while offset + 2 <= code.len() {
+        let low = u16::from_le_bytes([code[offset], code[offset + 1]]);
+        if low & 0b11 != 0b11 {
+            isa.instructions += 1;
+            isa.compressed += 1;
+            offset += 2;
+            continue;
+        }
+        if offset + 4 > code.len() {
+            break;
+        }
+        let bits = u32::from_le_bytes([
+            code[offset],
+            code[offset + 1],
+            code[offset + 2],
+            code[offset + 3],
+        ]);
+        isa.instructions += 1;
+        if b::factory::<u64>(bits, VERSION2).is_some() {
+            isa.b += 1;
+        } else if a::factory::<u64>(bits, VERSION2).is_some() {
+            isa.a += 1;
+        }
+        offset += 4;
+    }
+}
+
+/// Decode the code with macro-op fusion enabled, return `None` if the code can't be loaded
+fn count_fused_instructions(addr: u64, code: &[u8]) -> Option<usize> {
+    let end = addr.checked_add(code.len() as u64)?;
+    if end > RISCV_MAX_MEMORY as u64 {
+        return None;
+    }
+    let mut memory = SparseMemory::<u64>::new_with_memory(RISCV_MAX_MEMORY);
+    memory.store_bytes(addr, code).ok()?;
+    let mut decoder = build_decoder::<u64>(ISA_IMC | ISA_B | ISA_A | ISA_MOP, VERSION2);
+    let mut count = 0;
+    let mut pc = addr;
+    while pc < end {
+        match decoder.decode(&mut memory, pc) {
+            Ok(inst) => {
+                let opcode = extract_opcode(inst);
+                if (insts::OP_WIDE_MUL..=insts::OP_CUSTOM_LOAD_IMM).contains(&opcode)
+                    || opcode == insts::OP_FAR_JUMP_REL
+                    || opcode == insts::OP_FAR_JUMP_ABS
+                {
+                    count += 1;
+                }
+                pc += u64::from(instruction_length(inst));
+            }
+            // data in the code section
+            Err(_) => pc += 2,
+        }
+    }
+    Some(count)
+}
+
+/// Capacity of a cell containing the binary, locked by the default lock
+fn deploy_capacity(data_size: usize, type_id: bool) -> Result<Capacity> {
+    let script = |args_size: usize| {
+        Script::new_builder()
+            .hash_type(ScriptHashType::Type.into())
+            .args(Bytes::from(vec![0u8; args_size]).pack())
+            .build()
+    };
+    let type_script = if type_id {
+        Some(script(TYPE_ID_ARGS_SIZE))
+    } else {
+        None
+    };
+    let output = CellOutput::new_builder()
+        .lock(script(LOCK_ARGS_SIZE))
+        .type_(type_script.pack())
+        .build();
+    let data_capacity = Capacity::bytes(data_size).map_err(|err| anyhow!(err))?;
+    output
+        .occupied_capacity(data_capacity)
+        .map_err(|err| anyhow!(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_testtool::builtin::ALWAYS_SUCCESS;
+
+    #[test]
+    fn test_parse() {
+        let inspection = Inspection::parse(&ALWAYS_SUCCESS, 3).unwrap();
+        assert_eq!(inspection.size, ALWAYS_SUCCESS.len());
+        assert_eq!(inspection.entry, 0x100cc);
+        assert!(!inspection.stripped);
+        assert!(inspection.has_debug_info);
+        let sections: Vec<_> = inspection
+            .sections
+            .iter()
+            .map(|s| (s.name.as_str(), s.size))
+            .take(3)
+            .collect();
+        assert_eq!(
+            sections,
+            vec![(".data", 1864), (".symtab", 1656), (".text", 800)]
+        );
+        let symbols: Vec<_> = inspection
+            .symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.size))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                ("impure_data", 1864),
+                ("__call_exitprocs", 174),
+                ("__libc_init_array", 102)
+            ]
+        );
+        assert_eq!((inspection.isa.b, inspection.isa.a), (0, 0));
+        assert_eq!(inspection.isa.mop, Some(3));
+        assert_eq!(inspection.min_vm_version(), 0);
+        assert!(inspection.capacity < inspection.capacity_with_type_id);
+
+        let output = inspection.to_string();
+        assert!(
+            output.contains("Stripped: no, debug info: yes"),
+            "{}",
+            output
+        );
+        assert!(output.contains("\n  .text                           800\n"));
+        assert!(output.contains("Largest symbols:\n        1864  impure_data\n"));
+        assert!(output.contains("Required VM version: 0 (hash type: data, data1, data2 or type)"));
+
+        assert!(Inspection::parse(b"not an ELF binary", 3).is_err());
+    }
+
+    #[test]
+    fn test_isa_usage() {
+        let code: Vec<u8> = [
+            // add a0, a0, a1
+            &0x00b50533u32.to_le_bytes()[..],
+            // c.nop
+            &0x0001u16.to_le_bytes(),
+            // clz a0, a1
+            &0x60059513u32.to_le_bytes(),
+            // lui a0, 0x12; addiw a0, a0, 0x345, fused to a load immediate
+            &0x00012537u32.to_le_bytes(),
+            &0x3455051bu32.to_le_bytes(),
+        ]
+        .concat();
+        let mut isa = IsaUsage::default();
+        scan_instructions(&code, &mut isa);
+        assert_eq!(
+            (isa.instructions, isa.compressed, isa.b, isa.a),
+            (5, 1, 1, 0)
+        );
+        assert_eq!(count_fused_instructions(0x10000, &code), Some(1));
+        // amoadd.w a0, a2, (a1)
+        scan_instructions(&0x00c5a52fu32.to_le_bytes(), &mut isa);
+        assert_eq!((isa.instructions, isa.b, isa.a), (6, 1, 1));
+
+        let mut inspection = Inspection::parse(&ALWAYS_SUCCESS, 0).unwrap();
+        inspection.isa.b = 1;
+        assert_eq!(inspection.min_vm_version(), 1);
+        inspection.isa.a = 1;
+        assert_eq!(inspection.min_vm_version(), 2);
+        assert!(inspection
+            .to_string()
+            .contains("(hash type: data2 or type)"));
+    }
+}
@@ -1,13 +1,24 @@
+pub mod build_image;
+pub mod builder;
 pub mod checker;
 pub mod config;
+pub mod config_check;
 pub mod config_manipulate;
 pub mod debugger;
 pub mod deployment;
+pub mod fingerprint;
 pub mod generator;
+pub mod inspector;
+pub mod manifest;
+pub mod molecule;
+pub mod native_toolchain;
+pub mod post_build;
 pub mod project_context;
 pub mod recipe;
 pub mod signal;
 pub mod tester;
+pub mod upgrade;
 pub mod util;
+pub mod verify_build;
 pub mod version;
 pub mod wallet;
@@ -0,0 +1,180 @@
+//! Build artifacts manifest
+//!
+//! `capsule build` writes `build/<env>/manifest.json` describing the contract binaries,
+//! so SDKs and deployment scripts can read the hashes without recomputing them.
+
+use crate::config::{Contract, TemplateType};
+use crate::project_context::{BuildEnv, Context};
+use crate::recipe::Toolchain;
+use anyhow::{Context as _, Result};
+use ckb_testtool::ckb_types::{packed::CellOutput, prelude::*, H256};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Data hash of the cell containing the binary, which is the `code_hash` of scripts
+/// referencing the contract by `data` hash type
+pub fn data_hash(data: &[u8]) -> H256 {
+    CellOutput::calc_data_hash(data).unpack()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    pub name: String,
+    pub template_type: TemplateType,
+    /// Path of the binary, relative to the project
+    pub path: PathBuf,
+    pub size: usize,
+    pub data_hash: H256,
+    pub build_env: String,
+    pub toolchain: Toolchain,
+    /// Commit of the project when the contract was built
+    pub git_commit: Option<String>,
+    /// The work tree had uncommitted changes, so the binary may not be built from the commit
+    #[serde(default)]
+    pub dirty: bool,
+}
+
+impl Artifact {
+    pub fn new(
+        context: &Context,
+        contract: &Contract,
+        build_env: BuildEnv,
+        binary_path: &Path,
+        toolchain: Toolchain,
+        git_commit: Option<String>,
+        dirty: bool,
+    ) -> Result<Self> {
+        let binary =
+            fs::read(binary_path).with_context(|| format!("failed to read {:?}", binary_path))?;
+        let path = binary_path
+            .strip_prefix(&context.project_path)
+            .unwrap_or(binary_path)
+            .to_path_buf();
+        let build_env = match build_env {
+            BuildEnv::Debug => "debug",
+            BuildEnv::Release => "release",
+        };
+        Ok(Artifact {
+            name: contract.name.clone(),
+            template_type: contract.template_type,
+            path,
+            size: binary.len(),
+            data_hash: data_hash(&binary),
+            build_env: build_env.to_string(),
+            toolchain,
+            git_commit,
+            dirty,
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub contracts: Vec<Artifact>,
+}
+
+impl Manifest {
+    pub fn path(context: &Context, build_env: BuildEnv) -> PathBuf {
+        context.contracts_build_path(build_env).join(MANIFEST_FILE)
+    }
+
+    pub fn load(context: &Context, build_env: BuildEnv) -> Result<Self> {
+        let path = Self::path(context, build_env);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read(&path)?;
+        serde_json::from_slice(&content).with_context(|| format!("failed to parse {:?}", path))
+    }
+
+    pub fn save(&self, context: &Context, build_env: BuildEnv) -> Result<()> {
+        let path = Self::path(context, build_env);
+        fs::create_dir_all(path.parent().expect("build dir"))?;
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Artifact> {
+        self.contracts.iter().find(|a| a.name == name)
+    }
+
+    /// Insert or replace the artifact, artifacts are sorted by name
+    pub fn insert(&mut self, artifact: Artifact) {
+        self.remove(&artifact.name);
+        let index = self
+            .contracts
+            .partition_point(|a| a.name.as_str() < artifact.name.as_str());
+        self.contracts.insert(index, artifact);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.contracts.retain(|a| a.name != name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_testtool::builtin::ALWAYS_SUCCESS;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_data_hash() {
+        // blake2b-256 with the `ckb-default-hash` personalization
+        let expected =
+            H256::from_str("e683b04139344768348499c23eb1326d5a52d6db006c0d2fece00a831f3660d7")
+                .unwrap();
+        assert_eq!(data_hash(&ALWAYS_SUCCESS), expected);
+    }
+
+    #[test]
+    fn test_manifest() {
+        let project_path =
+            std::env::temp_dir().join(format!("capsule-manifest-{}", std::process::id()));
+        let context =
+            Context::from_config(&project_path, toml_edit::de::from_str("").unwrap()).unwrap();
+        let build_dir = context.contracts_build_path(BuildEnv::Release);
+        fs::create_dir_all(&build_dir).unwrap();
+        assert!(Manifest::load(&context, BuildEnv::Release)
+            .unwrap()
+            .contracts
+            .is_empty());
+
+        let artifact = |name: &str, dirty: bool| {
+            let binary_path = build_dir.join(name);
+            fs::write(&binary_path, &ALWAYS_SUCCESS[..]).unwrap();
+            Artifact::new(
+                &context,
+                &Contract::new(name.to_string(), TemplateType::C),
+                BuildEnv::Release,
+                &binary_path,
+                Toolchain::default(),
+                Some("0123abcd".to_string()),
+                dirty,
+            )
+            .unwrap()
+        };
+        let mut manifest = Manifest::default();
+        manifest.insert(artifact("type", false));
+        manifest.insert(artifact("lock", true));
+        manifest.insert(artifact("type", true));
+        manifest.save(&context, BuildEnv::Release).unwrap();
+
+        let manifest = Manifest::load(&context, BuildEnv::Release).unwrap();
+        let names: Vec<_> = manifest.contracts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["lock", "type"]);
+        let lock = manifest.get("lock").unwrap();
+        assert_eq!(lock.path, Path::new("build/release/lock"));
+        assert_eq!(lock.size, ALWAYS_SUCCESS.len());
+        assert_eq!(lock.data_hash, data_hash(&ALWAYS_SUCCESS));
+        assert_eq!(lock.build_env, "release");
+        assert_eq!(lock.git_commit.as_deref(), Some("0123abcd"));
+        assert!(lock.dirty);
+        assert!(manifest.get("type").unwrap().dirty);
+        fs::remove_dir_all(&project_path).unwrap();
+    }
+}
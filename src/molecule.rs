@@ -0,0 +1,259 @@
+//! Molecule code generation
+//!
+//! Schemas under `schemas/` are compiled by moleculec into the output directories
+//! configured in `[molecule]` of capsule.toml: Rust modules for contracts (no_std),
+//! Rust modules for the tests crate (std) and C headers. Code is regenerated only
+//! if the schemas, moleculec or the config changed since the last generation.
+
+use crate::config::MoleculeConfig;
+use crate::fingerprint::hash_sources;
+use crate::project_context::Context;
+use anyhow::{anyhow, bail, Context as ErrorContext, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const DEFAULT_SCHEMAS_DIR: &str = "schemas";
+const FINGERPRINT_FILE: &str = ".molecule.json";
+const SCHEMA_EXTENSION: &str = "mol";
+const RUST_MOD_FILE: &str = "mod.rs";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    RustNoStd,
+    RustStd,
+    C,
+}
+
+impl Language {
+    fn moleculec_language(&self) -> &'static str {
+        match self {
+            Language::RustNoStd | Language::RustStd => "rust",
+            Language::C => "c",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Language::RustNoStd | Language::RustStd => "rs",
+            Language::C => "h",
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct CodegenFingerprint {
+    /// blake2b hash of the schemas
+    sources: String,
+    moleculec: String,
+    config: String,
+    /// Generated files, removed when their schemas are removed
+    files: Vec<PathBuf>,
+}
+
+impl MoleculeConfig {
+    pub fn schemas_dir(&self) -> &Path {
+        self.schemas_dir
+            .as_deref()
+            .unwrap_or_else(|| Path::new(DEFAULT_SCHEMAS_DIR))
+    }
+
+    fn outputs(&self) -> Vec<(Language, &Path)> {
+        [
+            (Language::RustNoStd, &self.rust),
+            (Language::RustStd, &self.rust_std),
+            (Language::C, &self.c),
+        ]
+        .into_iter()
+        .filter_map(|(language, dir)| dir.as_deref().map(|dir| (language, dir)))
+        .collect()
+    }
+
+    fn moleculec(&self) -> &Path {
+        self.moleculec
+            .as_deref()
+            .unwrap_or_else(|| Path::new("moleculec"))
+    }
+}
+
+/// Version of moleculec, None if it can't be found
+pub fn moleculec_version(moleculec: &Path) -> Option<String> {
+    let output = Command::new(moleculec).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+}
+
+/// Schema files to compile, sorted by path
+fn schema_files(context: &Context, config: &MoleculeConfig) -> Result<Vec<PathBuf>> {
+    let schemas_dir = context.project_path.join(config.schemas_dir());
+    let mut files = Vec::new();
+    if config.schemas.is_empty() {
+        if !schemas_dir.is_dir() {
+            return Ok(files);
+        }
+        for entry in fs::read_dir(&schemas_dir)? {
+            let path = entry?.path();
+            if path.is_file()
+                && path.extension().and_then(|ext| ext.to_str()) == Some(SCHEMA_EXTENSION)
+            {
+                files.push(path);
+            }
+        }
+    } else {
+        for schema in &config.schemas {
+            let path = schemas_dir.join(schema);
+            if !path.is_file() {
+                bail!("can't find the molecule schema {:?}", path);
+            }
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn module_name(schema: &Path) -> Result<String> {
+    schema
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.replace('-', "_"))
+        .ok_or_else(|| anyhow!("invalid schema file name {:?}", schema))
+}
+
+fn compile(moleculec: &Path, language: Language, schema: &Path) -> Result<Vec<u8>> {
+    let output = Command::new(moleculec)
+        .args(["--language", language.moleculec_language(), "--schema-file"])
+        .arg(schema)
+        .output()
+        .with_context(|| format!("failed to run {:?}", moleculec))?;
+    if !output.status.success() {
+        bail!(
+            "failed to compile the molecule schema {:?}: {}",
+            schema,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// `mod.rs` of the generated Rust modules, schemas import each other with `super::`
+fn rust_mod_file(language: Language, modules: &[String]) -> String {
+    let mut content =
+        String::from("// Generated by capsule from the molecule schemas, don't edit\n");
+    if language == Language::RustNoStd {
+        content.push_str(
+            "// The crate depends on `molecule` with `default-features = false` to build in no_std\n",
+        );
+    }
+    content.push_str("#![allow(clippy::all)]\n#![allow(dead_code)]\n\n");
+    for module in modules {
+        content.push_str(&format!("pub mod {};\n", module));
+    }
+    content
+}
+
+/// Generate code from the molecule schemas, return false if the code is up to date
+pub fn generate(context: &Context) -> Result<bool> {
+    let config = &context.config.molecule;
+    let outputs = config.outputs();
+    if outputs.is_empty() {
+        return Ok(false);
+    }
+    let schemas = schema_files(context, config)?;
+    let moleculec = config.moleculec();
+    let version = moleculec_version(moleculec).ok_or_else(|| {
+        anyhow!(
+            "can't find {:?} to compile the molecule schemas, please install it with `cargo install moleculec`",
+            moleculec
+        )
+    })?;
+    let fingerprint_path = context.contracts_build_dir().join(FINGERPRINT_FILE);
+    let previous: CodegenFingerprint = match fs::read(&fingerprint_path) {
+        Ok(content) => serde_json::from_slice(&content).unwrap_or_default(),
+        Err(_) => CodegenFingerprint::default(),
+    };
+    let mut fingerprint = CodegenFingerprint {
+        sources: hash_sources(&context.project_path, &schemas)?,
+        moleculec: version,
+        config: serde_json::to_string(config)?,
+        files: Vec::new(),
+    };
+    let mut files = Vec::new();
+    for (language, dir) in &outputs {
+        for schema in &schemas {
+            let module = module_name(schema)?;
+            files.push(dir.join(format!("{}.{}", module, language.extension())));
+        }
+        if matches!(language, Language::RustNoStd | Language::RustStd) {
+            files.push(dir.join(RUST_MOD_FILE));
+        }
+    }
+    fingerprint.files = files;
+    let up_to_date = fingerprint == previous
+        && fingerprint
+            .files
+            .iter()
+            .all(|file| context.project_path.join(file).exists());
+    if up_to_date {
+        return Ok(false);
+    }
+
+    for (language, dir) in &outputs {
+        let dir = context.project_path.join(dir);
+        fs::create_dir_all(&dir)?;
+        let mut modules = Vec::new();
+        for schema in &schemas {
+            let module = module_name(schema)?;
+            let code = compile(moleculec, *language, schema)?;
+            fs::write(
+                dir.join(format!("{}.{}", module, language.extension())),
+                code,
+            )?;
+            modules.push(module);
+        }
+        if matches!(language, Language::RustNoStd | Language::RustStd) {
+            fs::write(dir.join(RUST_MOD_FILE), rust_mod_file(*language, &modules))?;
+        }
+    }
+    // remove the code of removed schemas
+    for file in &previous.files {
+        if !fingerprint.files.contains(file) {
+            let path = context.project_path.join(file);
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+    fs::create_dir_all(context.contracts_build_dir())?;
+    fs::write(&fingerprint_path, serde_json::to_vec_pretty(&fingerprint)?)?;
+    println!(
+        "Generated code from {} molecule schema(s) in {}",
+        schemas.len(),
+        config.schemas_dir().display()
+    );
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust_mod_file() {
+        let modules = vec!["blockchain".to_string(), "my_types".to_string()];
+        let content = rust_mod_file(Language::RustStd, &modules);
+        assert!(content.ends_with("pub mod blockchain;\npub mod my_types;\n"));
+        assert!(!content.contains("no_std"));
+        assert!(rust_mod_file(Language::RustNoStd, &modules).contains("no_std"));
+        assert_eq!(
+            module_name(Path::new("schemas/my-types.mol")).unwrap(),
+            "my_types"
+        );
+    }
+}
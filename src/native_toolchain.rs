@@ -0,0 +1,142 @@
+//! Local RISC-V toolchains
+//!
+//! With `build_mode = "native"`, C and Lua contracts are built with a RISC-V GNU toolchain
+//! or clang installed on the host instead of the docker image. The same Makefile targets
+//! are used, only the compiler variables are overridden on the make command line.
+
+use anyhow::{anyhow, Result};
+use std::process::Command;
+
+/// Prefixes of GNU toolchains targeting rv64imac with the lp64 ABI, as the docker image does
+const GNU_PREFIXES: [&str; 2] = ["riscv64-unknown-linux-gnu", "riscv64-unknown-elf"];
+/// The same ISA as the GNU toolchains, the B extension can be enabled by the `cflags`
+/// of contracts targeting the VM versions supporting it
+const CLANG_FLAGS: &str = "--target=riscv64 -march=rv64imac -Wno-unknown-warning-option";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NativeToolchainKind {
+    Gnu { prefix: String },
+    Clang,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NativeToolchain {
+    pub kind: NativeToolchainKind,
+    /// First line of `<cc> --version`
+    pub version: String,
+}
+
+impl NativeToolchain {
+    /// Find a RISC-V toolchain in `PATH`, GNU toolchains are preferred
+    pub fn detect() -> Option<Self> {
+        for prefix in GNU_PREFIXES {
+            if let Some(version) = program_version(&format!("{}-gcc", prefix)) {
+                return Some(NativeToolchain {
+                    kind: NativeToolchainKind::Gnu {
+                        prefix: prefix.to_string(),
+                    },
+                    version,
+                });
+            }
+        }
+        // clang needs the RISC-V backend and lld to link
+        let version = program_version("clang")?;
+        let targets = Command::new("clang").arg("--print-targets").output().ok()?;
+        if !String::from_utf8_lossy(&targets.stdout).contains("riscv64")
+            || program_version("ld.lld").is_none()
+        {
+            return None;
+        }
+        Some(NativeToolchain {
+            kind: NativeToolchainKind::Clang,
+            version,
+        })
+    }
+
+    /// Like `detect`, but return an error if no toolchain is found
+    pub fn require() -> Result<Self> {
+        Self::detect().ok_or_else(|| {
+            anyhow!("can't find a local RISC-V toolchain for the native build, please install riscv64-unknown-linux-gnu-gcc or clang with lld, or build in docker")
+        })
+    }
+
+    pub fn cc(&self) -> String {
+        match &self.kind {
+            NativeToolchainKind::Gnu { prefix } => format!("{}-gcc", prefix),
+            NativeToolchainKind::Clang => format!("clang {}", CLANG_FLAGS),
+        }
+    }
+
+    pub fn ld(&self) -> String {
+        match &self.kind {
+            NativeToolchainKind::Gnu { .. } => self.cc(),
+            NativeToolchainKind::Clang => format!("{} -fuse-ld=lld", self.cc()),
+        }
+    }
+
+    pub fn objcopy(&self) -> String {
+        match &self.kind {
+            NativeToolchainKind::Gnu { prefix } => format!("{}-objcopy", prefix),
+            NativeToolchainKind::Clang => "llvm-objcopy".to_string(),
+        }
+    }
+
+    pub fn is_gnu(&self) -> bool {
+        matches!(self.kind, NativeToolchainKind::Gnu { .. })
+    }
+
+    /// Variables overriding the compilers of the C and Lua Makefiles
+    pub fn make_variables(&self) -> Vec<String> {
+        vec![
+            format!("CC={}", self.cc()),
+            format!("LD={}", self.ld()),
+            format!("OBJCOPY={}", self.objcopy()),
+            format!("LUA_CC={}", self.cc()),
+        ]
+    }
+}
+
+fn program_version(program: &str) -> Option<String> {
+    let output = Command::new(program).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make_variables() {
+        let gnu = NativeToolchain {
+            kind: NativeToolchainKind::Gnu {
+                prefix: "riscv64-unknown-elf".to_string(),
+            },
+            version: String::new(),
+        };
+        assert_eq!(
+            gnu.make_variables(),
+            vec![
+                "CC=riscv64-unknown-elf-gcc",
+                "LD=riscv64-unknown-elf-gcc",
+                "OBJCOPY=riscv64-unknown-elf-objcopy",
+                "LUA_CC=riscv64-unknown-elf-gcc",
+            ]
+        );
+        let clang = NativeToolchain {
+            kind: NativeToolchainKind::Clang,
+            version: String::new(),
+        };
+        assert_eq!(
+            clang.cc(),
+            "clang --target=riscv64 -march=rv64imac -Wno-unknown-warning-option"
+        );
+        assert!(clang.ld().ends_with("-fuse-ld=lld"));
+        assert_eq!(clang.objcopy(), "llvm-objcopy");
+    }
+}
@@ -0,0 +1,179 @@
+//! Post-build steps
+//!
+//! Strip, split debug info and rewrite sections of contract binaries with objcopy,
+//! then run the extra commands configured in `[contracts.post_build]`.
+
+use crate::config::{PostBuildConfig, StripMode};
+use crate::native_toolchain::NativeToolchain;
+use crate::project_context::{BuildEnv, Context};
+use crate::signal::Signal;
+use crate::util::cli::{self, shell_quote};
+use crate::util::docker::DockerCommand;
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_OBJCOPY: &str = "riscv64-unknown-linux-gnu-objcopy";
+const DEBUG_FILE_EXTENSION: &str = "debug";
+
+impl PostBuildConfig {
+    pub fn applies_to(&self, build_env: BuildEnv) -> bool {
+        match build_env {
+            BuildEnv::Debug => self.debug,
+            BuildEnv::Release => true,
+        }
+    }
+
+    /// Shell commands running the steps on the binary, the path is relative to the project.
+    /// The arguments of objcopy are quoted, the user commands are run as they are.
+    fn shell_commands(&self, binary: &str) -> Vec<String> {
+        let objcopy = self.objcopy.as_deref().unwrap_or(DEFAULT_OBJCOPY);
+        let debug_file = format!("{}.{}", binary, DEBUG_FILE_EXTENSION);
+        let mut commands = Vec::new();
+        if self.split_debug {
+            commands.push(shell_command(
+                objcopy,
+                ["--only-keep-debug", binary, debug_file.as_str()],
+            ));
+        }
+        let mut args = Vec::new();
+        match self.strip {
+            StripMode::None => {}
+            StripMode::Debug => args.push("--strip-debug".to_string()),
+            StripMode::All => args.push("--strip-all".to_string()),
+        }
+        for section in &self.remove_sections {
+            args.push(format!("--remove-section={}", section));
+        }
+        args.extend(self.objcopy_args.iter().cloned());
+        if self.split_debug {
+            args.push(format!("--add-gnu-debuglink={}", debug_file));
+        }
+        if !args.is_empty() {
+            args.push(binary.to_string());
+            commands.push(shell_command(objcopy, args.iter().map(String::as_str)));
+        }
+        if !self.commands.is_empty() {
+            commands.push(format!("export BINARY={}", shell_quote(binary)));
+            commands.extend(self.commands.iter().cloned());
+        }
+        commands
+    }
+}
+
+/// Shell command of the program and arguments, each of them is quoted
+fn shell_command<'a>(program: &'a str, args: impl IntoIterator<Item = &'a str>) -> String {
+    std::iter::once(program)
+        .chain(args)
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Path of the split debug file of the binary
+pub fn debug_file_path(binary_path: &Path) -> PathBuf {
+    let mut file_name = binary_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(DEBUG_FILE_EXTENSION);
+    binary_path.with_file_name(file_name)
+}
+
+/// Run the post-build steps on the binary, in the build image if there is one
+pub fn run_post_build(
+    context: &Context,
+    config: &PostBuildConfig,
+    binary_path: &Path,
+    image: Option<&str>,
+    signal: &Signal,
+) -> Result<()> {
+    let debug_file = debug_file_path(binary_path);
+    if debug_file.exists() {
+        // don't leave a stale debug file for the debugger
+        fs::remove_file(&debug_file)?;
+    }
+    let binary = binary_path
+        .strip_prefix(&context.project_path)
+        .map_err(|_| anyhow!("binary {:?} is not in the project", binary_path))?
+        .to_string_lossy()
+        .to_string();
+    let commands = match (image, &config.objcopy) {
+        // use the objcopy of the local toolchain
+        (None, None) => match NativeToolchain::detect() {
+            Some(toolchain) => PostBuildConfig {
+                objcopy: Some(toolchain.objcopy()),
+                ..config.clone()
+            }
+            .shell_commands(&binary),
+            None => config.shell_commands(&binary),
+        },
+        _ => config.shell_commands(&binary),
+    };
+    if commands.is_empty() {
+        return Ok(());
+    }
+    context.output.println("Running post-build steps");
+    let shell_cmd = commands.join(" && ");
+    match image {
+        Some(image) => {
+            let project_path = context.project_path.to_string_lossy().to_string();
+            let build_dir = binary_path
+                .parent()
+                .and_then(|dir| dir.strip_prefix(&context.project_path).ok())
+                .map(|dir| dir.to_string_lossy().to_string())
+                .unwrap_or_default();
+            DockerCommand::with_context(
+                context,
+                image.to_string(),
+                project_path,
+                context.docker_env_file.clone(),
+            )
+            .fix_dir_permission(build_dir)
+            .run_with_output(shell_cmd, signal, &context.output)
+        }
+        None => cli::run_shell(shell_cmd, &context.project_path, signal, &context.output),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_commands() {
+        let config = PostBuildConfig {
+            strip: StripMode::Debug,
+            split_debug: true,
+            remove_sections: vec![".comment".to_string()],
+            commands: vec!["ls -l $BINARY".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.shell_commands("build/release/a"),
+            vec![
+                "riscv64-unknown-linux-gnu-objcopy --only-keep-debug build/release/a build/release/a.debug",
+                "riscv64-unknown-linux-gnu-objcopy --strip-debug --remove-section=.comment --add-gnu-debuglink=build/release/a.debug build/release/a",
+                "export BINARY=build/release/a",
+                "ls -l $BINARY",
+            ]
+        );
+        assert!(PostBuildConfig::default().shell_commands("a").is_empty());
+        let config = PostBuildConfig {
+            objcopy: Some("/opt/riscv tools/objcopy".to_string()),
+            remove_sections: vec![".note; rm -rf ~".to_string()],
+            commands: vec!["ls -l \"$BINARY\"".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.shell_commands("build/release/it's"),
+            vec![
+                "'/opt/riscv tools/objcopy' '--remove-section=.note; rm -rf ~' 'build/release/it'\\''s'",
+                "export BINARY='build/release/it'\\''s'",
+                "ls -l \"$BINARY\"",
+            ]
+        );
+        assert_eq!(
+            debug_file_path(Path::new("build/release/a.so")),
+            Path::new("build/release/a.so.debug")
+        );
+    }
+}
@@ -1,5 +1,7 @@
 /// Project Context
-use crate::config::{Config, Deployment};
+use crate::config::{Config, ContainerRuntime, Contract, Deployment};
+use crate::config_check::{self, Severity};
+use crate::util::cli::Output;
 use crate::version::Version;
 use anyhow::{anyhow, Result};
 use log::error;
@@ -12,6 +14,7 @@ pub const CONTRACTS_DIR: &str = "contracts";
 const CONTRACTS_BUILD_DIR: &str = "build";
 const MIGRATIONS_DIR: &str = "migrations";
 pub const CONFIG_FILE: &str = "capsule.toml";
+pub const TESTS_DIR: &str = "tests";
 pub const CARGO_CONFIG_FILE: &str = "Cargo.toml";
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -65,6 +68,10 @@ pub struct Context {
     pub use_docker_host: bool,
     pub docker_env_file: String,
     pub rustup_dir: Option<String>,
+    // Where the output of build commands goes
+    pub output: Output,
+    // Overrides the build dir of contracts
+    pub build_dir: Option<PathBuf>,
 }
 
 impl Context {
@@ -73,46 +80,108 @@ impl Context {
     }
 
     pub fn load_from_path<P: AsRef<Path>>(path: P) -> Result<Context> {
-        let mut project_path = PathBuf::new();
-        project_path.push(&path);
-        let content = {
-            let mut config_path = project_path.clone();
-            config_path.push(CONFIG_FILE);
-            read_config_file(config_path)?
-        };
-        let config: Config = toml_edit::de::from_str(&content).expect("parse config");
+        let context = Self::load_from_path_unchecked(path)?;
         let capsule_version = Version::current();
-        let project_version: Version = config.version.parse()?;
+        let project_version: Version = context.config.version.parse()?;
         if !capsule_version.is_compatible(&project_version) {
             return Err(anyhow!(
-                "Incompatible capsule version {}, this project requires a version that's compatible with {}",
+                "Incompatible capsule version {}, this project requires a version that's compatible with {}, run `capsule upgrade` to upgrade an older project",
                 capsule_version.to_string(),
                 project_version.to_string()
             ));
         }
+        Ok(context)
+    }
+
+    /// Load the project without checking the capsule version, which is upgraded by `capsule upgrade`
+    pub fn load_from_path_unchecked<P: AsRef<Path>>(path: P) -> Result<Context> {
+        let config_path = path.as_ref().join(CONFIG_FILE);
+        let content = read_config_file(&config_path)?;
+        let config = config_check::parse_config(&config_path, &content).map_err(|diagnostics| {
+            let errors: Vec<_> = diagnostics.iter().map(ToString::to_string).collect();
+            anyhow!("invalid config: {}", errors.join("\n"))
+        })?;
+        let context = Self::from_config(path, config)?;
+        let mut errors = Vec::new();
+        for diagnostic in config_check::check_config(&context, &config_path, &content) {
+            match diagnostic.severity {
+                Severity::Error => errors.push(diagnostic.to_string()),
+                Severity::Warning => println!("warn: {}", diagnostic),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(anyhow!("invalid config: {}", errors.join("\n")));
+        }
+        Ok(context)
+    }
+
+    /// Context of the parsed config, which is not validated
+    pub fn from_config<P: AsRef<Path>>(path: P, mut config: Config) -> Result<Context> {
+        let mut project_path = PathBuf::new();
+        project_path.push(&path);
+        if let Some(runtime) = ContainerRuntime::from_env()? {
+            config.container_runtime = runtime;
+        }
         Ok(Context {
             config,
             project_path,
             use_docker_host: false,
             docker_env_file: String::new(),
             rustup_dir: None,
+            output: Output::Inherit,
+            build_dir: None,
         })
     }
 
+    /// The default workspace of Rust contracts
     pub fn workspace_dir(&self) -> Result<PathBuf> {
-        let mut path = self.project_path.clone();
-        if let Some(workspace_dir) = self.config.rust.workspace_dir.as_ref() {
-            match workspace_dir.to_str() {
-                Some(".") => {}
-                Some(CONTRACTS_DIR) => {
-                    path.push(workspace_dir);
-                }
-                dir => {
-                    return Err(anyhow!("Invalid `workspace_dir` config: {:?}, only allowed \".\" or \"contracts\".", dir));
+        match self.config.rust.workspace_dir.as_ref() {
+            Some(dir) => self.resolve_workspace_dir(dir),
+            None => Ok(self.project_path.clone()),
+        }
+    }
+
+    fn resolve_workspace_dir(&self, dir: &Path) -> Result<PathBuf> {
+        if dir.is_absolute() {
+            return Err(anyhow!(
+                "Invalid workspace dir {:?}, it must be relative to the project dir",
+                dir
+            ));
+        }
+        Ok(self.project_path.join(dir))
+    }
+
+    /// The workspace the Rust contract is built in
+    pub fn contract_workspace_dir(&self, contract: &Contract) -> Result<PathBuf> {
+        match contract.workspace.as_ref() {
+            Some(dir) => self.resolve_workspace_dir(dir),
+            None => self.workspace_dir(),
+        }
+    }
+
+    /// Tests crate of the contract, the one of the default workspace is used if the
+    /// workspace of the contract doesn't have one
+    pub fn contract_tests_dir(&self, contract: &Contract) -> Result<PathBuf> {
+        let tests_dir = self.contract_workspace_dir(contract)?.join(TESTS_DIR);
+        if tests_dir.exists() {
+            Ok(tests_dir)
+        } else {
+            Ok(self.workspace_dir()?.join(TESTS_DIR))
+        }
+    }
+
+    /// All the workspaces of Rust contracts, the default one is the first
+    pub fn workspace_dirs(&self) -> Result<Vec<PathBuf>> {
+        let mut dirs = vec![self.workspace_dir()?];
+        for contract in &self.config.contracts {
+            if let Some(dir) = contract.workspace.as_ref() {
+                let dir = self.resolve_workspace_dir(dir)?;
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
                 }
             }
         }
-        Ok(path)
+        Ok(dirs)
     }
 
     pub fn contracts_path(&self) -> PathBuf {
@@ -122,6 +191,9 @@ impl Context {
     }
 
     pub fn contracts_build_dir(&self) -> PathBuf {
+        if let Some(dir) = self.build_dir.as_ref() {
+            return dir.clone();
+        }
         let mut path = self.project_path.clone();
         path.push(CONTRACTS_BUILD_DIR);
         path
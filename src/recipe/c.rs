@@ -1,26 +1,38 @@
-use crate::config::Contract;
+use crate::config::{BuildMode, Contract, ContractVariant};
 use crate::generator::{CreateContract, TEMPLATES};
+use crate::native_toolchain::NativeToolchain;
 use crate::project_context::{BuildConfig, BuildEnv, Context, CONTRACTS_DIR};
-use crate::recipe::Recipe;
+use crate::recipe::{
+    check_extra_cflags, makefile_variable, rewrite_makefile_rules, Recipe, Toolchain,
+};
 use crate::signal::Signal;
 use crate::util::cli;
 use crate::util::git;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Context as _, Result};
 use std::fs;
 use std::io::Write;
 use std::marker::PhantomData;
 use std::path::PathBuf;
+use std::process::Command;
 use tera;
 
 // Files
 
 const MAKEFILE: &str = "Makefile";
+/// Program running the build image in the Makefile
+pub(crate) const CONTAINER_RUNTIME_VARIABLE: &str = "DOCKER";
+/// Build image in the Makefile, overridden by the pinned image in capsule.toml
+const IMAGE_VARIABLE: &str = "BUILDER_DOCKER";
+/// Header generated from the CKB protocol schema in the Makefile
+const PROTOCOL_HEADER_VARIABLE: &str = "PROTOCOL_HEADER";
 
 // Dirs
 
 const C_DIR_PREFIX: &str = "c";
+const C_CLANG_DIR_PREFIX: &str = "c-clang";
 const DEPS_DIR_PREFIX: &str = "deps";
 const SRC_DIR_PREFIX: &str = "src";
+const BUILD_DIR: &str = "build";
 const DEBUG_DIR: &str = "build/debug";
 const RELEASE_DIR: &str = "build/release";
 
@@ -32,8 +44,19 @@ const CKB_C_STDLIB_NAME: &str = "ckb-c-stdlib";
 
 pub trait CRecipe {
     fn bin_name(name: &str) -> String;
+    /// Template of the contract source, relative to the templates dir
     fn src_template() -> &'static str;
+    /// Template of the Makefile rules of the contract, relative to the templates dir
     fn build_template() -> &'static str;
+    /// Directory of the contracts and the Makefile template,
+    /// the Makefile defines the compiler used by the rules of `build_template`
+    fn dir_name() -> &'static str {
+        C_DIR_PREFIX
+    }
+    /// Return true if contracts are always built with the compiler on the host
+    fn native() -> bool {
+        false
+    }
 }
 pub struct CBin;
 
@@ -43,11 +66,11 @@ impl CRecipe for CBin {
     }
 
     fn src_template() -> &'static str {
-        "bin/contract/example.c"
+        "c/bin/contract/example.c"
     }
 
     fn build_template() -> &'static str {
-        "bin/contract/BUILD"
+        "c/bin/contract/BUILD"
     }
 }
 pub struct CSharedLib;
@@ -58,11 +81,57 @@ impl CRecipe for CSharedLib {
     }
 
     fn src_template() -> &'static str {
-        "sharedlib/contract/example.c"
+        "c/sharedlib/contract/example.c"
+    }
+
+    fn build_template() -> &'static str {
+        "c/sharedlib/contract/BUILD"
+    }
+}
+pub struct CClang;
+
+impl CRecipe for CClang {
+    fn bin_name(name: &str) -> String {
+        name.to_string()
+    }
+
+    fn src_template() -> &'static str {
+        "c/bin/contract/example.c"
+    }
+
+    fn build_template() -> &'static str {
+        "c/bin/contract/BUILD"
+    }
+
+    fn dir_name() -> &'static str {
+        C_CLANG_DIR_PREFIX
+    }
+
+    fn native() -> bool {
+        true
+    }
+}
+pub struct CClangSharedLib;
+
+impl CRecipe for CClangSharedLib {
+    fn bin_name(name: &str) -> String {
+        format!("{}.so", name)
+    }
+
+    fn src_template() -> &'static str {
+        "c/sharedlib/contract/example.c"
     }
 
     fn build_template() -> &'static str {
-        "sharedlib/contract/BUILD"
+        "c/sharedlib/contract/BUILD"
+    }
+
+    fn dir_name() -> &'static str {
+        C_CLANG_DIR_PREFIX
+    }
+
+    fn native() -> bool {
+        true
     }
 }
 
@@ -81,7 +150,7 @@ impl<R: CRecipe> C<R> {
 
     fn c_dir(&self) -> PathBuf {
         let mut c_dir = self.context.contracts_path();
-        c_dir.push(C_DIR_PREFIX);
+        c_dir.push(R::dir_name());
         c_dir
     }
 
@@ -117,7 +186,7 @@ impl<R: CRecipe> C<R> {
         let rel_path = format!(
             "{contracts}/{c}/{deps}/{name}",
             contracts = CONTRACTS_DIR,
-            c = C_DIR_PREFIX,
+            c = R::dir_name(),
             deps = DEPS_DIR_PREFIX,
             name = CKB_C_STDLIB_NAME
         );
@@ -129,16 +198,36 @@ impl<R: CRecipe> C<R> {
         )?;
 
         // Generate files
-        let f = "Makefile";
-        let template_path = format!("c/{}", f);
+        let template_path = format!("{}/{}", R::dir_name(), MAKEFILE);
         let content = TEMPLATES.render(&template_path, &tera::Context::default())?;
         let mut file_path = c_dir;
-        file_path.push(f);
+        file_path.push(MAKEFILE);
         fs::write(file_path, content)?;
 
         Ok(())
     }
 
+    /// Versions of the compiler of the Makefile and lld on the host
+    fn host_toolchain(&self) -> Result<Toolchain> {
+        let cc = makefile_variable(&self.makefile_path(), "CC").unwrap_or_else(|| "clang".into());
+        let mut toolchain = Toolchain::default();
+        for program in [cc.as_str(), "ld.lld"] {
+            let output = match Command::new(program).arg("--version").output() {
+                Ok(output) if output.status.success() => output,
+                _ => {
+                    return Err(anyhow!(
+                        "can't find {}, please install clang and lld",
+                        program
+                    ))
+                }
+            };
+            if let Some(version) = String::from_utf8_lossy(&output.stdout).lines().next() {
+                toolchain.versions.push(version.trim().to_string());
+            }
+        }
+        Ok(toolchain)
+    }
+
     fn source_name(&self, name: &str) -> String {
         format!("{}.c", name)
     }
@@ -149,6 +238,21 @@ impl<R: CRecipe> C<R> {
             BuildEnv::Release => format!("{}/{}", RELEASE_DIR, R::bin_name(name)),
         }
     }
+
+    /// Remove the binaries built by the Makefile
+    fn remove_build_targets(&self, name: &str) -> Result<()> {
+        for build_env in [BuildEnv::Debug, BuildEnv::Release] {
+            let bin_path = self.c_dir().join(self.build_target(build_env, name));
+            // the Makefile extracts the debug info into `<bin>.debug`
+            let debug_path = PathBuf::from(format!("{}.debug", bin_path.display()));
+            for path in [debug_path, bin_path] {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<R: CRecipe> Recipe for C<R> {
@@ -161,31 +265,34 @@ impl<R: CRecipe> Recipe for C<R> {
     fn create_contract(
         &self,
         contract: &Contract,
+        variant: ContractVariant,
         rewrite_config: bool,
         _signal: &Signal,
         _docker_env_file: String,
     ) -> Result<()> {
+        if variant != ContractVariant::Default {
+            bail!(
+                "the {} variant is only supported by Rust contracts",
+                variant.name()
+            );
+        }
         // setup c environment if needed
         self.setup_c_environment()?;
 
         // new contract
         let name = &contract.name;
         println!("New contract {:?}", &name);
-        let context = tera::Context::from_serialize(&CreateContract { name: name.clone() })?;
+        let context = tera::Context::from_serialize(CreateContract { name: name.clone() })?;
 
         // initialize contract code
-        let f = R::src_template();
-        let template_path = format!("c/{}", f);
-        let content = TEMPLATES.render(&template_path, &context)?;
+        let content = TEMPLATES.render(R::src_template(), &context)?;
         let mut src_path = self.src_dir();
         src_path.push(self.source_name(name));
         fs::write(src_path, content)?;
 
         if rewrite_config {
             println!("Rewrite Makefile");
-            let f = R::build_template();
-            let template_path = format!("c/{}", f);
-            let content = TEMPLATES.render(&template_path, &context)?;
+            let content = TEMPLATES.render(R::build_template(), &context)?;
             let makefile_path = self.makefile_path();
             fs::OpenOptions::new()
                 .append(true)
@@ -195,6 +302,57 @@ impl<R: CRecipe> Recipe for C<R> {
         Ok(())
     }
 
+    fn prepare_contract(&self, _contract: &Contract) -> Result<()> {
+        // the template appends the build rules to the Makefile
+        self.setup_c_environment()
+    }
+
+    fn remove_contract(&self, contract: &Contract) -> Result<()> {
+        let name = &contract.name;
+        let src_path = self.src_dir().join(self.source_name(name));
+        if src_path.exists() {
+            fs::remove_file(&src_path)?;
+            println!("Removed {:?}", src_path);
+        }
+        self.remove_build_targets(name)?;
+        let makefile = fs::read_to_string(self.makefile_path())?;
+        match rewrite_makefile_rules(&makefile, R::build_template(), name, None)? {
+            Some(content) => {
+                println!("Rewrite Makefile");
+                fs::write(self.makefile_path(), content)?;
+            }
+            None => println!(
+                "warn: can't find the build rules of '{}' in {:?}, please remove them manually",
+                name,
+                self.makefile_path()
+            ),
+        }
+        Ok(())
+    }
+
+    fn rename_contract(&self, contract: &Contract, new_name: &str) -> Result<()> {
+        let name = &contract.name;
+        let src_path = self.src_dir().join(self.source_name(name));
+        let new_src_path = self.src_dir().join(self.source_name(new_name));
+        // find the rules before moving anything
+        let makefile = fs::read_to_string(self.makefile_path())?;
+        let content = rewrite_makefile_rules(&makefile, R::build_template(), name, Some(new_name))?
+            .ok_or_else(|| {
+                anyhow!(
+                    "can't find the build rules of '{}' in {:?}, please rename the contract manually",
+                    name,
+                    self.makefile_path()
+                )
+            })?;
+        fs::rename(&src_path, &new_src_path)
+            .with_context(|| format!("failed to move {:?}", src_path))?;
+        println!("Moved {:?} to {:?}", src_path, new_src_path);
+        self.remove_build_targets(name)?;
+        println!("Rewrite Makefile");
+        fs::write(self.makefile_path(), content)?;
+        Ok(())
+    }
+
     /// run command
     /// Delegate to cli command
     fn run(&self, _contract: &Contract, build_cmd: String, signal: &Signal) -> Result<()> {
@@ -210,16 +368,39 @@ impl<R: CRecipe> Recipe for C<R> {
         signal: &Signal,
         _build_args_opt: Option<Vec<String>>,
     ) -> Result<()> {
+        check_extra_cflags(&self.makefile_path(), c)?;
         let build_target = self.build_target(config.build_env, &c.name);
         let mut bin_path = self.c_dir();
         bin_path.push(&build_target);
         // make sure the bin dir is exist
         fs::create_dir_all(bin_path.parent().ok_or(anyhow!("expect build dir"))?)?;
-        self.run(
-            c,
-            format!("make via-docker ARGS=\"{}\"", &build_target),
-            signal,
-        )?;
+        let mut cmd = Command::new("make");
+        if !R::native() && self.context.config.build_mode == BuildMode::Docker {
+            // ARGS is passed to make in docker by `bash -c "make $(ARGS)"`
+            let make_args: Vec<_> = std::iter::once(build_target.clone())
+                .chain(c.build.make_variables())
+                .chain(c.build.args.iter().cloned())
+                .map(|arg| cli::shell_quote(&arg))
+                .collect();
+            cmd.arg("via-docker")
+                .arg(format!("ARGS={}", make_args.join(" ")))
+                .arg(format!(
+                    "{}={}",
+                    CONTAINER_RUNTIME_VARIABLE,
+                    self.context.config.container_runtime.program()
+                ));
+            if let Some(image) = &self.context.config.images.c {
+                cmd.arg(format!("{}={}", IMAGE_VARIABLE, image));
+            }
+        } else {
+            cmd.arg(&build_target);
+            if !R::native() {
+                cmd.args(NativeToolchain::require()?.make_variables());
+            }
+            cmd.args(c.build.make_variables()).args(&c.build.args);
+        }
+        cmd.envs(&c.build.env).current_dir(self.c_dir());
+        cli::run_command(cmd, signal, &self.context.output)?;
 
         // copy to build dir
         if !bin_path.exists() {
@@ -228,17 +409,74 @@ impl<R: CRecipe> Recipe for C<R> {
                 bin_path,
             ));
         }
-        let mut target_path = self.context.project_path.clone();
-        target_path.push(&build_target);
+        let target_path = self.output_path(c, config.build_env);
         // make sure the target dir is exist
         fs::create_dir_all(target_path.parent().ok_or(anyhow!("expect build dir"))?)?;
         fs::copy(bin_path, target_path)?;
         Ok(())
     }
 
+    /// Generate the protocol header, which all the contract rules depend on
+    fn prepare_build(&self, signal: &Signal) -> Result<()> {
+        let header = match makefile_variable(&self.makefile_path(), PROTOCOL_HEADER_VARIABLE) {
+            Some(header) => header,
+            None => return Ok(()),
+        };
+        let mut cmd = Command::new("make");
+        cmd.arg(header).current_dir(self.c_dir());
+        cli::run_command(cmd, signal, &self.context.output)
+    }
+
     /// clean contract
     /// Delegate to Makefile
     fn clean(&self, _contracts: &[Contract], signal: &Signal) -> Result<()> {
         cli::run("make clean".to_string(), self.c_dir(), signal)
     }
+
+    /// Contracts may include any header of the C dir, so the whole dir is hashed except the
+    /// build outputs, plus the headers generated from molecule schemas
+    fn source_paths(&self, _contract: &Contract) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        let c_dir = self.c_dir();
+        if c_dir.is_dir() {
+            for entry in fs::read_dir(&c_dir)? {
+                let path = entry?.path();
+                if path
+                    .file_name()
+                    .map(|name| name != BUILD_DIR)
+                    .unwrap_or(true)
+                {
+                    paths.push(path);
+                }
+            }
+        }
+        if let Some(dir) = self.context.config.molecule.c.as_ref() {
+            paths.push(self.context.project_path.join(dir));
+        }
+        Ok(paths)
+    }
+
+    fn output_path(&self, contract: &Contract, build_env: BuildEnv) -> PathBuf {
+        self.context
+            .contracts_build_path(build_env)
+            .join(contract.output_name(R::bin_name(&contract.name)))
+    }
+
+    fn toolchain(&self, _contract: &Contract) -> Result<Toolchain> {
+        if R::native() {
+            return self.host_toolchain();
+        }
+        match self.context.config.build_mode {
+            BuildMode::Docker => Ok(Toolchain::with_image(
+                self.context.config.container_runtime,
+                self.context
+                    .config
+                    .images
+                    .c
+                    .clone()
+                    .or_else(|| makefile_variable(&self.makefile_path(), IMAGE_VARIABLE)),
+            )),
+            BuildMode::Native => Ok(Toolchain::native(&NativeToolchain::require()?)),
+        }
+    }
 }
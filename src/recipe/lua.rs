@@ -1,20 +1,29 @@
-use crate::config::{Contract, TemplateType};
+use crate::config::{BuildMode, Contract, ContractVariant, TemplateType};
 use crate::generator::{CreateContract, TEMPLATES};
+use crate::native_toolchain::NativeToolchain;
 use crate::project_context::{BuildConfig, BuildEnv, Context, CONTRACTS_DIR};
-use crate::recipe::Recipe;
+use crate::recipe::c::CONTAINER_RUNTIME_VARIABLE;
+use crate::recipe::{
+    check_extra_cflags, makefile_variable, rewrite_makefile_rules, Recipe, Toolchain,
+};
 use crate::signal::Signal;
 use crate::util::cli;
 use crate::util::git;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Context as _, Result};
 use std::fs;
 use std::io::Write;
 use std::marker::PhantomData;
 use std::path::PathBuf;
+use std::process::Command;
 use tera;
 
 // Files
 
 const MAKEFILE: &str = "Makefile";
+/// Suffix of the Makefile targets building in docker, it's empty in native builds
+const VIA_DOCKER_VARIABLE: &str = "VIA_DOCKER";
+/// Build image in the Makefile, overridden by the pinned image in capsule.toml
+const IMAGE_VARIABLE: &str = "LUA_BUILDER_IMAGE";
 
 // Dirs
 
@@ -98,6 +107,28 @@ impl<R: LuaRecipe> Lua<R> {
         p
     }
 
+    /// All the Lua contracts are built by one `make build`, so the settings passed to make
+    /// must be the same, otherwise some contracts would be built with the flags of another.
+    fn check_shared_settings(&self, contract: &Contract) -> Result<()> {
+        let conflict = self.context.config.contracts.iter().find(|c| {
+            matches!(
+                c.template_type,
+                TemplateType::Lua | TemplateType::LuaEmbedded
+            ) && (c.build.cflags != contract.build.cflags
+                || c.build.env != contract.build.env
+                || c.build.args != contract.build.args)
+        });
+        if let Some(c) = conflict {
+            bail!(
+                "Lua contracts are built together by `make build`, but the `cflags`, `env` or `args` \
+                 of '{}' differ from the ones of '{}', please use the same settings",
+                contract.name,
+                c.name
+            );
+        }
+        Ok(())
+    }
+
     fn setup_lua_environment(&self) -> Result<()> {
         println!("Setup Lua environment");
         let lua_dir = self.lua_dir();
@@ -160,6 +191,37 @@ impl<R: LuaRecipe> Lua<R> {
         }
     }
 
+    /// Files of the tests crate generated for the contract
+    fn generated_tests(
+        &self,
+        name: &str,
+        contract_type: TemplateType,
+    ) -> Result<Vec<(PathBuf, String)>> {
+        let context = tera::Context::from_serialize(CreateContract {
+            name: name.to_string(),
+        })?;
+        let mut files = Vec::new();
+        for f in &["Cargo.toml", "build.rs", "src/lib.rs", "src/tests.rs"] {
+            let template_path = self.template_path(f, contract_type);
+            let content = TEMPLATES.render(&template_path, &context)?;
+            let mut file_path = self.context.project_path.clone();
+            file_path.push(format!("tests/{}", f));
+            files.push((file_path, content));
+        }
+        Ok(files)
+    }
+
+    /// Remove the binaries built by the Makefile
+    fn remove_build_targets(&self, name: &str) -> Result<()> {
+        for build_env in [BuildEnv::Debug, BuildEnv::Release] {
+            let bin_path = self.lua_dir().join(self.bin_path(build_env, name));
+            if bin_path.exists() {
+                fs::remove_file(bin_path)?;
+            }
+        }
+        Ok(())
+    }
+
     fn bin_path(&self, build_env: BuildEnv, name: &str) -> String {
         match build_env {
             BuildEnv::Debug => format!("{}/{}", DEBUG_DIR, R::bin_name(name)),
@@ -182,17 +244,24 @@ impl<R: LuaRecipe> Recipe for Lua<R> {
     fn create_contract(
         &self,
         contract: &Contract,
+        variant: ContractVariant,
         rewrite_config: bool,
         _signal: &Signal,
         _docker_env_file: String,
     ) -> Result<()> {
+        if variant != ContractVariant::Default {
+            bail!(
+                "the {} variant is only supported by Rust contracts",
+                variant.name()
+            );
+        }
         // setup lua environment if needed
         self.setup_lua_environment()?;
 
         // new contract
         let name = &contract.name;
         println!("New contract {:?}", &name);
-        let context = tera::Context::from_serialize(&CreateContract { name: name.clone() })?;
+        let context = tera::Context::from_serialize(CreateContract { name: name.clone() })?;
 
         // initialize contract code
         let f = R::src_template();
@@ -202,17 +271,7 @@ impl<R: LuaRecipe> Recipe for Lua<R> {
         src_path.push(self.source_name(name, contract.template_type));
         fs::write(src_path, content)?;
 
-        for (f, template_name) in &[
-            ("Cargo.toml", None),
-            ("build.rs", None),
-            ("src/lib.rs", None),
-            ("src/tests.rs", None),
-        ] {
-            let template_path =
-                self.template_path(template_name.unwrap_or(f), contract.template_type);
-            let content = TEMPLATES.render(&template_path, &context)?;
-            let mut file_path = self.context.project_path.clone();
-            file_path.push(format!("tests/{}", f));
+        for (file_path, content) in self.generated_tests(name, contract.template_type)? {
             fs::write(file_path, content)?;
         }
 
@@ -230,6 +289,96 @@ impl<R: LuaRecipe> Recipe for Lua<R> {
         Ok(())
     }
 
+    fn prepare_contract(&self, _contract: &Contract) -> Result<()> {
+        // the template appends the build rules to the Makefile
+        self.setup_lua_environment()
+    }
+
+    fn remove_contract(&self, contract: &Contract) -> Result<()> {
+        let name = &contract.name;
+        let src_path = self
+            .src_dir()
+            .join(self.source_name(name, contract.template_type));
+        if src_path.exists() {
+            fs::remove_file(&src_path)?;
+            println!("Removed {:?}", src_path);
+        }
+        self.remove_build_targets(name)?;
+        let f = R::build_template();
+        let template_path = format!("{}/{}", LUA_TEMPLATE_DIR_PREFIX, f);
+        let makefile = fs::read_to_string(self.makefile_path())?;
+        match rewrite_makefile_rules(&makefile, &template_path, name, None)? {
+            Some(content) => {
+                println!("Rewrite Makefile");
+                fs::write(self.makefile_path(), content)?;
+            }
+            None => println!(
+                "warn: can't find the build rules of '{}' in {:?}, please remove them manually",
+                name,
+                self.makefile_path()
+            ),
+        }
+        // the tests crate is generated for the contract, it's kept for the other contracts
+        let references = [
+            format!("\"{}\"", name),
+            format!("/{}\"", name),
+            format!("/{}.lua\"", name),
+        ];
+        for (file_path, _) in self.generated_tests(name, contract.template_type)? {
+            let content = fs::read_to_string(&file_path).unwrap_or_default();
+            if references.iter().any(|r| content.contains(r.as_str())) {
+                println!(
+                    "warn: {:?} still references '{}', please update it manually",
+                    file_path, name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn rename_contract(&self, contract: &Contract, new_name: &str) -> Result<()> {
+        let name = &contract.name;
+        let src_path = self
+            .src_dir()
+            .join(self.source_name(name, contract.template_type));
+        let new_src_path = self
+            .src_dir()
+            .join(self.source_name(new_name, contract.template_type));
+        // find the rules before moving anything
+        let f = R::build_template();
+        let template_path = format!("{}/{}", LUA_TEMPLATE_DIR_PREFIX, f);
+        let makefile = fs::read_to_string(self.makefile_path())?;
+        let content = rewrite_makefile_rules(&makefile, &template_path, name, Some(new_name))?
+            .ok_or_else(|| {
+                anyhow!(
+                    "can't find the build rules of '{}' in {:?}, please rename the contract manually",
+                    name,
+                    self.makefile_path()
+                )
+            })?;
+        fs::rename(&src_path, &new_src_path)
+            .with_context(|| format!("failed to move {:?}", src_path))?;
+        println!("Moved {:?} to {:?}", src_path, new_src_path);
+        self.remove_build_targets(name)?;
+        println!("Rewrite Makefile");
+        fs::write(self.makefile_path(), content)?;
+        // regenerate the tests crate unless it has been edited
+        let generated = self.generated_tests(name, contract.template_type)?;
+        let new_generated = self.generated_tests(new_name, contract.template_type)?;
+        for ((file_path, content), (_, new_content)) in generated.into_iter().zip(new_generated) {
+            if fs::read_to_string(&file_path).ok().as_deref() == Some(content.as_str()) {
+                fs::write(&file_path, new_content)?;
+                println!("Rewrite {:?}", file_path);
+            } else if content != new_content {
+                println!(
+                    "warn: {:?} has been modified, please rename '{}' in it manually",
+                    file_path, name
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// run command
     /// Delegate to cli command
     fn run(&self, _contract: &Contract, build_cmd: String, signal: &Signal) -> Result<()> {
@@ -250,7 +399,54 @@ impl<R: LuaRecipe> Recipe for Lua<R> {
         bin_path.push(&path);
         // make sure the bin dir is exist
         fs::create_dir_all(bin_path.parent().ok_or(anyhow!("expect build dir"))?)?;
-        self.run(c, "make build".to_string(), signal)?;
+        check_extra_cflags(&self.makefile_path(), c)?;
+        self.check_shared_settings(c)?;
+        // all the Lua contracts are built by `make build`, they share the same settings
+        let mut cmd = Command::new("make");
+        cmd.arg("build");
+        if self.context.config.build_mode == BuildMode::Native {
+            let makefile = fs::read_to_string(self.makefile_path())?;
+            if !makefile.contains(VIA_DOCKER_VARIABLE) {
+                return Err(anyhow!(
+                    "{:?} doesn't support the native build, {} is not defined",
+                    self.makefile_path(),
+                    VIA_DOCKER_VARIABLE
+                ));
+            }
+            let toolchain = NativeToolchain::require()?;
+            // the embedded rule links libgcc.a found by `$(LUA_CC) --print-search-dirs`
+            if !toolchain.is_gnu()
+                && self
+                    .context
+                    .config
+                    .contracts
+                    .iter()
+                    .any(|c| c.template_type == TemplateType::LuaEmbedded)
+            {
+                bail!(
+                    "Lua contracts embedded in C are linked with libgcc, which requires a RISC-V GNU toolchain, \
+                     please install riscv64-unknown-linux-gnu-gcc or build in docker"
+                );
+            }
+            cmd.arg(format!("{}=", VIA_DOCKER_VARIABLE))
+                .args(toolchain.make_variables());
+        } else {
+            cmd.arg(format!(
+                "{}={}",
+                CONTAINER_RUNTIME_VARIABLE,
+                self.context.config.container_runtime.program()
+            ));
+            // the image of ckb-lua is `BUILDER_DOCKER`
+            if let Some(image) = &self.context.config.images.lua {
+                cmd.arg(format!("{}={}", IMAGE_VARIABLE, image))
+                    .arg(format!("BUILDER_DOCKER={}", image));
+            }
+        }
+        cmd.args(c.build.make_variables())
+            .args(&c.build.args)
+            .envs(&c.build.env)
+            .current_dir(self.lua_dir());
+        cli::run_command(cmd, signal, &self.context.output)?;
 
         // copy to build dir
         if !bin_path.exists() {
@@ -259,8 +455,7 @@ impl<R: LuaRecipe> Recipe for Lua<R> {
                 bin_path,
             ));
         }
-        let mut target_path = self.context.project_path.clone();
-        target_path.push(&path);
+        let target_path = self.output_path(c, config.build_env);
         // make sure the target dir is exist
         fs::create_dir_all(target_path.parent().ok_or(anyhow!("expect build dir"))?)?;
         fs::copy(bin_path, target_path)?;
@@ -272,4 +467,45 @@ impl<R: LuaRecipe> Recipe for Lua<R> {
     fn clean(&self, _contracts: &[Contract], signal: &Signal) -> Result<()> {
         cli::run("make clean".to_string(), self.lua_dir(), signal)
     }
+
+    fn source_paths(&self, contract: &Contract) -> Result<Vec<PathBuf>> {
+        Ok(vec![
+            self.src_dir()
+                .join(self.source_name(&contract.name, contract.template_type)),
+            self.makefile_path(),
+        ])
+    }
+
+    fn output_path(&self, contract: &Contract, build_env: BuildEnv) -> PathBuf {
+        self.context
+            .contracts_build_path(build_env)
+            .join(contract.output_name(R::bin_name(&contract.name)))
+    }
+
+    fn toolchain(&self, _contract: &Contract) -> Result<Toolchain> {
+        let mut toolchain = match self.context.config.build_mode {
+            BuildMode::Docker => Toolchain::with_image(
+                self.context.config.container_runtime,
+                self.context
+                    .config
+                    .images
+                    .lua
+                    .clone()
+                    .or_else(|| makefile_variable(&self.makefile_path(), IMAGE_VARIABLE)),
+            ),
+            BuildMode::Native => Toolchain::native(&NativeToolchain::require()?),
+        };
+        let ckb_lua_dir: PathBuf = [DEPS_DIR_PREFIX, CKB_LUA_NAME].iter().collect();
+        if let Ok(commit) = git::head_commit(self.lua_dir().join(ckb_lua_dir)) {
+            toolchain
+                .versions
+                .push(format!("{} {}", CKB_LUA_NAME, commit));
+        }
+        Ok(toolchain)
+    }
+
+    /// `make build` builds all the Lua contracts
+    fn exclusive_build(&self) -> bool {
+        true
+    }
 }
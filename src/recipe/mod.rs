@@ -2,30 +2,219 @@ mod c;
 mod lua;
 pub mod rust;
 
-use crate::config::{Contract, TemplateType};
-use crate::project_context::{BuildConfig, Context};
+use crate::config::{ContainerRuntime, Contract, ContractVariant, TemplateType};
+use crate::generator::{CreateContract, TEMPLATES};
+use crate::native_toolchain::NativeToolchain;
+use crate::project_context::{BuildConfig, BuildEnv, Context};
 use crate::signal::Signal;
-use anyhow::Result;
+use crate::util::docker;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Reference of the extra C flags in Makefiles
+const EXTRA_CFLAGS: &str = "$(EXTRA_CFLAGS)";
 
 pub fn get_recipe(context: Context, template_type: TemplateType) -> Result<Box<dyn Recipe>> {
     match template_type {
         TemplateType::Rust => Ok(Box::new(rust::Rust::new(context))),
+        TemplateType::RustSharedLib => Ok(Box::new(rust::Rust::sharedlib(context))),
         TemplateType::C => Ok(Box::new(c::C::<c::CBin>::new(context))),
         TemplateType::CSharedLib => Ok(Box::new(c::C::<c::CSharedLib>::new(context))),
+        TemplateType::CClang => Ok(Box::new(c::C::<c::CClang>::new(context))),
+        TemplateType::CClangSharedLib => Ok(Box::new(c::C::<c::CClangSharedLib>::new(context))),
         TemplateType::Lua => Ok(Box::new(lua::Lua::<lua::LuaStandalone>::new(context))),
         TemplateType::LuaEmbedded => Ok(Box::new(lua::Lua::<lua::LuaEmbedded>::new(context))),
     }
 }
 
+/// Toolchain used to build contracts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Toolchain {
+    /// Compiler versions on the host, e.g. `rustc --version`
+    #[serde(default)]
+    pub versions: Vec<String>,
+    /// Build image
+    pub image: Option<String>,
+    /// Local image id of the build image
+    pub image_id: Option<String>,
+}
+
+impl Toolchain {
+    pub fn with_image(runtime: ContainerRuntime, image: Option<String>) -> Self {
+        let image_id = image
+            .as_deref()
+            .and_then(|image| docker::image_id(runtime, image));
+        Toolchain {
+            versions: Vec::new(),
+            image,
+            image_id,
+        }
+    }
+
+    /// Toolchain installed on the host
+    pub fn native(native: &NativeToolchain) -> Self {
+        Toolchain {
+            versions: vec![native.version.clone()],
+            image: None,
+            image_id: None,
+        }
+    }
+}
+
+/// Read a variable defined as `NAME := value` from a Makefile
+pub(crate) fn makefile_variable(makefile: &Path, name: &str) -> Option<String> {
+    let content = fs::read_to_string(makefile).ok()?;
+    content.lines().find_map(|line| {
+        let (key, value) = line.split_once(":=")?;
+        if key.trim() == name {
+            Some(value.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Check the Makefile passes `EXTRA_CFLAGS` to the compiler if the contract sets `cflags`,
+/// Makefiles of projects created before `cflags` was supported don't use it.
+pub(crate) fn check_extra_cflags(makefile: &Path, contract: &Contract) -> Result<()> {
+    if contract.build.cflags.is_empty() {
+        return Ok(());
+    }
+    let content = fs::read_to_string(makefile)?;
+    if !content.contains(EXTRA_CFLAGS) {
+        bail!(
+            "The `cflags` of contract '{}' can't be applied, {:?} doesn't use {}, \
+             please run `capsule upgrade` or append it to the CFLAGS",
+            contract.name,
+            makefile,
+            EXTRA_CFLAGS
+        );
+    }
+    Ok(())
+}
+
+/// Rewrite the rules of the contract in the content of the Makefile, the rules are removed
+/// if there is no new name. Return None if the rules can't be found.
+///
+/// The rules appended from the current template are matched as a whole. Otherwise, e.g. they
+/// were appended by an older template or edited, the rules mentioning the contract in their
+/// targets, prerequisites or recipes are rewritten.
+pub(crate) fn rewrite_makefile_rules(
+    content: &str,
+    template: &str,
+    name: &str,
+    new_name: Option<&str>,
+) -> Result<Option<String>> {
+    let render = |name: &str| {
+        TEMPLATES.render(
+            template,
+            &tera::Context::from_serialize(CreateContract {
+                name: name.to_string(),
+            })?,
+        )
+    };
+    let rules = render(name)?;
+    if content.contains(&rules) {
+        let new_rules = match new_name {
+            Some(new_name) => render(new_name)?,
+            None => String::new(),
+        };
+        return Ok(Some(content.replacen(&rules, &new_rules, 1)));
+    }
+    Ok(rewrite_rules_of(content, name, new_name))
+}
+
+/// Return true if the line of a Makefile is the header of a rule, e.g. `build/debug/a: src/a.c`
+fn is_rule_header(line: &str) -> bool {
+    if line.starts_with(char::is_whitespace) || line.starts_with('#') {
+        return false;
+    }
+    match line.split_once(':') {
+        Some((targets, rest)) => !targets.contains('=') && !rest.starts_with('='),
+        None => false,
+    }
+}
+
+/// Byte offsets of the name in the line where it's a path segment, e.g. `$(DIR)/a`, `src/a.c`
+fn name_segments(line: &str, name: &str) -> Vec<usize> {
+    let is_start = |c: char| c == '/' || c.is_whitespace() || c == '"' || c == '\'';
+    let is_end = |c: char| c == '.' || c == ':' || c.is_whitespace() || c == '"' || c == '\'';
+    line.match_indices(name)
+        .map(|(offset, _)| offset)
+        .filter(|offset| {
+            line[..*offset].chars().next_back().map_or(true, is_start)
+                && line[offset + name.len()..]
+                    .chars()
+                    .next()
+                    .map_or(true, is_end)
+        })
+        .collect()
+}
+
+/// Rewrite the rules mentioning the contract, a rule is the header and the recipe lines
+fn rewrite_rules_of(content: &str, name: &str, new_name: Option<&str>) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut rewritten: Vec<String> = Vec::new();
+    let mut found = false;
+    let mut index = 0;
+    while index < lines.len() {
+        let mut end = index + 1;
+        if is_rule_header(lines[index]) {
+            while end < lines.len() && lines[end].starts_with('\t') {
+                end += 1;
+            }
+        }
+        let rule = &lines[index..end];
+        if !is_rule_header(lines[index])
+            || rule.iter().all(|line| name_segments(line, name).is_empty())
+        {
+            rewritten.extend(rule.iter().map(|line| line.to_string()));
+            index = end;
+            continue;
+        }
+        found = true;
+        match new_name {
+            Some(new_name) => rewritten.extend(rule.iter().map(|line| {
+                let offsets = name_segments(line, name);
+                let mut line = line.to_string();
+                for offset in offsets.into_iter().rev() {
+                    line.replace_range(offset..offset + name.len(), new_name);
+                }
+                line
+            })),
+            // remove the blank line separating the rule from the next one
+            None if lines.get(end).map_or(false, |line| line.trim().is_empty()) => end += 1,
+            None => {}
+        }
+        index = end;
+    }
+    if !found {
+        return None;
+    }
+    let mut content = rewritten.join("\n");
+    content.push('\n');
+    Some(content)
+}
+
 pub trait Recipe {
     fn exists(&self, name: &str) -> bool;
     fn create_contract(
         &self,
         contract: &Contract,
+        variant: ContractVariant,
         rewrite_config: bool,
         signal: &Signal,
         env_file: String,
     ) -> Result<()>;
+    /// Set up the environment of the contract and register it to the build files,
+    /// without generating the sources, which come from an external template
+    fn prepare_contract(&self, contract: &Contract) -> Result<()>;
+    /// Remove the sources of the contract and unregister it from the build files
+    fn remove_contract(&self, contract: &Contract) -> Result<()>;
+    /// Move the sources of the contract and rewrite its entries in the build files
+    fn rename_contract(&self, contract: &Contract, new_name: &str) -> Result<()>;
     fn run(&self, contract: &Contract, build_cmd: String, signal: &Signal) -> Result<()>;
     fn run_build(
         &self,
@@ -35,4 +224,93 @@ pub trait Recipe {
         build_args_opt: Option<Vec<String>>,
     ) -> Result<()>;
     fn clean(&self, contracts: &[Contract], signal: &Signal) -> Result<()>;
+    /// Files and directories the contract binary is built from
+    fn source_paths(&self, contract: &Contract) -> Result<Vec<PathBuf>>;
+    /// Path of the contract binary copied to the build directory
+    fn output_path(&self, contract: &Contract, build_env: BuildEnv) -> PathBuf;
+    /// Toolchain the contract is built with
+    fn toolchain(&self, contract: &Contract) -> Result<Toolchain>;
+    /// `RUSTFLAGS` the contract is built with, the ambient ones and the ones of the contract
+    fn rustflags(&self, _contract: &Contract) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+    /// Build the prerequisites shared by the contracts of the recipe, it runs once before the
+    /// contracts are built, so concurrent builds don't race on them
+    fn prepare_build(&self, _signal: &Signal) -> Result<()> {
+        Ok(())
+    }
+    /// Return true if contracts of the recipe can't be built concurrently with each other
+    fn exclusive_build(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_makefile_rules() {
+        let template = "c/bin/contract/BUILD";
+        let makefile = |names: &[&str]| {
+            let mut content = "CC := gcc\n\nall:\n\techo all\n\n".to_string();
+            for name in names {
+                content.push_str(
+                    &TEMPLATES
+                        .render(
+                            template,
+                            &tera::Context::from_serialize(CreateContract {
+                                name: name.to_string(),
+                            })
+                            .unwrap(),
+                        )
+                        .unwrap(),
+                );
+            }
+            content
+        };
+        let content = makefile(&["lock", "lock-v2"]);
+        assert_eq!(
+            rewrite_makefile_rules(&content, template, "lock", Some("type")).unwrap(),
+            Some(makefile(&["type", "lock-v2"]))
+        );
+        assert_eq!(
+            rewrite_makefile_rules(&content, template, "lock", None).unwrap(),
+            Some(makefile(&["lock-v2"]))
+        );
+        assert_eq!(
+            rewrite_makefile_rules(&content, template, "unknown", None).unwrap(),
+            None
+        );
+
+        // rules of an older template are matched by the name
+        let content = "DIR := build\n\nbuild-linked-binary: src/lock.c\n\t$(CC) -o $(DIR)/lock $<\n\nbuild-linked-binary: src/lock-v2.c\n\t$(CC) -o $(DIR)/lock-v2 $<\n";
+        assert_eq!(
+            rewrite_makefile_rules(content, template, "lock", Some("type"))
+                .unwrap()
+                .as_deref(),
+            Some("DIR := build\n\nbuild-linked-binary: src/type.c\n\t$(CC) -o $(DIR)/type $<\n\nbuild-linked-binary: src/lock-v2.c\n\t$(CC) -o $(DIR)/lock-v2 $<\n")
+        );
+        assert_eq!(
+            rewrite_makefile_rules(content, template, "lock", None)
+                .unwrap()
+                .as_deref(),
+            Some("DIR := build\n\nbuild-linked-binary: src/lock-v2.c\n\t$(CC) -o $(DIR)/lock-v2 $<\n")
+        );
+    }
+
+    #[test]
+    fn test_check_extra_cflags() {
+        let makefile =
+            std::env::temp_dir().join(format!("capsule-extra-cflags-{}.mk", std::process::id()));
+        fs::write(&makefile, "CFLAGS := -O3 -g\n").unwrap();
+        let mut contract = Contract::new("lock".to_string(), TemplateType::C);
+        assert!(check_extra_cflags(&makefile, &contract).is_ok());
+        contract.build.cflags = vec!["-O2".to_string()];
+        let err = check_extra_cflags(&makefile, &contract).unwrap_err();
+        assert!(err.to_string().contains("capsule upgrade"), "{}", err);
+        fs::write(&makefile, "CFLAGS := -O3 -g $(EXTRA_CFLAGS)\n").unwrap();
+        assert!(check_extra_cflags(&makefile, &contract).is_ok());
+        fs::remove_file(makefile).unwrap();
+    }
 }
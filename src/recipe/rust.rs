@@ -1,83 +1,280 @@
-use crate::config::Contract;
-use crate::config_manipulate::{append_cargo_workspace_member, Document};
+use crate::config::{ContainerRuntime, Contract, ContractVariant, TemplateType};
+use crate::config_manipulate::{
+    append_cargo_workspace_member, remove_cargo_workspace_member, rename_cargo_workspace_member,
+    Document,
+};
 use crate::generator::{CreateContract, TEMPLATES};
 use crate::project_context::{
     read_config_file, write_config_file, BuildConfig, BuildEnv, Context, CARGO_CONFIG_FILE,
-    CONTRACTS_DIR,
+    CONTRACTS_DIR, TESTS_DIR,
 };
-use crate::recipe::Recipe;
+use crate::recipe::{Recipe, Toolchain};
 use crate::signal::Signal;
-use anyhow::{bail, Context as _, Result};
+use crate::util::cli;
+use anyhow::{anyhow, bail, Context as _, Result};
 use path_macro::path;
 use tera;
 use xshell::{cmd, Shell};
 
+use std::collections::BTreeMap;
 use std::env::VarError;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
 
 pub const DOCKER_IMAGE: &str = "thewawar/ckb-capsule:2022-08-01";
-const RUST_TARGET: &str = "riscv64imac-unknown-none-elf";
+pub const RUST_TARGET: &str = "riscv64imac-unknown-none-elf";
+const CROSS_CONFIG_FILE: &str = "Cross.toml";
+const CROSS_CONTAINER_ENGINE_ENV: &str = "CROSS_CONTAINER_ENGINE";
+/// Shared libraries are linked as position independent shared objects
+const SHAREDLIB_RUST_FLAGS: &str = "-C relocation-model=pic -C link-arg=-shared";
 
 pub struct Rust {
     context: Context,
+    sharedlib: bool,
 }
 
 impl Rust {
     pub fn new(context: Context) -> Self {
-        Self { context }
+        Self {
+            context,
+            sharedlib: false,
+        }
+    }
+
+    /// Recipe of shared libraries, which are loaded by other contracts with `ckb_dlopen`
+    pub fn sharedlib(context: Context) -> Self {
+        Self {
+            context,
+            sharedlib: true,
+        }
+    }
+
+    fn bin_name(&self, contract: &Contract) -> String {
+        if self.sharedlib {
+            format!("{}.so", contract.name)
+        } else {
+            contract.name.clone()
+        }
+    }
+
+    /// Directory of the contract crates of the workspace
+    fn contracts_dir(&self, workspace_dir: &Path) -> PathBuf {
+        if workspace_dir.starts_with(self.context.contracts_path()) {
+            workspace_dir.to_path_buf()
+        } else {
+            workspace_dir.join(CONTRACTS_DIR)
+        }
+    }
+
+    /// Directory of the contract crate
+    pub fn contract_path(&self, contract: &Contract) -> Result<PathBuf> {
+        let workspace_dir = self.context.contract_workspace_dir(contract)?;
+        Ok(self.contracts_dir(&workspace_dir).join(&contract.name))
+    }
+
+    /// The contract in capsule.toml, or a new contract in the default workspace
+    fn find_contract(&self, name: &str) -> Contract {
+        self.context
+            .config
+            .contracts
+            .iter()
+            .find(|c| c.name == name)
+            .cloned()
+            .unwrap_or_else(|| Contract::new(name.to_string(), TemplateType::Rust))
     }
 
-    fn contract_path(&self, name: &str) -> PathBuf {
-        let mut path = self.context.contracts_path();
-        path.push(name);
-        path
+    /// Path relative to the project dir, which is printed
+    fn relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.context.project_path)
+            .unwrap_or(path)
     }
 
-    fn rewrite_config_for_new_contract(&self, name: &str) -> Result<()> {
-        // rewrite config
+    /// Manifest of the workspace of the contract, which must exist before contracts are added
+    pub fn workspace_manifest(&self, contract: &Contract) -> Result<PathBuf> {
+        let workspace_dir = self.context.contract_workspace_dir(contract)?;
+        let path = workspace_dir.join(CARGO_CONFIG_FILE);
+        if !path.exists() {
+            bail!(
+                "can't find the workspace {:?}, please create it with a Cargo.toml first",
+                workspace_dir
+            );
+        }
+        Ok(path)
+    }
+
+    fn cross_config_path(&self, workspace_dir: &Path) -> PathBuf {
+        // cross reads Cross.toml from the workspace root
+        let path = workspace_dir.join(CROSS_CONFIG_FILE);
+        if path.exists() {
+            path
+        } else {
+            self.context.project_path.join(CROSS_CONFIG_FILE)
+        }
+    }
+
+    /// The image cross builds contracts of the workspace in
+    fn cross_image(&self, workspace_dir: &Path) -> Result<Option<String>> {
+        let path = self.cross_config_path(workspace_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let doc = read_config_file(&path)?.parse::<Document>()?;
+        let image = doc
+            .get("target")
+            .and_then(|target| target.get(RUST_TARGET))
+            .and_then(|target| target.get("image"))
+            .and_then(|image| image.as_str())
+            .map(|image| image.to_string());
+        Ok(image)
+    }
+
+    /// The pinned image in capsule.toml, or the image in Cross.toml of the workspace
+    fn build_image(&self, workspace_dir: &Path) -> Result<Option<String>> {
+        match &self.context.config.images.rust {
+            Some(image) => Ok(Some(image.clone())),
+            None => self.cross_image(workspace_dir),
+        }
+    }
+
+    /// Template of the variant, which overrides the file of the default contract template
+    fn variant_template(&self, variant: ContractVariant, file: &str) -> String {
+        let variant_path = format!("rust/contract/{}/{}", variant.name(), file);
+        if variant != ContractVariant::Default
+            && TEMPLATES
+                .get_template_names()
+                .any(|name| name == variant_path)
         {
-            println!("Rewrite Cargo.toml");
-            let mut cargo_path = self.context.workspace_dir()?;
-            let workspace_member = if Some(Some(CONTRACTS_DIR))
-                == self
-                    .context
-                    .config
-                    .rust
-                    .workspace_dir
-                    .as_ref()
-                    .map(|dir| dir.to_str())
-            {
-                name.to_string()
-            } else {
-                format!("{}/{}", CONTRACTS_DIR, name)
-            };
-            cargo_path.push(CARGO_CONFIG_FILE);
-            let config_content = read_config_file(&cargo_path)?;
-            let mut doc = config_content.parse::<Document>()?;
-            append_cargo_workspace_member(&mut doc, workspace_member)?;
-            write_config_file(&cargo_path, doc.to_string())?;
+            variant_path
+        } else {
+            format!("rust/contract/{}", file)
         }
+    }
+
+    /// The shared library called by the loader variant, which is tested with it
+    fn find_library(&self) -> Result<String> {
+        self.context
+            .config
+            .contracts
+            .iter()
+            .find(|c| c.template_type.is_sharedlib())
+            .map(|c| c.name.clone())
+            .ok_or_else(|| {
+                anyhow!("can't find a shared library in capsule.toml, please create one with `capsule new-contract <name> --template rust-sharedlib` first")
+            })
+    }
+
+    /// Add the tests rendered from the template to the tests crate
+    fn create_tests(
+        &self,
+        contract: &Contract,
+        template: &str,
+        library: Option<String>,
+    ) -> Result<()> {
+        let name = &contract.name;
+        let tests_path = self.context.contract_tests_dir(contract)?;
+        let lib_path = tests_path.join("src/lib.rs");
+        if !lib_path.exists() {
+            println!("warn: can't find {:?}, skip generating tests", lib_path);
+            return Ok(());
+        }
+        let module = format!("{}_tests", name.replace('-', "_"));
+        let mut context = tera::Context::from_serialize(CreateContract {
+            name: name.to_string(),
+        })?;
+        if let Some(library) = library {
+            context.insert("library", &library);
+        }
+        let content = TEMPLATES.render(template, &context)?;
+        fs::write(tests_path.join(format!("src/{}.rs", module)), content)?;
+        let mut lib = fs::read_to_string(&lib_path)?;
+        if !lib.ends_with('\n') {
+            lib.push('\n');
+        }
+        lib.push_str(&format!("\n#[cfg(test)]\nmod {};\n", module));
+        fs::write(&lib_path, lib)?;
+        println!("Created tests {}/src/{}.rs", TESTS_DIR, module);
         Ok(())
     }
+
+    /// Path of the contract in the members of its workspace
+    fn workspace_member(&self, contract: &Contract) -> Result<String> {
+        let workspace_dir = self.context.contract_workspace_dir(contract)?;
+        let contracts_dir = self.contracts_dir(&workspace_dir);
+        let relative_dir = contracts_dir
+            .strip_prefix(&workspace_dir)
+            .unwrap_or_else(|_| Path::new(CONTRACTS_DIR));
+        let member = relative_dir
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .chain(std::iter::once(contract.name.clone()))
+            .collect::<Vec<_>>()
+            .join("/");
+        Ok(member)
+    }
+
+    /// Read, modify and write back the Cargo.toml of the workspace
+    fn rewrite_workspace_config(
+        &self,
+        contract: &Contract,
+        f: impl FnOnce(&mut Document) -> Result<()>,
+    ) -> Result<()> {
+        let cargo_path = self.workspace_manifest(contract)?;
+        println!("Rewrite {}", self.relative_path(&cargo_path).display());
+        let config_content = read_config_file(&cargo_path)?;
+        let mut doc = config_content.parse::<Document>()?;
+        f(&mut doc)?;
+        write_config_file(&cargo_path, doc.to_string())?;
+        Ok(())
+    }
+
+    /// Add a stub of the tests of an imported contract, which runs it as a lock script
+    pub fn create_tests_stub(&self, contract: &Contract) -> Result<()> {
+        self.create_tests(contract, "rust/tests/import.rs", None)
+    }
+
+    fn rewrite_config_for_new_contract(&self, contract: &Contract) -> Result<()> {
+        let workspace_member = self.workspace_member(contract)?;
+        self.rewrite_workspace_config(contract, |doc| {
+            append_cargo_workspace_member(doc, workspace_member)
+        })
+    }
 }
 
 impl Recipe for Rust {
     fn exists(&self, name: &str) -> bool {
-        self.contract_path(name).exists()
+        self.contract_path(&self.find_contract(name))
+            .map(|path| path.exists())
+            .unwrap_or(false)
     }
 
     fn create_contract(
         &self,
         contract: &Contract,
+        variant: ContractVariant,
         rewrite_config: bool,
         _signal: &Signal,
         _docker_env_file: String,
     ) -> Result<()> {
         let name = &contract.name;
+        if self.sharedlib && variant != ContractVariant::Default {
+            bail!(
+                "the {} variant is not supported by shared libraries",
+                variant.name()
+            );
+        }
+        let library = match variant {
+            ContractVariant::SharedLibLoader => Some(self.find_library()?),
+            _ => None,
+        };
+        let workspace_dir = self.context.contract_workspace_dir(contract)?;
+        if rewrite_config {
+            self.workspace_manifest(contract)?;
+        }
         println!("New contract {:?}", &name);
-        let path = self.context.contracts_path();
-        let context = tera::Context::from_serialize(&CreateContract { name: name.clone() })?;
+        let path = self.contracts_dir(&workspace_dir);
+        fs::create_dir_all(&path)?;
+        let context = tera::Context::from_serialize(CreateContract { name: name.clone() })?;
         // generate contract
         let mut cmd = std::process::Command::new("cargo");
         let output = cmd
@@ -91,25 +288,95 @@ impl Recipe for Rust {
         contract_path.push(path);
         contract_path.push(name);
         // initialize contract code
-        for (f, template_name) in &[
-            ("src/main.rs", None),
-            ("src/error.rs", None),
-            ("src/entry.rs", None),
-            ("Cargo.toml", Some("Cargo-manifest.toml")),
-        ] {
-            let template_path = format!("rust/contract/{}", template_name.unwrap_or(f));
+        let files: &[(&str, Option<&str>)] = if self.sharedlib {
+            &[
+                ("src/main.rs", None),
+                ("Cargo.toml", Some("Cargo-manifest.toml")),
+            ]
+        } else {
+            &[
+                ("src/main.rs", None),
+                ("src/error.rs", None),
+                ("src/entry.rs", None),
+                ("Cargo.toml", Some("Cargo-manifest.toml")),
+            ]
+        };
+        for (f, template_name) in files {
+            let template_path = if self.sharedlib {
+                format!("rust/sharedlib/{}", template_name.unwrap_or(f))
+            } else {
+                self.variant_template(variant, template_name.unwrap_or(f))
+            };
             let content = TEMPLATES.render(&template_path, &context)?;
             let mut file_path = contract_path.clone();
             file_path.push(f);
             fs::write(file_path, content)?;
         }
 
+        if variant != ContractVariant::Default {
+            let template = format!("rust/tests/variants/{}.rs", variant.name());
+            self.create_tests(contract, &template, library)?;
+        }
         if rewrite_config {
-            self.rewrite_config_for_new_contract(&contract.name)?;
+            self.rewrite_config_for_new_contract(contract)?;
         }
         Ok(())
     }
 
+    fn prepare_contract(&self, contract: &Contract) -> Result<()> {
+        self.rewrite_config_for_new_contract(contract)
+    }
+
+    fn remove_contract(&self, contract: &Contract) -> Result<()> {
+        let member = self.workspace_member(contract)?;
+        self.rewrite_workspace_config(contract, |doc| {
+            if !remove_cargo_workspace_member(doc, &member)? {
+                println!("warn: can't find {:?} in the workspace members", member);
+            }
+            Ok(())
+        })?;
+        let contract_path = self.contract_path(contract)?;
+        if contract_path.exists() {
+            fs::remove_dir_all(&contract_path)?;
+            println!("Removed {}", self.relative_path(&contract_path).display());
+        }
+        Ok(())
+    }
+
+    fn rename_contract(&self, contract: &Contract, new_name: &str) -> Result<()> {
+        let mut renamed = contract.clone();
+        renamed.name = new_name.to_string();
+        let contract_path = self.contract_path(contract)?;
+        let new_contract_path = self.contract_path(&renamed)?;
+        fs::rename(&contract_path, &new_contract_path)
+            .with_context(|| format!("failed to move {:?}", contract_path))?;
+        println!(
+            "Moved {} to {}",
+            self.relative_path(&contract_path).display(),
+            self.relative_path(&new_contract_path).display()
+        );
+        // the package name is the name of the binary
+        let manifest_path = new_contract_path.join(CARGO_CONFIG_FILE);
+        let mut doc = read_config_file(&manifest_path)?.parse::<Document>()?;
+        if let Some(package) = doc
+            .get_mut("package")
+            .and_then(|package| package.as_table_like_mut())
+        {
+            if package.get("name").and_then(|name| name.as_str()) == Some(contract.name.as_str()) {
+                package.insert("name", toml_edit::value(new_name));
+                write_config_file(&manifest_path, doc.to_string())?;
+            }
+        }
+        let member = self.workspace_member(contract)?;
+        let new_member = self.workspace_member(&renamed)?;
+        self.rewrite_workspace_config(contract, |doc| {
+            if !rename_cargo_workspace_member(doc, &member, &new_member)? {
+                println!("warn: can't find {:?} in the workspace members", member);
+            }
+            Ok(())
+        })
+    }
+
     /// run command in build image
     fn run(&self, _contract: &Contract, _build_cmd: String, _signal: &Signal) -> Result<()> {
         bail!("run command is no longer supported for rust contracts, just use cargo or cross directly")
@@ -120,34 +387,37 @@ impl Recipe for Rust {
         &self,
         contract: &Contract,
         config: BuildConfig,
-        _signal: &Signal,
+        signal: &Signal,
         build_args_opt: Option<Vec<String>>,
     ) -> Result<()> {
+        let settings = &contract.build;
+        let target = settings.target.as_deref().unwrap_or(RUST_TARGET);
         // docker cargo build
-        let (debug_or_release, build_cmd_opt) = match config.build_env {
-            BuildEnv::Debug => ("debug", None),
-            BuildEnv::Release => ("release", Some("--release")),
+        let (debug_or_release, build_cmd_opt) = match (config.build_env, &settings.profile) {
+            (BuildEnv::Debug, _) => ("debug", Vec::new()),
+            (BuildEnv::Release, None) => ("release", vec!["--release".to_string()]),
+            (BuildEnv::Release, Some(profile)) => (
+                profile.as_str(),
+                vec!["--profile".to_string(), profile.clone()],
+            ),
         };
-        let bin_path = path!("target" / RUST_TARGET / debug_or_release / &contract.name);
+        let bin_path = path!("target" / target / debug_or_release / &contract.name);
+        let sharedlib_rust_flags = self.sharedlib.then(|| SHAREDLIB_RUST_FLAGS.to_string());
 
         let sh = Shell::new()?;
-        sh.change_dir(self.context.workspace_dir()?);
+        sh.change_dir(self.context.contract_workspace_dir(contract)?);
 
         // TODO: support host network.
         if self.context.use_docker_host {
-            eprintln!("warn: host network is not supported in cross yet; as an alternative, run `cargo fetch` first");
+            self.context.output.println("warn: host network is not supported in cross yet; as an alternative, run `cargo fetch` first");
         }
-        let orig_rust_flags = match std::env::var("RUSTFLAGS") {
-            Ok(f) => Some(f),
-            Err(VarError::NotPresent) => None,
-            Err(e) => bail!(e),
-        };
+        let orig_rust_flags = ambient_rustflags()?;
         let debug_assertions_rust_flag = if config.always_debug {
             Some("--cfg debug_assertions".to_string())
         } else {
             None
         };
-        let remap_rust_flags = if debug_or_release == "release" && config.remap {
+        let remap_rust_flags = if config.build_env == BuildEnv::Release && config.remap {
             // XXX: windows?
             let home = std::env::var("HOME").context("reading environment variable HOME")?;
             let pwd = sh.current_dir();
@@ -164,29 +434,65 @@ impl Recipe for Rust {
             orig_rust_flags,
             debug_assertions_rust_flag,
             remap_rust_flags,
+            sharedlib_rust_flags,
         ]
         .into_iter()
         .flatten()
+        .chain(settings.rustflags.iter().cloned())
         .collect::<Vec<_>>()
         .join(" ");
 
         let _env_guard = if !rust_flags.is_empty() {
-            println!("RUSTFLAGS={}", rust_flags);
+            self.context
+                .output
+                .println(&format!("RUSTFLAGS={}", rust_flags));
             Some(sh.push_env("RUSTFLAGS", rust_flags))
         } else {
             None
         };
 
         let pkg = &contract.name;
-        let args = build_args_opt.into_iter().flatten();
-        cmd!(sh, "cross build -p {pkg} {build_cmd_opt...} {args...}").run()?;
+        // the default target is set in Cross.toml
+        let target_opt = settings
+            .target
+            .as_ref()
+            .map(|target| vec!["--target".to_string(), target.clone()])
+            .unwrap_or_default();
+        let features = if settings.features.is_empty() {
+            Vec::new()
+        } else {
+            vec!["--features".to_string(), settings.features.join(",")]
+        };
+        let args = settings
+            .args
+            .iter()
+            .cloned()
+            .chain(build_args_opt.into_iter().flatten());
+        let mut build_cmd = cmd!(
+            sh,
+            "cross build -p {pkg} {target_opt...} {build_cmd_opt...} {features...} {args...}"
+        )
+        .envs(&settings.env);
+        if let Some(image) = &self.context.config.images.rust {
+            // cross reads the image of the target from the environment first
+            let image_env = format!(
+                "CROSS_TARGET_{}_IMAGE",
+                target.to_uppercase().replace('-', "_")
+            );
+            build_cmd = build_cmd.env(image_env, image);
+        }
+        let runtime = self.context.config.container_runtime;
+        if runtime != ContainerRuntime::Docker {
+            build_cmd = build_cmd.env(CROSS_CONTAINER_ENGINE_ENV, runtime.program());
+        }
+        self.context.output.println(&format!("$ {}", build_cmd));
+        cli::run_command(build_cmd.into(), signal, &self.context.output)?;
 
         // copy to build dir
-        let mut target_path = self.context.contracts_build_path(config.build_env);
+        let target_path = self.output_path(contract, config.build_env);
         // make sure the dir is exist
-        sh.create_dir(&target_path)?;
-        target_path.push(&contract.name);
-        println!("Copying to target directory");
+        sh.create_dir(self.context.contracts_build_path(config.build_env))?;
+        self.context.output.println("Copying to target directory");
         sh.copy_file(bin_path, target_path)?;
         Ok(())
     }
@@ -194,14 +500,273 @@ impl Recipe for Rust {
     /// clean contract
     fn clean(&self, contracts: &[Contract], _signal: &Signal) -> Result<()> {
         let sh = Shell::new()?;
-        // Do we want `cargo clean -p contract1 -p contract2 ...`?
-        cmd!(sh, "cargo clean").run()?;
+        let mut workspace_dirs = Vec::new();
+        for c in contracts {
+            let workspace_dir = self.context.contract_workspace_dir(c)?;
+            if !workspace_dirs.contains(&workspace_dir) {
+                workspace_dirs.push(workspace_dir);
+            }
+        }
+        for workspace_dir in workspace_dirs {
+            sh.change_dir(workspace_dir);
+            // Do we want `cargo clean -p contract1 -p contract2 ...`?
+            cmd!(sh, "cargo clean").run()?;
+        }
         let build_dir = self.context.contracts_build_dir();
         for c in contracts {
-            sh.remove_path(path!(build_dir / "debug" / &c.name))?;
-            sh.remove_path(path!(build_dir / "release" / &c.name))?;
+            let output_name = c.output_name(self.bin_name(c));
+            sh.remove_path(path!(build_dir / "debug" / &output_name))?;
+            sh.remove_path(path!(build_dir / "release" / &output_name))?;
         }
 
         Ok(())
     }
+
+    fn source_paths(&self, contract: &Contract) -> Result<Vec<PathBuf>> {
+        let workspace_dir = self.context.contract_workspace_dir(contract)?;
+        let contract_path = self.contract_path(contract)?;
+        let mut paths = vec![
+            contract_path.clone(),
+            workspace_dir.join(CARGO_CONFIG_FILE),
+            workspace_dir.join("Cargo.lock"),
+            self.context.project_path.join("rust-toolchain"),
+            self.cross_config_path(&workspace_dir),
+        ];
+        let workspace_deps = workspace_path_dependencies(&workspace_dir)?;
+        path_dependencies(&contract_path, &workspace_deps, &mut paths)?;
+        Ok(paths)
+    }
+
+    fn output_path(&self, contract: &Contract, build_env: BuildEnv) -> PathBuf {
+        self.context
+            .contracts_build_path(build_env)
+            .join(contract.output_name(self.bin_name(contract)))
+    }
+
+    fn toolchain(&self, contract: &Contract) -> Result<Toolchain> {
+        let workspace_dir = self.context.contract_workspace_dir(contract)?;
+        let mut toolchain = Toolchain::with_image(
+            self.context.config.container_runtime,
+            self.build_image(&workspace_dir)?,
+        );
+        for program in ["rustc", "cross"] {
+            let output = match Command::new(program)
+                .arg("--version")
+                .current_dir(&workspace_dir)
+                .output()
+            {
+                Ok(output) if output.status.success() => output,
+                _ => continue,
+            };
+            if let Some(version) = String::from_utf8_lossy(&output.stdout).lines().next() {
+                toolchain.versions.push(version.trim().to_string());
+            }
+        }
+        Ok(toolchain)
+    }
+
+    fn rustflags(&self, contract: &Contract) -> Result<Vec<String>> {
+        Ok(ambient_rustflags()?
+            .iter()
+            .flat_map(|flags| flags.split_whitespace())
+            .map(str::to_string)
+            .chain(contract.build.rustflags.iter().cloned())
+            .collect())
+    }
+}
+
+/// `RUSTFLAGS` of the environment capsule runs in, which are passed to the build
+fn ambient_rustflags() -> Result<Option<String>> {
+    match std::env::var("RUSTFLAGS") {
+        Ok(f) => Ok(Some(f)),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(e) => bail!(e),
+    }
+}
+
+/// Paths of the `path` dependencies in `[workspace.dependencies]`
+fn workspace_path_dependencies(workspace_dir: &Path) -> Result<BTreeMap<String, PathBuf>> {
+    let manifest_path = workspace_dir.join(CARGO_CONFIG_FILE);
+    let mut deps = BTreeMap::new();
+    if !manifest_path.exists() {
+        return Ok(deps);
+    }
+    let doc = read_config_file(&manifest_path)?.parse::<Document>()?;
+    if let Some(table) = doc
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"))
+        .and_then(|deps| deps.as_table_like())
+    {
+        for (name, dep) in table.iter() {
+            if let Some(path) = dep.get("path").and_then(|path| path.as_str()) {
+                deps.insert(name.to_string(), normalize_path(&workspace_dir.join(path)));
+            }
+        }
+    }
+    Ok(deps)
+}
+
+/// Collect the local crates the crate depends on through `path` dependencies, recursively,
+/// dependencies inherited from the workspace are looked up in `workspace_deps`.
+fn path_dependencies(
+    crate_dir: &Path,
+    workspace_deps: &BTreeMap<String, PathBuf>,
+    paths: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let manifest_path = crate_dir.join(CARGO_CONFIG_FILE);
+    if !manifest_path.exists() {
+        return Ok(());
+    }
+    let doc = read_config_file(&manifest_path)?.parse::<Document>()?;
+    // dev-dependencies are not linked into the contract
+    let mut tables = vec![doc.get("dependencies"), doc.get("build-dependencies")];
+    if let Some(targets) = doc
+        .get("target")
+        .and_then(|targets| targets.as_table_like())
+    {
+        for (_, target) in targets.iter() {
+            tables.push(target.get("dependencies"));
+            tables.push(target.get("build-dependencies"));
+        }
+    }
+    for (name, dep) in tables
+        .into_iter()
+        .flatten()
+        .filter_map(|table| table.as_table_like())
+        .flat_map(|table| table.iter())
+    {
+        let path = match dep.get("path").and_then(|path| path.as_str()) {
+            Some(path) => Some(normalize_path(&crate_dir.join(path))),
+            None if dep.get("workspace").and_then(|w| w.as_bool()) == Some(true) => {
+                workspace_deps.get(name).cloned()
+            }
+            None => None,
+        };
+        if let Some(path) = path {
+            if !paths.contains(&path) {
+                paths.push(path.clone());
+                path_dependencies(&path, workspace_deps, paths)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove `.` and `..` from the path without touching the file system
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(config: &str) -> Rust {
+        let config = toml_edit::de::from_str(config).unwrap();
+        Rust::new(Context::from_config("/project", config).unwrap())
+    }
+
+    #[test]
+    fn test_workspace_member() {
+        let mut contract = Contract::new("lock".to_string(), TemplateType::Rust);
+        for (workspace_dir, member, path) in [
+            (".", "contracts/lock", "/project/contracts/lock"),
+            ("contracts", "lock", "/project/contracts/lock"),
+            ("contracts/rust", "lock", "/project/contracts/rust/lock"),
+            (
+                "onchain",
+                "contracts/lock",
+                "/project/onchain/contracts/lock",
+            ),
+        ] {
+            let rust = recipe(&format!("[rust]\nworkspace_dir = \"{}\"\n", workspace_dir));
+            assert_eq!(rust.workspace_member(&contract).unwrap(), member);
+            assert_eq!(rust.contract_path(&contract).unwrap(), Path::new(path));
+        }
+        let rust = recipe("[rust]\nworkspace_dir = \"onchain\"\n");
+        contract.workspace = Some(PathBuf::from("libs"));
+        assert_eq!(
+            rust.contract_path(&contract).unwrap(),
+            Path::new("/project/libs/contracts/lock")
+        );
+        assert!(recipe("[rust]\nworkspace_dir = \"/onchain\"\n")
+            .workspace_member(&Contract::new("lock".to_string(), TemplateType::Rust))
+            .is_err());
+    }
+
+    #[test]
+    fn test_path_dependencies() {
+        let project_path =
+            std::env::temp_dir().join(format!("capsule-path-deps-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&project_path);
+        let write = |path: &str, content: &str| {
+            let path = project_path.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write(
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"contracts/lock\"]\n\n[workspace.dependencies]\nutils = { path = \"libs/utils\" }\n",
+        );
+        write(
+            "contracts/lock/Cargo.toml",
+            "[package]\nname = \"lock\"\n\n[dependencies]\ncommon = { path = \"../../libs/common\" }\nutils = { workspace = true }\nckb-std = \"0.15\"\n\n[dev-dependencies]\ntest-utils = { path = \"../../libs/test-utils\" }\n",
+        );
+        write(
+            "libs/common/Cargo.toml",
+            "[package]\nname = \"common\"\n\n[target.'cfg(target_arch = \"riscv64\")'.dependencies]\nhash = { path = \"../hash\" }\n",
+        );
+        write(
+            "libs/hash/Cargo.toml",
+            "[package]\nname = \"hash\"\n\n[dependencies]\ncommon = { path = \"../common\" }\n",
+        );
+
+        let config = toml_edit::de::from_str("").unwrap();
+        let rust = Rust::new(Context::from_config(&project_path, config).unwrap());
+        let contract = Contract::new("lock".to_string(), TemplateType::Rust);
+        let paths = rust.source_paths(&contract).unwrap();
+        for dep in ["libs/common", "libs/utils", "libs/hash"] {
+            assert!(paths.contains(&project_path.join(dep)), "{:?}", paths);
+        }
+        assert!(!paths.contains(&project_path.join("libs/test-utils")));
+        fs::remove_dir_all(project_path).unwrap();
+    }
+
+    #[test]
+    fn test_cross_image_of_workspace() {
+        let project_path =
+            std::env::temp_dir().join(format!("capsule-cross-image-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&project_path);
+        let cross_config =
+            |image: &str| format!("[target.{}]\nimage = \"{}\"\n", RUST_TARGET, image);
+        fs::create_dir_all(project_path.join("libs")).unwrap();
+        fs::write(
+            project_path.join(CROSS_CONFIG_FILE),
+            cross_config("example/default:1"),
+        )
+        .unwrap();
+        fs::write(
+            project_path.join("libs").join(CROSS_CONFIG_FILE),
+            cross_config("example/libs:1"),
+        )
+        .unwrap();
+        let config = toml_edit::de::from_str("").unwrap();
+        let rust = Rust::new(Context::from_config(&project_path, config).unwrap());
+        let mut contract = Contract::new("lock".to_string(), TemplateType::Rust);
+        let image = |contract: &Contract| rust.toolchain(contract).unwrap().image;
+        assert_eq!(image(&contract).as_deref(), Some("example/default:1"));
+        contract.workspace = Some(PathBuf::from("libs"));
+        assert_eq!(image(&contract).as_deref(), Some("example/libs:1"));
+        fs::remove_dir_all(&project_path).unwrap();
+    }
 }
@@ -19,6 +19,14 @@ impl Signal {
         Signal { running }
     }
 
+    /// Signal not bound to Ctrl-C, the handler can only be set once per process
+    #[cfg(test)]
+    pub fn detached() -> Self {
+        Signal {
+            running: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
     pub fn is_running(&self) -> bool {
         self.running.load(Ordering::SeqCst)
     }
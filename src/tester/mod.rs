@@ -0,0 +1,421 @@
+pub mod report;
+
+use crate::project_context::{BuildEnv, Context, CARGO_CONFIG_FILE, TESTS_DIR};
+use anyhow::{anyhow, bail, Result};
+use ckb_testtool::report::{read_outcomes, TxOutcome, REPORT_FILE_ENV_VAR};
+use report::{ReportFormat, TestEvent, TestReport};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use xshell::{cmd, Shell};
+
+const TEST_ENV_VAR: &str = "CAPSULE_TEST_ENV";
+const REPORT_DIR: &str = ".tmp";
+
+pub struct Tester;
+
+impl Tester {
+    pub fn run(project_context: &Context, env: BuildEnv, test_name: Option<&str>) -> Result<()> {
+        Self::run_tests(project_context, env, test_name, None, None)
+    }
+
+    /// Run the tests against both debug and release contracts,
+    /// and compare the outcome of each verified transaction.
+    pub fn run_differential(project_context: &Context, test_name: Option<&str>) -> Result<()> {
+        let report_dir = project_context.project_path.join(REPORT_DIR);
+        fs::create_dir_all(&report_dir)?;
+        let mut reports = Vec::new();
+        for env in [BuildEnv::Debug, BuildEnv::Release] {
+            let report_path = report_dir.join(format!("test-report-{}.jsonl", env_arg(env)));
+            if report_path.exists() {
+                fs::remove_file(&report_path)?;
+            }
+            let passed =
+                Self::run_tests(project_context, env, test_name, Some(&report_path), None).is_ok();
+            let outcomes = if report_path.exists() {
+                read_outcomes(&report_path)?
+            } else {
+                Vec::new()
+            };
+            reports.push((passed, outcomes));
+        }
+        let (release_passed, release_outcomes) = reports.pop().expect("release report");
+        let (debug_passed, debug_outcomes) = reports.pop().expect("debug report");
+
+        println!("------------------------------");
+        let divergences = compare_outcomes(&debug_outcomes, &release_outcomes);
+        for divergence in &divergences {
+            println!("{}", divergence);
+        }
+        if debug_passed != release_passed {
+            println!(
+                "tests {} in debug but {} in release",
+                if debug_passed { "passed" } else { "failed" },
+                if release_passed { "passed" } else { "failed" },
+            );
+        }
+        println!("------------------------------");
+        if !divergences.is_empty() || debug_passed != release_passed {
+            bail!(
+                "found {} divergent transactions between debug and release contracts",
+                divergences.len()
+            );
+        }
+        if !debug_passed {
+            bail!("tests failed in both debug and release");
+        }
+        println!(
+            "No divergence in {} transactions between debug and release contracts",
+            debug_outcomes.len()
+        );
+        Ok(())
+    }
+
+    /// Run the tests and write per-test status, duration and
+    /// per-transaction outcomes to `report_path`.
+    pub fn run_with_output_report(
+        project_context: &Context,
+        env: BuildEnv,
+        test_name: Option<&str>,
+        format: ReportFormat,
+        report_path: &Path,
+    ) -> Result<()> {
+        let env_arg = env_arg(env);
+        let report_dir = project_context.project_path.join(REPORT_DIR);
+        fs::create_dir_all(&report_dir)?;
+        let tx_report_path = report_dir.join(format!("test-report-{}.jsonl", env_arg));
+        if tx_report_path.exists() {
+            fs::remove_file(&tx_report_path)?;
+        }
+        let mut report = TestReport::new(env_arg);
+        let result = Self::run_tests(
+            project_context,
+            env,
+            test_name,
+            Some(&tx_report_path),
+            Some(&mut report),
+        );
+        if tx_report_path.exists() {
+            report.add_transactions(read_outcomes(&tx_report_path)?);
+        }
+        if let Some(dir) = report_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(report_path, report.render(format)?)?;
+        println!(
+            "test result: {} passed; {} failed; {} ignored",
+            report.passed, report.failed, report.ignored
+        );
+        println!("Write test report to {:?}", report_path);
+        result
+    }
+
+    /// Run the tests crate of each workspace, the test events are collected into `report`
+    /// if it's given. The tests of the other workspaces are run even if some fail.
+    fn run_tests(
+        project_context: &Context,
+        env: BuildEnv,
+        test_name: Option<&str>,
+        tx_report_path: Option<&Path>,
+        mut report: Option<&mut TestReport>,
+    ) -> Result<()> {
+        let env_arg = env_arg(env);
+        println!("{TEST_ENV_VAR}={env_arg}");
+        let mut result = Ok(());
+        for test_dir in test_dirs(project_context)? {
+            let test_run = TestRun {
+                env_arg,
+                test_name,
+                tx_report_path,
+            };
+            let dir_result = match report.as_deref_mut() {
+                Some(report) => test_run.run_json(&test_dir, report),
+                None => test_run.run(&test_dir),
+            };
+            if let Err(err) = dir_result {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Settings of a test run, shared by the tests crates of all workspaces
+struct TestRun<'a> {
+    env_arg: &'a str,
+    test_name: Option<&'a str>,
+    tx_report_path: Option<&'a Path>,
+}
+
+impl<'a> TestRun<'a> {
+    fn envs(&self) -> Vec<(&'static str, &'a OsStr)> {
+        let mut envs = vec![(TEST_ENV_VAR, OsStr::new(self.env_arg))];
+        if let Some(path) = self.tx_report_path {
+            envs.push((REPORT_FILE_ENV_VAR, path.as_os_str()));
+        }
+        envs
+    }
+
+    /// Run `cargo test` with the output of the tests printed
+    fn run(&self, test_dir: &Path) -> Result<()> {
+        let sh = Shell::new()?;
+        sh.change_dir(test_dir);
+        let test_name = self.test_name;
+        let mut cmd = cmd!(sh, "cargo test {test_name...} -- --nocapture");
+        for (key, val) in self.envs() {
+            cmd = cmd.env(key, val);
+        }
+        cmd.run()?;
+        Ok(())
+    }
+
+    /// Build the tests and run the test binaries with the JSON output of libtest.
+    ///
+    /// The JSON output is unstable, it's allowed on stable toolchains by `RUSTC_BOOTSTRAP`,
+    /// which is only set on the test binaries: set on cargo, it would leak into the
+    /// compilation, change the code paths of crates detecting nightly and invalidate
+    /// the build cache.
+    fn run_json(&self, test_dir: &Path, report: &mut TestReport) -> Result<()> {
+        let output = Command::new("cargo")
+            .args(["test", "--no-run", "--message-format=json"])
+            .current_dir(test_dir)
+            .stderr(Stdio::inherit())
+            .output()?;
+        if !output.status.success() {
+            bail!("failed to build the tests in {:?}", test_dir);
+        }
+        let mut result = Ok(());
+        for artifact in test_artifacts(&output.stdout) {
+            let package_dir = artifact
+                .manifest_path
+                .parent()
+                .unwrap_or(test_dir)
+                .to_path_buf();
+            let mut child = Command::new(&artifact.executable)
+                .args(self.test_name)
+                .args([
+                    "-Z",
+                    "unstable-options",
+                    "--format",
+                    "json",
+                    "--report-time",
+                ])
+                .envs(self.envs())
+                .env("RUSTC_BOOTSTRAP", "1")
+                // set by `cargo test`, the loader of ckb-testtool locates the project with it
+                .env("CARGO_MANIFEST_DIR", &package_dir)
+                .current_dir(&package_dir)
+                .stdout(Stdio::piped())
+                .spawn()?;
+            let stdout = child.stdout.take().expect("piped stdout");
+            read_test_events(stdout, report)?;
+            if !child.wait()?.success() && result.is_ok() {
+                result = Err(anyhow!("tests in {:?} failed", test_dir));
+            }
+        }
+        result
+    }
+}
+
+/// A line of `cargo test --no-run --message-format=json`
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    manifest_path: Option<PathBuf>,
+    executable: Option<PathBuf>,
+    profile: Option<CargoProfile>,
+}
+
+#[derive(Deserialize)]
+struct CargoProfile {
+    test: bool,
+}
+
+struct TestArtifact {
+    manifest_path: PathBuf,
+    executable: PathBuf,
+}
+
+/// Test binaries built by cargo
+fn test_artifacts(messages: &[u8]) -> Vec<TestArtifact> {
+    messages
+        .split(|b| *b == b'\n')
+        .filter_map(|line| serde_json::from_slice::<CargoMessage>(line).ok())
+        .filter(|msg| {
+            msg.reason == "compiler-artifact" && msg.profile.as_ref().map_or(false, |p| p.test)
+        })
+        .filter_map(|msg| {
+            Some(TestArtifact {
+                manifest_path: msg.manifest_path?,
+                executable: msg.executable?,
+            })
+        })
+        .collect()
+}
+
+/// Print the progress of the tests and collect the events into the report
+fn read_test_events(output: impl Read, report: &mut TestReport) -> Result<()> {
+    for line in BufReader::new(output).lines() {
+        let line = line?;
+        let event: TestEvent = match serde_json::from_str(&line) {
+            Ok(event) => event,
+            Err(_) => {
+                println!("{}", line);
+                continue;
+            }
+        };
+        if let TestEvent::Test {
+            event,
+            name,
+            stdout,
+            ..
+        } = &event
+        {
+            if event != "started" {
+                println!("test {} ... {}", name, event);
+            }
+            if let Some(stdout) = stdout {
+                print!("{}", stdout);
+            }
+        }
+        report.add_event(event);
+    }
+    Ok(())
+}
+
+/// Tests crates of the Rust workspaces, the one of the default workspace is run
+/// even if it's missing to report the error
+fn test_dirs(project_context: &Context) -> Result<Vec<PathBuf>> {
+    let workspace_dirs = project_context.workspace_dirs()?;
+    let mut test_dirs: Vec<PathBuf> = workspace_dirs
+        .iter()
+        .map(|dir| dir.join(TESTS_DIR))
+        .filter(|dir| dir.join(CARGO_CONFIG_FILE).exists())
+        .collect();
+    if test_dirs.is_empty() {
+        test_dirs.push(workspace_dirs[0].join(TESTS_DIR));
+    }
+    Ok(test_dirs)
+}
+
+fn env_arg(env: BuildEnv) -> &'static str {
+    match env {
+        BuildEnv::Debug => "debug",
+        BuildEnv::Release => "release",
+    }
+}
+
+fn describe_outcome(outcome: Option<&TxOutcome>) -> String {
+    match outcome {
+        None => "not verified".to_string(),
+        Some(TxOutcome {
+            exit_code: Some(exit_code),
+            ..
+        }) => format!("failed with exit code {}", exit_code),
+        Some(TxOutcome {
+            error: Some(error), ..
+        }) => format!("failed: {}", error),
+        Some(TxOutcome { cycles, .. }) => format!("passed ({} cycles)", cycles.unwrap_or(0)),
+    }
+}
+
+/// Outcomes of a transaction in debug and release
+type OutcomePair<'a> = (Option<&'a TxOutcome>, Option<&'a TxOutcome>);
+
+/// Compare outcomes of the same transactions, cycles are expected to differ.
+/// Return descriptions of the divergent transactions.
+fn compare_outcomes(debug: &[TxOutcome], release: &[TxOutcome]) -> Vec<String> {
+    let mut txs: BTreeMap<(&str, usize), OutcomePair> = BTreeMap::new();
+    for outcome in debug {
+        txs.entry((&outcome.test, outcome.index)).or_default().0 = Some(outcome);
+    }
+    for outcome in release {
+        txs.entry((&outcome.test, outcome.index)).or_default().1 = Some(outcome);
+    }
+    txs.into_iter()
+        .filter(|(_, (debug, release))| match (debug, release) {
+            (Some(debug), Some(release)) => {
+                debug.is_success() != release.is_success()
+                    || debug.exit_code != release.exit_code
+                    || (debug.exit_code.is_none() && debug.error != release.error)
+            }
+            _ => true,
+        })
+        .map(|((test, index), (debug, release))| {
+            format!(
+                "{} tx #{}: debug {}, release {}",
+                test,
+                index,
+                describe_outcome(debug),
+                describe_outcome(release)
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(test: &str, index: usize, exit_code: Option<i8>) -> TxOutcome {
+        TxOutcome {
+            test: test.to_string(),
+            index,
+            tx_hash: String::new(),
+            cycles: exit_code.map_or(Some(index as u64), |_| None),
+            error: exit_code.map(|code| format!("error code {}", code)),
+            exit_code,
+        }
+    }
+
+    #[test]
+    fn test_test_artifacts() {
+        let messages = br#"{"reason":"compiler-artifact","manifest_path":"/p/tests/Cargo.toml","profile":{"test":false},"executable":null}
+{"reason":"compiler-artifact","manifest_path":"/p/tests/Cargo.toml","profile":{"test":true},"executable":"/p/target/debug/deps/tests-1234"}
+{"reason":"compiler-artifact","manifest_path":"/p/tests/Cargo.toml","profile":{"test":false},"executable":"/p/target/debug/tool"}
+{"reason":"build-finished","success":true}
+"#;
+        let artifacts = test_artifacts(messages);
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(
+            artifacts[0].executable,
+            PathBuf::from("/p/target/debug/deps/tests-1234")
+        );
+        assert_eq!(
+            artifacts[0].manifest_path,
+            PathBuf::from("/p/tests/Cargo.toml")
+        );
+    }
+
+    #[test]
+    fn test_compare_outcomes() {
+        let debug = vec![
+            outcome("tests::a", 0, None),
+            outcome("tests::a", 1, Some(5)),
+            outcome("tests::b", 0, Some(5)),
+            outcome("tests::c", 0, None),
+        ];
+        let mut release = debug.clone();
+        release[0].cycles = Some(42);
+        assert!(compare_outcomes(&debug, &release).is_empty());
+
+        release[1] = outcome("tests::a", 1, None);
+        release[2] = outcome("tests::b", 0, Some(6));
+        release.pop();
+        let divergences = compare_outcomes(&debug, &release);
+        assert_eq!(
+            divergences,
+            vec![
+                "tests::a tx #1: debug failed with exit code 5, release passed (1 cycles)",
+                "tests::b tx #0: debug failed with exit code 5, release failed with exit code 6",
+                "tests::c tx #0: debug passed (0 cycles), release not verified",
+            ]
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Machine-readable test results
+//!
+//! Per-test status and duration are parsed from the libtest JSON output,
+//! per-transaction outcomes are collected by ckb-testtool.
+
+use anyhow::{anyhow, Error, Result};
+use ckb_testtool::report::TxOutcome;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    JUnit,
+}
+
+impl ReportFormat {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "json",
+            ReportFormat::JUnit => "xml",
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ReportFormat::Json),
+            "junit" => Ok(ReportFormat::JUnit),
+            _ => Err(anyhow!("Unexpected report format '{}'", s)),
+        }
+    }
+}
+
+/// A line of the libtest JSON output
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TestEvent {
+    Suite {
+        event: String,
+    },
+    Test {
+        event: String,
+        name: String,
+        exec_time: Option<f64>,
+        stdout: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Ok,
+    Failed,
+    Ignored,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestTransaction {
+    pub index: usize,
+    pub tx_hash: String,
+    pub cycles: Option<u64>,
+    pub error: Option<String>,
+    pub exit_code: Option<i8>,
+}
+
+impl From<TxOutcome> for TestTransaction {
+    fn from(outcome: TxOutcome) -> Self {
+        TestTransaction {
+            index: outcome.index,
+            tx_hash: outcome.tx_hash,
+            cycles: outcome.cycles,
+            error: outcome.error,
+            exit_code: outcome.exit_code,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestResult {
+    pub name: String,
+    pub status: TestStatus,
+    /// Duration in seconds
+    pub duration: Option<f64>,
+    pub stdout: Option<String>,
+    pub transactions: Vec<TestTransaction>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct TestReport {
+    pub env: String,
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    /// Duration in seconds
+    pub duration: f64,
+    pub tests: Vec<TestResult>,
+}
+
+impl TestReport {
+    pub fn new(env: &str) -> Self {
+        TestReport {
+            env: env.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Add a finished test from libtest output, return false if the event isn't a test result
+    pub fn add_event(&mut self, event: TestEvent) -> bool {
+        let (event, name, exec_time, stdout) = match event {
+            TestEvent::Test {
+                event,
+                name,
+                exec_time,
+                stdout,
+            } => (event, name, exec_time, stdout),
+            _ => return false,
+        };
+        let status = match event.as_str() {
+            "ok" => TestStatus::Ok,
+            "failed" => TestStatus::Failed,
+            "ignored" => TestStatus::Ignored,
+            _ => return false,
+        };
+        match status {
+            TestStatus::Ok => self.passed += 1,
+            TestStatus::Failed => self.failed += 1,
+            TestStatus::Ignored => self.ignored += 1,
+        }
+        self.duration += exec_time.unwrap_or_default();
+        self.tests.push(TestResult {
+            name,
+            status,
+            duration: exec_time,
+            stdout,
+            transactions: Vec::new(),
+        });
+        true
+    }
+
+    /// Attach transactions to the tests verifying them
+    pub fn add_transactions(&mut self, outcomes: Vec<TxOutcome>) {
+        for outcome in outcomes {
+            if let Some(test) = self.tests.iter_mut().find(|t| t.name == outcome.test) {
+                test.transactions.push(outcome.into());
+            }
+        }
+    }
+
+    pub fn render(&self, format: ReportFormat) -> Result<String> {
+        match format {
+            ReportFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+            ReportFormat::JUnit => Ok(self.to_junit()),
+        }
+    }
+
+    fn to_junit(&self) -> String {
+        let mut xml = String::new();
+        let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(
+            xml,
+            r#"<testsuites name="capsule" tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+            self.tests.len(),
+            self.failed,
+            self.ignored,
+            self.duration
+        );
+        let _ = writeln!(
+            xml,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+            escape_xml(&self.env),
+            self.tests.len(),
+            self.failed,
+            self.ignored,
+            self.duration
+        );
+        for test in &self.tests {
+            let (classname, name) = test.name.rsplit_once("::").unwrap_or(("", &test.name));
+            let _ = writeln!(
+                xml,
+                r#"    <testcase classname="{}" name="{}" time="{:.3}">"#,
+                escape_xml(classname),
+                escape_xml(name),
+                test.duration.unwrap_or_default()
+            );
+            match test.status {
+                TestStatus::Failed => {
+                    let _ = writeln!(
+                        xml,
+                        r#"      <failure message="test failed">{}</failure>"#,
+                        escape_xml(test.stdout.as_deref().unwrap_or_default())
+                    );
+                }
+                TestStatus::Ignored => {
+                    let _ = writeln!(xml, "      <skipped/>");
+                }
+                TestStatus::Ok => {}
+            }
+            if !test.transactions.is_empty() {
+                let _ = writeln!(xml, "      <properties>");
+                for tx in &test.transactions {
+                    let value = match (&tx.cycles, &tx.error) {
+                        (Some(cycles), _) => format!("cycles: {}", cycles),
+                        (_, Some(error)) => format!("error: {}", error),
+                        _ => String::new(),
+                    };
+                    let _ = writeln!(
+                        xml,
+                        r#"        <property name="tx#{} {}" value="{}"/>"#,
+                        tx.index,
+                        tx.tx_hash,
+                        escape_xml(&value)
+                    );
+                }
+                let _ = writeln!(xml, "      </properties>");
+            }
+            let _ = writeln!(xml, "    </testcase>");
+        }
+        let _ = writeln!(xml, "  </testsuite>");
+        let _ = writeln!(xml, "</testsuites>");
+        xml
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut report = TestReport::new("debug");
+        for line in [
+            r#"{ "type": "suite", "event": "started", "test_count": 2 }"#,
+            r#"{ "type": "test", "event": "started", "name": "tests::test_success" }"#,
+            r#"{ "type": "test", "name": "tests::test_success", "event": "ok", "exec_time": 0.5 }"#,
+            r#"{ "type": "test", "name": "tests::test_empty_args", "event": "failed", "exec_time": 0.25, "stdout": "error code 5 <" }"#,
+        ] {
+            report.add_event(serde_json::from_str(line).unwrap());
+        }
+        report.add_transactions(vec![TxOutcome {
+            test: "tests::test_success".to_string(),
+            index: 0,
+            tx_hash: "0x00".to_string(),
+            cycles: Some(42),
+            error: None,
+            exit_code: None,
+        }]);
+        assert_eq!((report.passed, report.failed, report.ignored), (1, 1, 0));
+        assert_eq!(report.duration, 0.75);
+        assert_eq!(report.tests[0].transactions[0].cycles, Some(42));
+
+        let xml = report.render(ReportFormat::JUnit).unwrap();
+        assert!(xml.contains(r#"<testcase classname="tests" name="test_success" time="0.500">"#));
+        assert!(xml.contains(r#"<property name="tx#0 0x00" value="cycles: 42"/>"#));
+        assert!(xml.contains("error code 5 &lt;</failure>"));
+    }
+}
@@ -0,0 +1,809 @@
+//! Project upgrade
+//!
+//! Projects created by older versions of capsule are migrated step by step: each
+//! migration inspects the project files and rewrites the parts which predate the
+//! current layout, so a project is only changed where it's outdated. Finally the
+//! `version` of capsule.toml is bumped to the current capsule version.
+
+use crate::config::TemplateType;
+use crate::config_manipulate::Document;
+use crate::generator::{CreateContract, TEMPLATES};
+use crate::project_context::{Context, CARGO_CONFIG_FILE, CONFIG_FILE, CONTRACTS_DIR};
+use crate::recipe::rust::Rust;
+use crate::util::diff::unified_diff;
+use crate::version::Version;
+use anyhow::{anyhow, bail, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{table, value, Item};
+
+const CROSS_CONFIG_FILE: &str = "Cross.toml";
+const TOOLCHAIN_FILE: &str = "rust-toolchain";
+const MAKEFILE: &str = "Makefile";
+/// ckb-std supports stable Rust since this version
+const CKB_STD_STABLE_VERSION: (u64, u64) = (0, 13);
+/// Rule building ckb-lua in the Lua Makefile before capsule 0.10.4, which runs docker
+const LUA_BUILD_COMMON_RULE: &str =
+    "build-common:\n\tmake -C deps/ckb-lua all-via-docker\n\tcp -r deps/ckb-lua/build/. $(DIR)\n\n";
+/// Unstable features enabled by the contract template before capsule 0.10, they are
+/// stable or provided by ckb-std now
+const TEMPLATE_FEATURES: &[&str] = &[
+    "asm",
+    "lang_items",
+    "alloc_error_handler",
+    "panic_info_message",
+];
+
+/// A change of a file made by a step
+struct FileChange {
+    path: PathBuf,
+    before: Option<String>,
+    after: String,
+}
+
+struct Step {
+    since: &'static str,
+    description: &'static str,
+    changes: Vec<FileChange>,
+}
+
+/// Project files with the changes of the previous steps
+struct Files<'a> {
+    project_path: &'a Path,
+    current: BTreeMap<PathBuf, String>,
+    changes: Vec<FileChange>,
+}
+
+impl<'a> Files<'a> {
+    /// Read the file, the path is relative to the project
+    fn read(&self, path: &Path) -> Option<String> {
+        match self.current.get(path) {
+            Some(content) => Some(content.clone()),
+            None => fs::read_to_string(self.project_path.join(path)).ok(),
+        }
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.current.contains_key(path) || self.project_path.join(path).exists()
+    }
+
+    fn write(&mut self, path: &Path, content: String) {
+        let before = self.read(path);
+        if before.as_deref() == Some(content.as_str()) {
+            return;
+        }
+        match self.changes.iter_mut().find(|change| change.path == path) {
+            Some(change) => change.after = content.clone(),
+            None => self.changes.push(FileChange {
+                path: path.to_path_buf(),
+                before,
+                after: content.clone(),
+            }),
+        }
+        self.current.insert(path.to_path_buf(), content);
+    }
+}
+
+struct Migration {
+    /// The capsule version introducing the layout
+    since: &'static str,
+    description: &'static str,
+    migrate: fn(&Project, &mut Files) -> Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        since: "0.10.0",
+        description:
+            "Build Rust contracts with cross and stable Rust instead of the capsule build image",
+        migrate: migrate_to_cross,
+    },
+    Migration {
+        since: "0.10.3",
+        description: "Upgrade rust-toolchain and the release profile of the Rust workspace",
+        migrate: migrate_rust_toolchain,
+    },
+    Migration {
+        since: "0.10.4",
+        description: "Pass the container runtime and extra C flags to the C Makefile",
+        migrate: migrate_c_makefile,
+    },
+    Migration {
+        since: "0.10.4",
+        description: "Build Lua contracts natively or with other container runtimes",
+        migrate: migrate_lua_makefile,
+    },
+];
+
+/// The project to upgrade, paths are relative to the project directory
+struct Project {
+    context: Context,
+    workspace_dirs: Vec<PathBuf>,
+    rust_contract_dirs: Vec<PathBuf>,
+}
+
+impl Project {
+    fn has_contracts(&self, f: impl Fn(TemplateType) -> bool) -> bool {
+        self.context
+            .config
+            .contracts
+            .iter()
+            .any(|c| f(c.template_type))
+    }
+
+    fn has_rust_contracts(&self) -> bool {
+        self.has_contracts(|t| matches!(t, TemplateType::Rust | TemplateType::RustSharedLib))
+    }
+}
+
+fn render_template(name: &str) -> Result<String> {
+    Ok(TEMPLATES.render(name, &tera::Context::default())?)
+}
+
+/// Major and minor numbers of a version or a version requirement, e.g. `=0.10.0`
+fn version_numbers(version: &str) -> Option<(u64, u64)> {
+    let version = version
+        .trim()
+        .trim_start_matches(|c: char| !c.is_ascii_digit());
+    let mut numbers = version.split('.').map(|n| n.parse::<u64>().ok());
+    Some((numbers.next()??, numbers.next().flatten().unwrap_or(0)))
+}
+
+/// Full version numbers of a stable toolchain, e.g. `1.74.0`
+fn toolchain_version(toolchain: &str) -> Option<Vec<u64>> {
+    toolchain
+        .trim()
+        .split('.')
+        .map(|n| n.parse().ok())
+        .collect()
+}
+
+/// Version of the ckb-std dependency in the contract template
+fn template_ckb_std_version() -> Result<String> {
+    let content = TEMPLATES.render(
+        "rust/contract/Cargo-manifest.toml",
+        &tera::Context::from_serialize(CreateContract {
+            name: "contract".to_string(),
+        })?,
+    )?;
+    let doc = content.parse::<Document>()?;
+    doc.get("dependencies")
+        .and_then(|deps| deps.get("ckb-std"))
+        .and_then(|ckb_std| ckb_std.as_str())
+        .map(|version| version.to_string())
+        .ok_or_else(|| anyhow!("can't find ckb-std in the contract template"))
+}
+
+fn migrate_to_cross(project: &Project, files: &mut Files) -> Result<()> {
+    if !project.has_rust_contracts() {
+        return Ok(());
+    }
+    let cross_config = PathBuf::from(CROSS_CONFIG_FILE);
+    if !files.exists(&cross_config)
+        && !project
+            .workspace_dirs
+            .iter()
+            .any(|dir| files.exists(&dir.join(CROSS_CONFIG_FILE)))
+    {
+        files.write(&cross_config, render_template(CROSS_CONFIG_FILE)?);
+    }
+    let toolchain = PathBuf::from(TOOLCHAIN_FILE);
+    if let Some(content) = files.read(&toolchain) {
+        if content.trim().starts_with("nightly") {
+            files.write(&toolchain, render_template(TOOLCHAIN_FILE)?);
+        }
+    }
+    // old ckb-std requires nightly Rust
+    let ckb_std_version = template_ckb_std_version()?;
+    for dir in &project.rust_contract_dirs {
+        let path = dir.join(CARGO_CONFIG_FILE);
+        let mut doc = match files.read(&path) {
+            Some(content) => content.parse::<Document>()?,
+            None => continue,
+        };
+        let dependency = match doc
+            .get_mut("dependencies")
+            .and_then(|deps| deps.get_mut("ckb-std"))
+        {
+            Some(dependency) => dependency,
+            None => continue,
+        };
+        // `ckb-std = "0.10.0"` or `ckb-std = { version = "0.10.0", ... }`
+        let version = if dependency.is_str() {
+            Some(dependency)
+        } else {
+            dependency
+                .as_table_like_mut()
+                .and_then(|t| t.get_mut("version"))
+        };
+        let version = match version {
+            Some(version) => version,
+            None => continue,
+        };
+        let outdated = version
+            .as_str()
+            .and_then(version_numbers)
+            .map(|numbers| numbers < CKB_STD_STABLE_VERSION)
+            .unwrap_or(false);
+        if outdated {
+            if let Some(v) = version.as_value_mut() {
+                let decor = v.decor().clone();
+                *v = ckb_std_version.as_str().into();
+                *v.decor_mut() = decor;
+            }
+            files.write(&path, doc.to_string());
+        }
+    }
+    for dir in &project.rust_contract_dirs {
+        for root in ["src/main.rs", "src/lib.rs"] {
+            let path = dir.join(root);
+            let content = match files.read(&path) {
+                Some(content) => content,
+                None => continue,
+            };
+            if let Some(content) = strip_template_features(files, dir, &path, &content)? {
+                files.write(&path, content);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove the `#![feature(...)]` of the old contract template from the crate root, which
+/// can't be compiled by stable Rust. Other unstable features and lang items defined by
+/// the contract can't be migrated, they must be removed by hand.
+fn strip_template_features(
+    files: &Files,
+    contract_dir: &Path,
+    path: &Path,
+    content: &str,
+) -> Result<Option<String>> {
+    let mut lines = Vec::new();
+    let mut stripped = Vec::new();
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("#[lang") || trimmed.starts_with("#[alloc_error_handler") {
+            bail!(
+                "{:?} defines lang items, which require nightly Rust, please remove them \
+                 and use the ones of ckb-std before upgrading",
+                path
+            );
+        }
+        let features = match trimmed
+            .strip_prefix("#![feature(")
+            .and_then(|features| features.strip_suffix(")]"))
+        {
+            Some(features) => features,
+            None => {
+                lines.push(line);
+                continue;
+            }
+        };
+        for feature in features.split(',').map(str::trim) {
+            if !feature.is_empty() && !TEMPLATE_FEATURES.contains(&feature) {
+                bail!(
+                    "{:?} enables the unstable feature `{}`, which can't be built with stable Rust, \
+                     please remove it before upgrading",
+                    path,
+                    feature
+                );
+            }
+            stripped.push(feature);
+        }
+    }
+    if stripped.is_empty() {
+        return Ok(None);
+    }
+    // `asm!` must be imported from `core::arch` on stable Rust
+    if stripped.contains(&"asm") && uses_builtin_asm(&files.project_path.join(contract_dir))? {
+        bail!(
+            "{:?} uses `asm!` of the unstable feature `asm`, please import it with \
+             `use core::arch::asm;` and remove the feature before upgrading",
+            contract_dir
+        );
+    }
+    let mut content = lines.join("\n");
+    content.push('\n');
+    Ok(Some(content))
+}
+
+/// Return true if a source file of the crate calls `asm!` without importing it
+fn uses_builtin_asm(dir: &Path) -> Result<bool> {
+    if dir.is_dir() {
+        if dir
+            .file_name()
+            .map(|name| name == "target")
+            .unwrap_or(false)
+        {
+            return Ok(false);
+        }
+        for entry in fs::read_dir(dir)? {
+            if uses_builtin_asm(&entry?.path())? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    } else if dir.extension().map(|ext| ext == "rs").unwrap_or(false) {
+        let content = fs::read_to_string(dir)?;
+        Ok(content.contains("asm!") && !content.contains("core::arch::asm"))
+    } else {
+        Ok(false)
+    }
+}
+
+fn migrate_rust_toolchain(project: &Project, files: &mut Files) -> Result<()> {
+    if !project.has_rust_contracts() {
+        return Ok(());
+    }
+    let toolchain = PathBuf::from(TOOLCHAIN_FILE);
+    let template = render_template(TOOLCHAIN_FILE)?;
+    if let Some(content) = files.read(&toolchain) {
+        let outdated = match (toolchain_version(&content), toolchain_version(&template)) {
+            (Some(version), Some(template_version)) => version < template_version,
+            _ => false,
+        };
+        if outdated {
+            files.write(&toolchain, template);
+        }
+    }
+    // add the settings of the release profile which are missing
+    for workspace_dir in &project.workspace_dirs {
+        add_release_profile(files, &workspace_dir.join(CARGO_CONFIG_FILE))?;
+    }
+    Ok(())
+}
+
+fn add_release_profile(files: &mut Files, manifest: &Path) -> Result<()> {
+    let mut doc = match files.read(manifest) {
+        Some(content) => content.parse::<Document>()?,
+        None => return Ok(()),
+    };
+    let template = render_template("Cargo-manifest.toml")?.parse::<Document>()?;
+    let template_profile = match template
+        .get("profile")
+        .and_then(|profile| profile.get("release"))
+        .and_then(|release| release.as_table())
+    {
+        Some(profile) => profile,
+        None => return Ok(()),
+    };
+    let profile = doc["profile"].or_insert(table());
+    if let Some(profile) = profile.as_table_mut() {
+        profile.set_implicit(true);
+    }
+    let release = profile["release"]
+        .or_insert(table())
+        .as_table_like_mut()
+        .ok_or_else(|| anyhow!("`profile.release` is not a table in {:?}", manifest))?;
+    let mut changed = false;
+    for (key, item) in template_profile.iter() {
+        if !release.contains_key(key) {
+            let item: Item = match item.as_value() {
+                Some(v) => value(v.clone()),
+                None => continue,
+            };
+            release.insert(key, item);
+            changed = true;
+        }
+    }
+    if changed {
+        files.write(manifest, doc.to_string());
+    }
+    Ok(())
+}
+
+/// Rewrite the lines of the file if it exists, lines are kept if the function returns None
+fn rewrite_lines(files: &mut Files, path: &Path, f: impl Fn(&str) -> Option<String>) {
+    let content = match files.read(path) {
+        Some(content) => content,
+        None => return,
+    };
+    let mut rewritten: String = content
+        .lines()
+        .map(|line| f(line).unwrap_or_else(|| line.to_string()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if content.ends_with('\n') {
+        rewritten.push('\n');
+    }
+    files.write(path, rewritten);
+}
+
+/// Append `$(EXTRA_CFLAGS)` to the flags variable, which passes the `cflags` of capsule.toml
+fn append_extra_cflags(line: &str, variable: &str) -> Option<String> {
+    let (name, _) = line.split_once(":=")?;
+    if name.trim() == variable && !line.contains("$(EXTRA_CFLAGS)") {
+        Some(format!("{} $(EXTRA_CFLAGS)", line.trim_end()))
+    } else {
+        None
+    }
+}
+
+/// Insert the lines at the index of the line, which is found by the function
+fn insert_lines(
+    content: &str,
+    lines: &str,
+    f: impl Fn(&[&str]) -> Option<usize>,
+) -> Option<String> {
+    let mut result: Vec<&str> = content.lines().collect();
+    let index = f(&result)?;
+    result.insert(index, lines);
+    let mut result = result.join("\n");
+    if content.ends_with('\n') {
+        result.push('\n');
+    }
+    Some(result)
+}
+
+fn migrate_c_makefile(project: &Project, files: &mut Files) -> Result<()> {
+    // C contracts built with clang are created by the current version
+    if !project.has_contracts(|t| matches!(t, TemplateType::C | TemplateType::CSharedLib)) {
+        return Ok(());
+    }
+    let makefile = PathBuf::from(CONTRACTS_DIR).join("c").join(MAKEFILE);
+    rewrite_lines(files, &makefile, |line| {
+        if let Some(line) = append_extra_cflags(line, "CFLAGS") {
+            return Some(line);
+        }
+        line.strip_prefix("\tdocker run ")
+            .map(|rest| format!("\t$(DOCKER) run {}", rest))
+    });
+    if let Some(content) = files.read(&makefile) {
+        if !content.contains("DOCKER ?=") {
+            let lines = "# container runtime, e.g. docker or podman\nDOCKER ?= docker";
+            // before the build image
+            if let Some(content) = insert_lines(&content, lines, |lines| {
+                lines.iter().position(|line| {
+                    line.starts_with("# docker pull") || line.starts_with("BUILDER_DOCKER")
+                })
+            }) {
+                files.write(&makefile, content);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn migrate_lua_makefile(project: &Project, files: &mut Files) -> Result<()> {
+    if !project.has_contracts(|t| matches!(t, TemplateType::Lua | TemplateType::LuaEmbedded)) {
+        return Ok(());
+    }
+    let makefile = PathBuf::from(CONTRACTS_DIR).join("lua").join(MAKEFILE);
+    rewrite_lines(files, &makefile, |line| {
+        if let Some(line) = append_extra_cflags(line, "LUA_CFLAGS") {
+            return Some(line);
+        }
+        match line {
+            "build: build-common build-linked-binary-via-docker" => {
+                Some("build: build-common build-linked-binary$(VIA_DOCKER)".to_string())
+            }
+            "\tdocker run --rm -v `pwd`:/code ${LUA_BUILDER_IMAGE} bash -c \"cd /code && make build-linked-binary\"" => Some(
+                "\t$(DOCKER) run --rm -v `pwd`:/code ${LUA_BUILDER_IMAGE} bash -c \"cd /code && make build-linked-binary EXTRA_CFLAGS='$(EXTRA_CFLAGS)'\"".to_string(),
+            ),
+            _ => None,
+        }
+    });
+    // ckb-lua is built by the Makefile of the project, with the container runtime
+    if let Some(content) = files.read(&makefile) {
+        if content.contains(LUA_BUILD_COMMON_RULE) {
+            // the rules from `build-common` to `clean` of the template
+            let template = render_template("lua/Makefile")?;
+            let start = template.find("build-common:").unwrap_or(0);
+            let end = template.find("clean:").unwrap_or(template.len());
+            files.write(
+                &makefile,
+                content.replacen(LUA_BUILD_COMMON_RULE, &template[start..end], 1),
+            );
+        }
+    }
+    if let Some(content) = files.read(&makefile) {
+        if !content.contains("VIA_DOCKER ?=") {
+            let lines = "# set to empty to build with the local toolchain\nVIA_DOCKER ?= -via-docker\nDOCKER ?= docker\n# image of ckb-lua, overridden by the pinned Lua image of capsule.toml\nBUILDER_DOCKER := $(shell sed -n 's/^BUILDER_DOCKER *[:?]*= *//p' deps/ckb-lua/Makefile)";
+            if let Some(content) =
+                // after the build directory
+                insert_lines(&content, lines, |lines| {
+                    lines
+                        .iter()
+                        .position(|line| line.starts_with("DIR ="))
+                        .map(|index| index + 1)
+                })
+            {
+                files.write(&makefile, content);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub struct Plan {
+    from: String,
+    to: String,
+    steps: Vec<Step>,
+    /// capsule.toml with the bumped version
+    config: FileChange,
+}
+
+impl Plan {
+    /// Return true if the project is up to date
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty() && self.config.before.as_deref() == Some(self.config.after.as_str())
+    }
+
+    /// Print the steps, with the diffs of the changed files
+    pub fn print(&self, diff: bool) {
+        println!("Upgrade the project from {} to {}", self.from, self.to);
+        let total = self.steps.len() + 1;
+        for (index, step) in self.steps.iter().enumerate() {
+            println!(
+                "[{}/{}] {} (capsule {})",
+                index + 1,
+                total,
+                step.description,
+                step.since
+            );
+            for change in &step.changes {
+                print_change(change, diff);
+            }
+        }
+        println!("[{}/{}] Bump the version of {}", total, total, CONFIG_FILE);
+        print_change(&self.config, diff);
+    }
+
+    /// Write the changed files
+    pub fn apply(&self, project_path: &Path) -> Result<()> {
+        let mut files = BTreeMap::new();
+        for change in self.steps.iter().flat_map(|step| &step.changes) {
+            files.insert(&change.path, &change.after);
+        }
+        files.insert(&self.config.path, &self.config.after);
+        for (path, content) in files {
+            let path = project_path.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, content)?;
+        }
+        Ok(())
+    }
+}
+
+fn print_change(change: &FileChange, diff: bool) {
+    let path = change.path.display().to_string();
+    if change.before.is_none() {
+        println!("    create {}", path);
+    } else {
+        println!("    update {}", path);
+    }
+    if diff {
+        print!(
+            "{}",
+            unified_diff(
+                &path,
+                change.before.as_deref().unwrap_or_default(),
+                &change.after
+            )
+        );
+    }
+}
+
+/// Compute the changes upgrading the project to the current capsule version
+pub fn plan(context: &Context) -> Result<Plan> {
+    let current = Version::current();
+    let project_version: Version = context.config.version.parse()?;
+    if project_version.is_newer_than(&current) {
+        bail!(
+            "the project requires capsule {}, which is newer than the current version {}",
+            context.config.version,
+            current.to_string()
+        );
+    }
+    let relative_path = |path: PathBuf| {
+        path.strip_prefix(&context.project_path)
+            .map(|path| path.to_path_buf())
+            .unwrap_or(path)
+    };
+    let workspace_dirs = context
+        .workspace_dirs()?
+        .into_iter()
+        .map(relative_path)
+        .collect();
+    let recipe = Rust::new(context.clone());
+    let rust_contract_dirs = context
+        .config
+        .contracts
+        .iter()
+        .filter(|c| {
+            matches!(
+                c.template_type,
+                TemplateType::Rust | TemplateType::RustSharedLib
+            )
+        })
+        .map(|c| recipe.contract_path(c).map(relative_path))
+        .collect::<Result<_>>()?;
+    let project = Project {
+        context: context.clone(),
+        workspace_dirs,
+        rust_contract_dirs,
+    };
+    let mut files = Files {
+        project_path: &context.project_path,
+        current: BTreeMap::new(),
+        changes: Vec::new(),
+    };
+    let mut steps = Vec::new();
+    for migration in MIGRATIONS {
+        (migration.migrate)(&project, &mut files)?;
+        let changes = std::mem::take(&mut files.changes);
+        if !changes.is_empty() {
+            steps.push(Step {
+                since: migration.since,
+                description: migration.description,
+                changes,
+            });
+        }
+    }
+
+    let config_path = PathBuf::from(CONFIG_FILE);
+    let content = files
+        .read(&config_path)
+        .ok_or_else(|| anyhow!("can't read {}", CONFIG_FILE))?;
+    let mut doc = content.parse::<Document>()?;
+    let to = if current.is_newer_than(&project_version) {
+        current.to_string()
+    } else {
+        context.config.version.clone()
+    };
+    if let Some(v) = doc.get_mut("version").and_then(|v| v.as_value_mut()) {
+        let decor = v.decor().clone();
+        *v = to.as_str().into();
+        *v.decor_mut() = decor;
+    }
+    Ok(Plan {
+        from: context.config.version.clone(),
+        to,
+        steps,
+        config: FileChange {
+            path: config_path,
+            before: Some(content),
+            after: doc.to_string(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions() {
+        assert_eq!(version_numbers("0.10.0"), Some((0, 10)));
+        assert_eq!(version_numbers("=0.9"), Some((0, 9)));
+        assert_eq!(version_numbers("*"), None);
+        assert!(toolchain_version("1.71.1\n") < toolchain_version("1.74.0"));
+        assert_eq!(toolchain_version("nightly-2022-08-01"), None);
+        assert_eq!(
+            append_extra_cflags("CFLAGS := -O3 -g", "CFLAGS").as_deref(),
+            Some("CFLAGS := -O3 -g $(EXTRA_CFLAGS)")
+        );
+        assert_eq!(
+            append_extra_cflags("CFLAGS := -O3 -g $(EXTRA_CFLAGS)", "CFLAGS"),
+            None
+        );
+        assert_eq!(append_extra_cflags("LUA_CFLAGS := -O3", "CFLAGS"), None);
+    }
+
+    /// A project of capsule 0.9 with a Rust contract built by nightly Rust
+    fn setup_project(name: &str, main_rs: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("capsule-upgrade-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        for (file, content) in [
+            (
+                CONFIG_FILE,
+                "version = \"0.9.0\"\ndeployment = \"deployment.toml\"\n\n[[contracts]]\nname = \"lock\"\ntemplate_type = \"Rust\"\n",
+            ),
+            (CARGO_CONFIG_FILE, "[workspace]\nmembers = [\"contracts/lock\"]\n"),
+            (TOOLCHAIN_FILE, "nightly-2021-12-25\n"),
+            (
+                "contracts/lock/Cargo.toml",
+                "[package]\nname = \"lock\"\n\n[dependencies]\nckb-std = \"0.9.0\"\n",
+            ),
+            ("contracts/lock/src/main.rs", main_rs),
+        ] {
+            let file = path.join(file);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, content).unwrap();
+        }
+        path
+    }
+
+    fn plan_project(path: &Path) -> Result<Plan> {
+        let content = fs::read_to_string(path.join(CONFIG_FILE)).unwrap();
+        let config = toml_edit::de::from_str(&content).unwrap();
+        plan(&Context::from_config(path, config).unwrap())
+    }
+
+    #[test]
+    fn test_plan_nightly_project() {
+        let path = setup_project(
+            "nightly",
+            "#![no_std]\n#![no_main]\n#![feature(lang_items)]\n#![feature(alloc_error_handler, panic_info_message)]\n\nmod entry;\n\nckb_std::entry!(program_entry);\n",
+        );
+        let plan = plan_project(&path).unwrap();
+        let changes: BTreeMap<_, _> = plan
+            .steps
+            .iter()
+            .flat_map(|step| &step.changes)
+            .map(|change| (change.path.to_str().unwrap(), change.after.as_str()))
+            .collect();
+        assert_eq!(
+            changes["contracts/lock/src/main.rs"],
+            "#![no_std]\n#![no_main]\n\nmod entry;\n\nckb_std::entry!(program_entry);\n"
+        );
+        let ckb_std_version = template_ckb_std_version().unwrap();
+        assert!(changes["contracts/lock/Cargo.toml"]
+            .contains(&format!("ckb-std = \"{}\"", ckb_std_version)));
+        assert!(!changes[TOOLCHAIN_FILE].starts_with("nightly"));
+        assert!(changes.contains_key(CROSS_CONFIG_FILE));
+        assert_eq!(
+            plan.config.after.lines().next(),
+            Some(format!("version = \"{}\"", plan.to).as_str())
+        );
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_lua_makefile() {
+        let path = setup_project("lua", "");
+        fs::write(
+            path.join(CONFIG_FILE),
+            "version = \"0.9.0\"\n\n[[contracts]]\nname = \"hello\"\ntemplate_type = \"Lua\"\n",
+        )
+        .unwrap();
+        fs::create_dir_all(path.join("contracts/lua")).unwrap();
+        fs::write(
+            path.join("contracts/lua/Makefile"),
+            format!(
+                ".PHONY: build clean\n\nBUILD_TARGET ?= debug\nDIR = build/$(BUILD_TARGET)\n\n{}clean:\n\tmake -C deps/ckb-lua clean\n\trm -rf $(DIR)\n",
+                LUA_BUILD_COMMON_RULE
+            ),
+        )
+        .unwrap();
+        let plan = plan_project(&path).unwrap();
+        let makefile = plan
+            .steps
+            .iter()
+            .flat_map(|step| &step.changes)
+            .find(|change| change.path == Path::new("contracts/lua/Makefile"))
+            .expect("Makefile is migrated");
+        assert_eq!(makefile.after, render_template("lua/Makefile").unwrap());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_plan_unknown_features() {
+        for (name, main_rs, message) in [
+            (
+                "feature",
+                "#![no_std]\n#![feature(core_intrinsics)]\n",
+                "core_intrinsics",
+            ),
+            (
+                "lang",
+                "#![feature(lang_items)]\n#[lang = \"eh_personality\"]\nextern \"C\" fn eh_personality() {}\n",
+                "lang items",
+            ),
+            (
+                "asm",
+                "#![feature(asm)]\nfn exit() { unsafe { asm!(\"ecall\") } }\n",
+                "core::arch::asm",
+            ),
+        ] {
+            let path = setup_project(name, main_rs);
+            let err = plan_project(&path).err().expect("unsupported features");
+            assert!(err.to_string().contains(message), "{}", err);
+            fs::remove_dir_all(path).unwrap();
+        }
+    }
+}
@@ -1,12 +1,62 @@
 use crate::signal::Signal;
 use anyhow::{anyhow, Result};
 use log::debug;
-use std::io;
+use std::io::{self, Read, Write};
 use std::path::Path;
-use std::process::Command;
-use std::thread::sleep;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
 use std::time::Duration;
 
+const INTERRUPT_TIMEOUT_SECS: u64 = 5;
+
+/// Where the output of commands goes
+#[derive(Clone, Default)]
+pub enum Output {
+    /// Print to the terminal
+    #[default]
+    Inherit,
+    /// Collect stdout and stderr, used to print the output of parallel builds as a whole
+    Capture(Arc<Mutex<Vec<u8>>>),
+}
+
+impl Output {
+    pub fn capture() -> Self {
+        Output::Capture(Default::default())
+    }
+
+    pub fn println(&self, msg: &str) {
+        match self {
+            Output::Inherit => println!("{}", msg),
+            Output::Capture(buf) => {
+                let mut buf = buf.lock().expect("lock");
+                buf.extend_from_slice(msg.as_bytes());
+                buf.push(b'\n');
+            }
+        }
+    }
+
+    /// Take the captured output
+    pub fn take(&self) -> Vec<u8> {
+        match self {
+            Output::Inherit => Vec::new(),
+            Output::Capture(buf) => std::mem::take(&mut *buf.lock().expect("lock")),
+        }
+    }
+}
+
+/// Returned by `run_command` if the command is killed by Ctrl-C
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
 pub fn ask_for_confirm(msg: &str) -> Result<bool> {
     println!("{} (Yes/No)", msg);
     let mut buf = String::new();
@@ -15,18 +65,77 @@ pub fn ask_for_confirm(msg: &str) -> Result<bool> {
 }
 
 pub fn run<P: AsRef<Path>>(shell_cmd: String, workdir: P, signal: &Signal) -> Result<()> {
-    debug!("Run command: {}", shell_cmd);
+    match run_shell(shell_cmd, workdir, signal, &Output::Inherit) {
+        Err(err) if err.is::<Interrupted>() => {
+            println!("Exiting...");
+            signal.exit()
+        }
+        result => result,
+    }
+}
+
+/// Quote the argument for sh, it's kept as is if there are no special characters
+pub fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-+=./,:@%".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Run the shell command, return `Interrupted` on Ctrl-C
+pub fn run_shell<P: AsRef<Path>>(
+    shell_cmd: String,
+    workdir: P,
+    signal: &Signal,
+    output: &Output,
+) -> Result<()> {
     let mut cmd = Command::new("sh");
     cmd.arg("-c").arg(&shell_cmd).current_dir(workdir);
+    run_command(cmd, signal, output)
+}
+
+/// Run the command, kill it and return `Interrupted` on Ctrl-C
+pub fn run_command(mut cmd: Command, signal: &Signal, output: &Output) -> Result<()> {
+    debug!("Run command: {:?}", cmd);
+    let captured = matches!(output, Output::Capture(_));
+    if captured {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        // Run in a new process group, so we can interrupt the whole process tree,
+        // otherwise grandchildren keep the pipes open after the child is killed.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+    }
     let mut child = cmd.spawn()?;
-    while signal.is_running() {
+    let mut readers = Vec::new();
+    if let Output::Capture(buf) = output {
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(copy_to_buffer(stdout, Arc::clone(buf)));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(copy_to_buffer(stderr, Arc::clone(buf)));
+        }
+    }
+    let result = loop {
+        if !signal.is_running() {
+            if captured {
+                interrupt_process_group(&mut child);
+            }
+            child.kill()?;
+            child.wait()?;
+            // don't wait for the output of orphaned processes
+            return Err(Interrupted.into());
+        }
         match child.try_wait() {
             Ok(Some(status)) => {
                 if status.success() {
-                    return Ok(());
+                    break Ok(());
                 } else {
-                    let err = anyhow!("process exit with code {:?}", status.code());
-                    return Err(err);
+                    break Err(anyhow!("process exit with code {:?}", status.code()));
                 }
             }
             Ok(None) => {
@@ -35,8 +144,52 @@ pub fn run<P: AsRef<Path>>(shell_cmd: String, workdir: P, signal: &Signal) -> Re
             }
             Err(e) => panic!("error attempting to wait: {}", e),
         }
+    };
+    for reader in readers {
+        let _ = reader.join();
+    }
+    result
+}
+
+/// Send SIGINT to the process group of the child and give it a while to exit
+fn interrupt_process_group(child: &mut Child) {
+    let group = format!("-{}", child.id());
+    let sent = Command::new("kill")
+        .args(["-INT", "--", &group])
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    if !sent {
+        return;
+    }
+    for _ in 0..INTERRUPT_TIMEOUT_SECS * 10 {
+        if let Ok(Some(_)) = child.try_wait() {
+            return;
+        }
+        sleep(Duration::from_millis(100));
+    }
+}
+
+fn copy_to_buffer<R: Read + Send + 'static>(
+    mut reader: R,
+    buf: Arc<Mutex<Vec<u8>>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut chunk = [0u8; 4096];
+        while let Ok(n) = reader.read(&mut chunk) {
+            if n == 0 {
+                break;
+            }
+            buf.lock().expect("lock").extend_from_slice(&chunk[..n]);
+        }
+    })
+}
+
+/// Print the output with each line prefixed, the output is printed as a whole
+pub fn print_prefixed(prefix: &str, output: &[u8]) {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for line in String::from_utf8_lossy(output).lines() {
+        let _ = writeln!(stdout, "[{}] {}", prefix, line);
     }
-    println!("Exiting...");
-    child.kill()?;
-    signal.exit()
 }
@@ -0,0 +1,131 @@
+//! Line diff
+//!
+//! A small unified diff of text files, used to preview the changes of commands
+//! rewriting project files. Files are small, so the longest common subsequence
+//! is computed with a plain table.
+
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Unified diff of the file, empty if the contents are the same
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    let old_lines: Vec<_> = old.lines().collect();
+    let new_lines: Vec<_> = new.lines().collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+    let mut output = format!("--- a/{}\n+++ b/{}\n", path, path);
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(index, _)| index)
+        .collect();
+    let mut index = 0;
+    while index < changed.len() {
+        // group the changes whose contexts overlap into a hunk
+        let start = changed[index].saturating_sub(CONTEXT_LINES);
+        let mut end = changed[index];
+        while index < changed.len() && changed[index] <= end + 2 * CONTEXT_LINES + 1 {
+            end = changed[index];
+            index += 1;
+        }
+        let end = (end + CONTEXT_LINES + 1).min(lines.len());
+        let old_start = lines[..start]
+            .iter()
+            .filter(|line| !matches!(line, Line::Added(_)))
+            .count();
+        let new_start = lines[..start]
+            .iter()
+            .filter(|line| !matches!(line, Line::Removed(_)))
+            .count();
+        let hunk = &lines[start..end];
+        let old_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Added(_)))
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Removed(_)))
+            .count();
+        // an empty range starts at the line before it
+        let first_line = |start: usize, count: usize| if count == 0 { start } else { start + 1 };
+        output.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            first_line(old_start, old_count),
+            old_count,
+            first_line(new_start, new_count),
+            new_count
+        ));
+        for line in hunk {
+            let (prefix, text) = match line {
+                Line::Same(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            output.push(prefix);
+            output.push_str(text);
+            output.push('\n');
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        assert_eq!(unified_diff("a", "x\n", "x\n"), "");
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n";
+        assert_eq!(
+            unified_diff("numbers", old, new),
+            "--- a/numbers\n+++ b/numbers\n\
+             @@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+             @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n"
+        );
+        assert_eq!(
+            unified_diff("new", "", "a\n"),
+            "--- a/new\n+++ b/new\n@@ -0,0 +1,1 @@\n+a\n"
+        );
+    }
+}
@@ -1,13 +1,61 @@
+use crate::config::ContainerRuntime;
 use crate::project_context::Context;
 use crate::signal::Signal;
+use crate::util::cli::{self, Output};
 use anyhow::{anyhow, Result};
 use log::debug;
 use std::env;
+use std::path::Path;
 use std::process::{Command, Stdio};
+use std::sync::OnceLock;
 use std::thread::sleep;
 use std::time::Duration;
 
-const DOCKER_BIN: &str = "docker";
+pub const CONTAINER_RUNTIME_ENV: &str = "CAPSULE_CONTAINER_RUNTIME";
+
+impl ContainerRuntime {
+    /// Read the runtime from `CAPSULE_CONTAINER_RUNTIME`
+    pub fn from_env() -> Result<Option<Self>> {
+        match env::var(CONTAINER_RUNTIME_ENV) {
+            Ok(value) if !value.is_empty() => Ok(Some(value.parse()?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn program(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Podman => "podman",
+            ContainerRuntime::Nerdctl => "nerdctl",
+        }
+    }
+
+    /// Return true if containers run in a user namespace, where root of the container
+    /// is the current user of the host. The runtime is only asked once per process.
+    pub fn is_rootless(&self) -> bool {
+        static ROOTLESS: [OnceLock<bool>; 3] = [OnceLock::new(), OnceLock::new(), OnceLock::new()];
+        *ROOTLESS[*self as usize].get_or_init(|| self.detect_rootless())
+    }
+
+    fn detect_rootless(&self) -> bool {
+        let info = |format: &str| {
+            Command::new(self.program())
+                .args(["info", "--format", format])
+                .stderr(Stdio::null())
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+                .unwrap_or_default()
+        };
+        match self {
+            ContainerRuntime::Docker => info("{{.SecurityOptions}}").contains("rootless"),
+            ContainerRuntime::Podman => info("{{.Host.Security.Rootless}}") == "true",
+            // nerdctl runs the rootless containerd of non-root users
+            ContainerRuntime::Nerdctl => users::get_current_uid() != 0,
+        }
+    }
+}
 
 struct Volume {
     volume: String,
@@ -20,6 +68,9 @@ struct Port {
 
 pub struct DockerCommand {
     bin: String,
+    rootless: bool,
+    // relabel bind mounts for SELinux
+    relabel_volumes: bool,
     uid: u32,
     gid: u32,
     user: String,
@@ -37,18 +88,45 @@ pub struct DockerCommand {
     inherited_env: Vec<&'static str>,
 }
 
+/// Return the local image id of the image, None if the image isn't pulled
+pub fn image_id(runtime: ContainerRuntime, image: &str) -> Option<String> {
+    let output = Command::new(runtime.program())
+        .args(["image", "inspect", "--format", "{{.Id}}", image])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let id = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    Some(id).filter(|id| !id.is_empty())
+}
+
 impl DockerCommand {
     pub fn with_context(
-        _context: &Context,
+        context: &Context,
         docker_image: String,
         code_path: String,
         env_file: String,
     ) -> Self {
-        Self::with_config(docker_image, code_path, env_file)
+        Self::with_config(
+            context.config.container_runtime,
+            docker_image,
+            code_path,
+            env_file,
+        )
     }
 
-    pub fn with_config(docker_image: String, code_path: String, env_file: String) -> Self {
-        let bin = DOCKER_BIN.to_string();
+    pub fn with_config(
+        runtime: ContainerRuntime,
+        docker_image: String,
+        code_path: String,
+        env_file: String,
+    ) -> Self {
+        let bin = runtime.program().to_string();
+        let rootless = runtime.is_rootless();
+        let relabel_volumes =
+            runtime == ContainerRuntime::Podman && Path::new("/sys/fs/selinux/enforce").exists();
         let uid = users::get_current_uid();
         let gid = users::get_current_gid();
         let user = users::get_current_username()
@@ -58,6 +136,8 @@ impl DockerCommand {
             .to_string();
         DockerCommand {
             bin,
+            rootless,
+            relabel_volumes,
             uid,
             gid,
             user,
@@ -114,7 +194,8 @@ impl DockerCommand {
 
     pub fn run(self, shell_cmd: String, signal: &Signal) -> Result<()> {
         debug!("Run command in docker: {}", shell_cmd);
-        let mut cmd = self.build(shell_cmd)?;
+        let interactive = atty::is(atty::Stream::Stdin);
+        let mut cmd = self.build(shell_cmd, interactive)?;
         let mut child = cmd.spawn()?;
         while signal.is_running() {
             match child.try_wait() {
@@ -138,9 +219,22 @@ impl DockerCommand {
         signal.exit()
     }
 
-    pub fn stop(name: &str) -> Result<()> {
+    /// Run the command with the output redirected, return `cli::Interrupted` on Ctrl-C
+    pub fn run_with_output(
+        self,
+        shell_cmd: String,
+        signal: &Signal,
+        output: &Output,
+    ) -> Result<()> {
+        debug!("Run command in docker: {}", shell_cmd);
+        let interactive = matches!(output, Output::Inherit) && atty::is(atty::Stream::Stdin);
+        let cmd = self.build(shell_cmd, interactive)?;
+        cli::run_command(cmd, signal, output)
+    }
+
+    pub fn stop(runtime: ContainerRuntime, name: &str) -> Result<()> {
         println!("Stop container {}...", name);
-        let mut cmd = Command::new(DOCKER_BIN);
+        let mut cmd = Command::new(runtime.program());
         cmd.args(["stop", name]);
         let exit_status = cmd.spawn()?.wait()?;
         if !exit_status.success() {
@@ -153,9 +247,11 @@ impl DockerCommand {
         Ok(())
     }
 
-    fn build(self, mut shell_cmd: String) -> Result<Command> {
+    fn build(self, mut shell_cmd: String, interactive: bool) -> Result<Command> {
         let DockerCommand {
             bin,
+            rootless,
+            relabel_volumes,
             uid,
             gid,
             user,
@@ -184,9 +280,10 @@ impl DockerCommand {
             format!("-eGID={}", gid).as_str(),
             format!("-eUSER={}", user).as_str(),
             "--rm",
-            format!("-v{}:/code", code_path).as_str(),
             format!("-w{}", workdir).as_str(),
         ]);
+        let volume_options = if relabel_volumes { ":z" } else { "" };
+        cmd.arg(format!("-v{}:/code{}", code_path, volume_options));
 
         // reusing cargo cache
         // mapping local volume `capsule-cache` to reusing cargo cache
@@ -199,7 +296,10 @@ impl DockerCommand {
 
         // mapping volumes
         for volumn in mapping_volumes {
-            cmd.arg(format!("-v{}:{}", volumn.volume, volumn.container).as_str());
+            cmd.arg(format!(
+                "-v{}:{}{}",
+                volumn.volume, volumn.container, volume_options
+            ));
         }
 
         // mapping ports
@@ -231,13 +331,14 @@ impl DockerCommand {
             cmd.arg("-d");
         }
 
-        if atty::is(atty::Stream::Stdin) {
+        if interactive {
             cmd.arg("-it");
         }
 
-        // fix files permission
+        // fix files permission, files created by root of rootless containers
+        // are already owned by the current user
         shell_cmd.push_str("; EXITCODE=$?");
-        for f in &fix_permission_files {
+        for f in fix_permission_files.iter().filter(|_| !rootless) {
             log::trace!("[fix permission] file: {}", f);
             shell_cmd.push_str(format!("; chown -R $UID:$GID {f}").as_str());
         }
@@ -42,3 +42,41 @@ pub fn add_submodule(context: &Context, url: &str, rel_path: &str, commit_id: &s
         .status()?;
     wait(status)
 }
+
+/// Return the commit id of HEAD
+pub fn head_commit<P: AsRef<Path>>(dir: P) -> Result<String> {
+    let output = Command::new(GIT_BIN)
+        .args(["rev-parse", "HEAD"])
+        .current_dir(dir)
+        .output()?;
+    wait(output.status)?;
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Return true if the work tree has uncommitted changes
+pub fn is_dirty<P: AsRef<Path>>(dir: P) -> Result<bool> {
+    let output = Command::new(GIT_BIN)
+        .args(["status", "--porcelain"])
+        .current_dir(dir)
+        .output()?;
+    wait(output.status)?;
+    Ok(!output.stdout.is_empty())
+}
+
+/// Clone the repository into the directory
+pub fn clone<P: AsRef<Path>>(url: &str, dir: P) -> Result<()> {
+    let status = Command::new(GIT_BIN)
+        .args(["clone", "--quiet", url])
+        .arg(dir.as_ref())
+        .status()?;
+    wait(status)
+}
+
+/// Check out the branch, tag or commit
+pub fn checkout<P: AsRef<Path>>(dir: P, reference: &str) -> Result<()> {
+    let status = Command::new(GIT_BIN)
+        .args(["checkout", "--quiet", reference])
+        .current_dir(dir)
+        .status()?;
+    wait(status)
+}
@@ -1,3 +1,4 @@
 pub mod cli;
+pub mod diff;
 pub mod docker;
 pub mod git;
@@ -0,0 +1,268 @@
+//! Reproducible build verification
+//!
+//! Rebuild a contract in release mode with path remapping, and compare the data hash
+//! of the binary with the hash of the deployed one. The contract is rebuilt in
+//! `build/verify`, so the binaries in `build/release` are kept.
+
+use crate::builder::{build_contracts, BuildOptions};
+use crate::config::Contract;
+use crate::deployment::manage::{last_snapshot_path, read_snapshot};
+use crate::manifest::data_hash;
+use crate::project_context::{BuildConfig, BuildEnv, Context, DeployEnv};
+use crate::recipe::{get_recipe, Toolchain};
+use crate::signal::Signal;
+use crate::util::git;
+use anyhow::{anyhow, Result};
+use ckb_testtool::ckb_types::H256;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Dir in the build dir of contracts where the contract is rebuilt
+const VERIFY_BUILD_DIR: &str = "verify";
+
+/// Where the expected data hash comes from
+pub enum ExpectedHash {
+    Hash(H256),
+    Snapshot {
+        env: DeployEnv,
+        /// Default is the last snapshot in `migrations/<env>`
+        path: Option<PathBuf>,
+        /// Name of the cell in the snapshot
+        cell: String,
+    },
+}
+
+pub struct VerifyBuildReport {
+    pub contract: String,
+    pub binary_path: PathBuf,
+    pub size: usize,
+    pub expected: H256,
+    /// Description of where the expected hash comes from
+    pub expected_source: String,
+    pub actual: H256,
+    pub toolchain: Toolchain,
+    pub commit: Option<String>,
+    pub dirty: bool,
+}
+
+impl VerifyBuildReport {
+    pub fn is_match(&self) -> bool {
+        self.expected == self.actual
+    }
+}
+
+impl fmt::Display for VerifyBuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Contract: {}", self.contract)?;
+        writeln!(f, "Binary: {:?} ({} bytes)", self.binary_path, self.size)?;
+        match &self.commit {
+            Some(commit) if self.dirty => {
+                writeln!(f, "Commit: {} (with uncommitted changes)", commit)?
+            }
+            Some(commit) => writeln!(f, "Commit: {}", commit)?,
+            None => writeln!(f, "Commit: unknown")?,
+        }
+        writeln!(f, "Toolchain:")?;
+        if let Some(image) = &self.toolchain.image {
+            writeln!(f, "  image: {}", image)?;
+        }
+        if let Some(image_id) = &self.toolchain.image_id {
+            writeln!(f, "  image id: {}", image_id)?;
+        }
+        for version in &self.toolchain.versions {
+            writeln!(f, "  {}", version)?;
+        }
+        writeln!(
+            f,
+            "Expected data hash: {:#x} ({})",
+            self.expected, self.expected_source
+        )?;
+        writeln!(f, "Actual data hash:   {:#x}", self.actual)?;
+        if self.is_match() {
+            write!(f, "Result: match")
+        } else {
+            write!(f, "Result: MISMATCH")
+        }
+    }
+}
+
+/// Parse a hex hash with or without the `0x` prefix
+pub fn parse_hash(s: &str) -> Result<H256> {
+    H256::from_str(s.trim_start_matches("0x")).map_err(|err| anyhow!("invalid hash {}: {}", s, err))
+}
+
+fn resolve_expected_hash(context: &Context, expected: ExpectedHash) -> Result<(H256, String)> {
+    match expected {
+        ExpectedHash::Hash(hash) => Ok((hash, "provided".to_string())),
+        ExpectedHash::Snapshot { env, path, cell } => {
+            let path = match path {
+                Some(path) => path,
+                None => {
+                    let migration_dir = context.migrations_path(env);
+                    last_snapshot_path(&migration_dir)?.ok_or_else(|| {
+                        anyhow!("can't find any deployment snapshot in {:?}", migration_dir)
+                    })?
+                }
+            };
+            let recipe = read_snapshot(&path)?;
+            let cell_recipe = recipe
+                .cell_recipes
+                .into_iter()
+                .find(|c| c.name == cell)
+                .ok_or_else(|| anyhow!("can't find cell '{}' in snapshot {:?}", cell, path))?;
+            Ok((cell_recipe.data_hash, format!("from {:?}", path)))
+        }
+    }
+}
+
+/// The build is only reproducible if the image is pinned by digest
+fn check_pinned_image(image: Option<&str>, allow_unpinned: bool) -> Result<()> {
+    let reason = match image {
+        Some(image) if image.contains("@sha256:") => return Ok(()),
+        Some(image) => format!("build image {} is not pinned by digest", image),
+        None => "the contract is not built in a build image".to_string(),
+    };
+    if allow_unpinned {
+        println!("warn: {}, the build may not be reproducible", reason);
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{}, the build may not be reproducible, pin the image with `capsule image update` or pass `--allow-unpinned`",
+            reason
+        ))
+    }
+}
+
+/// Rebuild the contract and compare its data hash with the expected one
+pub fn verify_build(
+    context: &Context,
+    contract: &Contract,
+    expected: ExpectedHash,
+    allow_unpinned: bool,
+    signal: &Signal,
+) -> Result<VerifyBuildReport> {
+    let (expected, expected_source) = resolve_expected_hash(context, expected)?;
+    let recipe = get_recipe(context.clone(), contract.template_type)?;
+    let toolchain = recipe.toolchain(contract)?;
+    check_pinned_image(toolchain.image.as_deref(), allow_unpinned)?;
+
+    // build into a separate dir, so the binaries in build/release are kept
+    let mut context = context.clone();
+    let build_dir = context.contracts_build_dir().join(VERIFY_BUILD_DIR);
+    if build_dir.exists() {
+        fs::remove_dir_all(&build_dir)?;
+    }
+    context.build_dir = Some(build_dir);
+    let recipe = get_recipe(context.clone(), contract.template_type)?;
+    let build_config = BuildConfig {
+        build_env: BuildEnv::Release,
+        always_debug: false,
+        remap: true,
+    };
+    let options = BuildOptions {
+        use_cache: false,
+        ..Default::default()
+    };
+    build_contracts(
+        &context,
+        &[contract.clone()],
+        build_config,
+        signal,
+        &[],
+        &options,
+    )?;
+
+    let binary_path = recipe.output_path(contract, BuildEnv::Release);
+    let binary = fs::read(&binary_path)?;
+    let commit = git::head_commit(&context.project_path).ok();
+    let dirty = commit.is_some() && git::is_dirty(&context.project_path)?;
+    Ok(VerifyBuildReport {
+        contract: contract.name.clone(),
+        binary_path,
+        size: binary.len(),
+        expected,
+        expected_source,
+        actual: data_hash(&binary),
+        toolchain,
+        commit,
+        dirty,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_hash() {
+        let hash = "0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8";
+        assert_eq!(parse_hash(hash).unwrap(), parse_hash(&hash[2..]).unwrap());
+        assert_eq!(format!("{:#x}", parse_hash(hash).unwrap()), hash);
+        assert!(parse_hash("0x1234").is_err());
+        assert!(parse_hash("hash").is_err());
+    }
+
+    #[test]
+    fn test_check_pinned_image() {
+        let pinned = "docker.io/nervos/capsule@sha256:0a1b";
+        assert!(check_pinned_image(Some(pinned), false).is_ok());
+        assert!(check_pinned_image(Some("docker.io/nervos/capsule:0.10"), false).is_err());
+        assert!(check_pinned_image(Some("docker.io/nervos/capsule:0.10"), true).is_ok());
+        assert!(check_pinned_image(None, false).is_err());
+        assert!(check_pinned_image(None, true).is_ok());
+    }
+
+    fn write_snapshot(path: &Path, data_hash: &H256) {
+        let recipe = serde_json::json!({
+            "cell_recipes": [{
+                "name": "lock",
+                "tx_hash": H256::default(),
+                "index": 0,
+                "occupied_capacity": 0,
+                "data_hash": data_hash,
+                "type_id": null,
+            }],
+            "dep_group_recipes": [],
+        });
+        fs::write(path, recipe.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_expected_hash_of_snapshot() {
+        let project_path =
+            std::env::temp_dir().join(format!("capsule-verify-build-{}", std::process::id()));
+        let context =
+            Context::from_config(&project_path, toml_edit::de::from_str("").unwrap()).unwrap();
+        let migration_dir = context.migrations_path(DeployEnv::Dev);
+        fs::create_dir_all(&migration_dir).unwrap();
+        let snapshot = |cell: &str| ExpectedHash::Snapshot {
+            env: DeployEnv::Dev,
+            path: None,
+            cell: cell.to_string(),
+        };
+        assert!(resolve_expected_hash(&context, snapshot("lock")).is_err());
+
+        let old_hash = H256([1; 32]);
+        let new_hash = H256([2; 32]);
+        write_snapshot(&migration_dir.join("2023-01-01-000000.json"), &old_hash);
+        write_snapshot(&migration_dir.join("2023-06-01-000000.json"), &new_hash);
+        let (hash, source) = resolve_expected_hash(&context, snapshot("lock")).unwrap();
+        assert_eq!(hash, new_hash);
+        assert!(source.contains("2023-06-01-000000.json"), "{}", source);
+        let (hash, _) = resolve_expected_hash(
+            &context,
+            ExpectedHash::Snapshot {
+                env: DeployEnv::Dev,
+                path: Some(migration_dir.join("2023-01-01-000000.json")),
+                cell: "lock".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(hash, old_hash);
+        assert!(resolve_expected_hash(&context, snapshot("type")).is_err());
+        fs::remove_dir_all(&project_path).unwrap();
+    }
+}
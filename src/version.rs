@@ -70,6 +70,11 @@ impl Version {
         }
     }
 
+    /// Compare the version numbers, ignoring the pre-release and the commit
+    pub fn is_newer_than(&self, version: &Version) -> bool {
+        (self.major, self.minor, self.patch) > (version.major, version.minor, version.patch)
+    }
+
     pub fn current() -> Self {
         let major = env!("CARGO_PKG_VERSION_MAJOR")
             .parse::<u8>()
@@ -17,11 +17,7 @@ pub fn tx_sign_message(tx: &TransactionView, begin_index: usize, len: usize) ->
     blake2b.update(&tx.hash().raw_data());
     // digest the first witness
     let witness = WitnessArgs::new_unchecked(tx.witnesses().get(begin_index).unwrap().unpack());
-    let zero_lock: Bytes = {
-        let mut buf = Vec::new();
-        buf.resize(SIGNATURE_SIZE, 0);
-        buf.into()
-    };
+    let zero_lock: Bytes = vec![0u8; SIGNATURE_SIZE].into();
     let witness_for_digest = witness.as_builder().lock(Some(zero_lock).pack()).build();
     let witness_len = witness_for_digest.as_bytes().len() as u64;
     blake2b.update(&witness_len.to_le_bytes());
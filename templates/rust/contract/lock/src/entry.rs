@@ -0,0 +1,121 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+// Import heap related library from `alloc`
+// https://doc.rust-lang.org/alloc/index.html
+use alloc::vec;
+
+// Import CKB syscalls and structures
+// https://docs.rs/ckb-std/
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, packed::WitnessArgs, prelude::*},
+    error::SysError,
+    high_level::{
+        load_input_since, load_script, load_tx_hash, load_witness, load_witness_args, QueryIter,
+    },
+};
+
+use blake2b_ref::{Blake2b, Blake2bBuilder};
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+
+use crate::error::Error;
+
+const BLAKE160_SIZE: usize = 20;
+const SIGNATURE_SIZE: usize = 65;
+
+/// The lock script args is the blake160 hash of a compressed secp256k1 public key,
+/// the lock field of the first witness in the script group is a recoverable signature
+/// of the transaction, hashed the same way as the default secp256k1 lock of CKB.
+pub fn main() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    if args.len() != BLAKE160_SIZE {
+        return Err(Error::InvalidArgs);
+    }
+
+    let witness_args = load_witness_args(0, Source::GroupInput)?;
+    let signature: Bytes = witness_args
+        .lock()
+        .to_opt()
+        .ok_or(Error::InvalidWitness)?
+        .unpack();
+    if signature.len() != SIGNATURE_SIZE {
+        return Err(Error::InvalidWitness);
+    }
+
+    let message = generate_sighash_all(witness_args)?;
+    let pubkey_hash = recover_pubkey_hash(&message, &signature)?;
+    if pubkey_hash[..] != args[..] {
+        return Err(Error::InvalidSignature);
+    }
+    Ok(())
+}
+
+fn new_blake2b() -> Blake2b {
+    Blake2bBuilder::new(32)
+        .personal(b"ckb-default-hash")
+        .build()
+}
+
+/// Hash the transaction, the witnesses of the script group and the witnesses without inputs,
+/// the lock field of the first witness is zero filled
+fn generate_sighash_all(witness_args: WitnessArgs) -> Result<[u8; 32], Error> {
+    let zero_lock: Bytes = Bytes::from(vec![0u8; SIGNATURE_SIZE]);
+    let witness_for_digest = witness_args
+        .as_builder()
+        .lock(Some(zero_lock).pack())
+        .build();
+    let witness_len = witness_for_digest.as_bytes().len() as u64;
+
+    let mut blake2b = new_blake2b();
+    blake2b.update(&load_tx_hash()?);
+    blake2b.update(&witness_len.to_le_bytes());
+    blake2b.update(&witness_for_digest.as_bytes());
+
+    // other witnesses in the script group
+    let mut i = 1;
+    loop {
+        match load_witness(i, Source::GroupInput) {
+            Ok(witness) => hash_witness(&mut blake2b, &witness),
+            Err(SysError::IndexOutOfBound) => break,
+            Err(err) => return Err(err.into()),
+        }
+        i += 1;
+    }
+    // witnesses without inputs
+    let mut i = QueryIter::new(load_input_since, Source::Input).count();
+    loop {
+        match load_witness(i, Source::Input) {
+            Ok(witness) => hash_witness(&mut blake2b, &witness),
+            Err(SysError::IndexOutOfBound) => break,
+            Err(err) => return Err(err.into()),
+        }
+        i += 1;
+    }
+
+    let mut message = [0u8; 32];
+    blake2b.finalize(&mut message);
+    Ok(message)
+}
+
+fn hash_witness(blake2b: &mut Blake2b, witness: &[u8]) {
+    blake2b.update(&(witness.len() as u64).to_le_bytes());
+    blake2b.update(witness);
+}
+
+/// Recover the public key from the signature and return its blake160 hash
+fn recover_pubkey_hash(message: &[u8; 32], signature: &[u8]) -> Result<[u8; 20], Error> {
+    let recovery_id = RecoveryId::from_byte(signature[64]).ok_or(Error::InvalidSignature)?;
+    let signature = Signature::from_slice(&signature[..64]).map_err(|_| Error::InvalidSignature)?;
+    let pubkey = VerifyingKey::recover_from_prehash(message, &signature, recovery_id)
+        .map_err(|_| Error::InvalidSignature)?;
+
+    let mut hash = [0u8; 32];
+    let mut blake2b = new_blake2b();
+    blake2b.update(pubkey.to_encoded_point(true).as_bytes());
+    blake2b.finalize(&mut hash);
+    let mut pubkey_hash = [0u8; BLAKE160_SIZE];
+    pubkey_hash.copy_from_slice(&hash[..BLAKE160_SIZE]);
+    Ok(pubkey_hash)
+}
@@ -0,0 +1,46 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+// Import CKB syscalls and structures
+// https://docs.rs/ckb-std/
+use ckb_std::{
+    ckb_types::{bytes::Bytes, prelude::*},
+    debug,
+    dynamic_loading_c_impl::{CKBDLContext, Symbol},
+    high_level::load_script,
+};
+
+use crate::error::Error;
+
+/// Memory the library is loaded into, which must hold the loaded segments of the library,
+/// including its .bss. The template library takes about 40 KB in release mode, debug
+/// builds are larger, increase it if the library grows.
+const LIBRARY_MEMORY_SIZE: usize = 128 * 1024;
+
+/// Signature of `plus_42` exported by the library
+type Plus42 = unsafe extern "C" fn(u32) -> u32;
+
+/// The script args is the data hash of the library, the library cell is in the cell deps
+pub fn main() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    if args.len() != 32 {
+        return Err(Error::InvalidArgs);
+    }
+    let mut library_hash = [0u8; 32];
+    library_hash.copy_from_slice(&args);
+
+    let mut context = unsafe { CKBDLContext::<[u8; LIBRARY_MEMORY_SIZE]>::new() };
+    let library = context
+        .load(&library_hash)
+        .map_err(|_| Error::LoadLibrary)?;
+    let plus_42: Symbol<Plus42> =
+        unsafe { library.get(b"plus_42") }.ok_or(Error::LoadLibrary)?;
+
+    let result = unsafe { plus_42(13) };
+    debug!("plus_42(13) = {}", result);
+    if result != 55 {
+        return Err(Error::UnexpectedResult);
+    }
+    Ok(())
+}
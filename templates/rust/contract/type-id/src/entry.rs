@@ -0,0 +1,68 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+// Import CKB syscalls and structures
+// https://docs.rs/ckb-std/
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    error::SysError,
+    high_level::{load_cell_type_hash, load_input, load_script, load_script_hash, QueryIter},
+};
+
+use blake2b_ref::Blake2bBuilder;
+
+use crate::error::Error;
+
+const TYPE_ID_SIZE: usize = 32;
+
+/// The first 32 bytes of the script args is the type id, which is the hash of the first
+/// input and the index of the output creating the cell, so the type script is unique.
+pub fn main() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    if args.len() < TYPE_ID_SIZE {
+        return Err(Error::InvalidArgs);
+    }
+
+    // there is at most one cell with the type id in the inputs and the outputs
+    if has_cell(1, Source::GroupInput)? || has_cell(1, Source::GroupOutput)? {
+        return Err(Error::InvalidTypeIdCells);
+    }
+    // the cell is updated or destroyed
+    if has_cell(0, Source::GroupInput)? {
+        return validate();
+    }
+
+    // the cell is created, check the type id
+    let first_input = load_input(0, Source::Input)?;
+    let script_hash = load_script_hash()?;
+    let output_index = QueryIter::new(load_cell_type_hash, Source::Output)
+        .position(|type_hash| type_hash == Some(script_hash))
+        .ok_or(Error::InvalidTypeIdCells)?;
+
+    let mut blake2b = Blake2bBuilder::new(32)
+        .personal(b"ckb-default-hash")
+        .build();
+    blake2b.update(first_input.as_slice());
+    blake2b.update(&(output_index as u64).to_le_bytes());
+    let mut type_id = [0u8; TYPE_ID_SIZE];
+    blake2b.finalize(&mut type_id);
+    if type_id[..] != args[..TYPE_ID_SIZE] {
+        return Err(Error::InvalidTypeId);
+    }
+    validate()
+}
+
+/// Check the cell here, the args after the type id can carry extra parameters
+fn validate() -> Result<(), Error> {
+    Ok(())
+}
+
+fn has_cell(index: usize, source: Source) -> Result<bool, Error> {
+    match load_cell_type_hash(index, source) {
+        Ok(_) => Ok(true),
+        Err(SysError::IndexOutOfBound) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
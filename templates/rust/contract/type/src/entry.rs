@@ -0,0 +1,52 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+// Import CKB syscalls and structures
+// https://docs.rs/ckb-std/
+use ckb_std::{
+    ckb_constants::Source,
+    debug,
+    high_level::{load_cell_data, QueryIter},
+};
+
+use crate::error::Error;
+
+pub fn main() -> Result<(), Error> {
+    // cells of the script group, which have the same type script as the running one
+    let inputs = QueryIter::new(load_cell_data, Source::GroupInput).count();
+    let outputs = QueryIter::new(load_cell_data, Source::GroupOutput).count();
+    debug!("group inputs: {}, group outputs: {}", inputs, outputs);
+
+    match (inputs, outputs) {
+        (0, _) => validate_creation(),
+        (_, 0) => validate_destruction(),
+        _ => validate_update(),
+    }
+}
+
+/// Cells are created, there are no cells of the type in the inputs
+fn validate_creation() -> Result<(), Error> {
+    // check the data of the created cells here
+    for data in QueryIter::new(load_cell_data, Source::GroupOutput) {
+        debug!("created cell data: {:?}", data);
+    }
+    Ok(())
+}
+
+/// Cells are updated, there are cells of the type in both the inputs and the outputs
+fn validate_update() -> Result<(), Error> {
+    // check the transition from the input data to the output data here
+    Ok(())
+}
+
+/// Cells are destroyed, there are no cells of the type in the outputs
+fn validate_destruction() -> Result<(), Error> {
+    // check whether the cells can be destroyed here
+    Ok(())
+}
+
+// Unit tests are supported.
+#[test]
+fn test_foo() {
+    assert!(true);
+}
@@ -0,0 +1,48 @@
+//! Generated by capsule
+//!
+//! A shared library loaded by contracts with `ckb_dlopen`,
+//! functions exported with `#[no_mangle] pub extern "C"` are looked up by name.
+//! The library is linked with `-shared` by capsule, there is no entry.
+
+#![no_std]
+#![no_main]
+
+use ckb_std::{
+    ckb_types::{packed::ScriptReader, prelude::*},
+    default_alloc, syscalls,
+};
+
+// The heap is in the .bss of the library, which is loaded into the memory given by the
+// loader, keep it small. The default heap of `default_alloc!()` is over 512 KB.
+default_alloc!(4 * 1024, 16 * 1024, 64);
+
+const SCRIPT_SIZE: usize = 32768;
+
+// error codes
+const ERROR_SYSCALL: i64 = -1;
+const ERROR_ENCODING: i64 = -2;
+
+#[no_mangle]
+pub extern "C" fn plus_42(num: u32) -> u32 {
+    42 + num
+}
+
+/// Length of the args of the running script, negative on errors
+#[no_mangle]
+pub extern "C" fn read_args_len() -> i64 {
+    let mut script = [0u8; SCRIPT_SIZE];
+    let len = match syscalls::load_script(&mut script, 0) {
+        Ok(len) => len,
+        Err(_) => return ERROR_SYSCALL,
+    };
+    match ScriptReader::from_slice(&script[..len]) {
+        Ok(script) => script.args().raw_data().len() as i64,
+        Err(_) => ERROR_ENCODING,
+    }
+}
+
+#[panic_handler]
+fn panic_handler(_info: &core::panic::PanicInfo) -> ! {
+    // exit the running script, the library has no way to return an error
+    syscalls::exit(-1)
+}
@@ -0,0 +1,53 @@
+use super::*;
+use ckb_testtool::ckb_types::{bytes::Bytes, core::TransactionBuilder, packed::*, prelude::*};
+use ckb_testtool::context::Context;
+
+const MAX_CYCLES: u64 = 10_000_000;
+
+// Stub generated by `capsule import`, the contract is run as the lock script of an input.
+// TODO: replace the args, the cells and the witnesses with ones the contract accepts,
+// then remove `#[ignore]`.
+#[test]
+#[ignore = "the stub transaction is not built for the imported contract yet"]
+fn test_{{ name | replace(from="-", to="_") }}() {
+    // deploy contract
+    let mut context = Context::default();
+    let contract_bin: Bytes = Loader::default().load_binary("{{ name }}");
+    let out_point = context.deploy_cell(contract_bin);
+
+    // prepare scripts
+    let lock_script = context
+        .build_script(&out_point, Bytes::new())
+        .expect("script");
+
+    // prepare cells
+    let input_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(1000u64.pack())
+            .lock(lock_script.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let input = CellInput::new_builder()
+        .previous_output(input_out_point)
+        .build();
+    let outputs = vec![CellOutput::new_builder()
+        .capacity(1000u64.pack())
+        .lock(lock_script)
+        .build()];
+    let outputs_data = vec![Bytes::new(); outputs.len()];
+
+    // build transaction
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .outputs(outputs)
+        .outputs_data(outputs_data.pack())
+        .build();
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
@@ -1,68 +1,8 @@
-use ckb_testtool::ckb_types::bytes::Bytes;
-use std::env;
-use std::fs;
-use std::path::PathBuf;
-use std::str::FromStr;
-
+// Contract binaries are loaded from `build/debug` or `build/release` by
+// `ckb_testtool::loader::Loader`, according to the `CAPSULE_TEST_ENV`
+// environment variable set by `capsule test`.
+pub use ckb_testtool::loader::{Loader, TestEnv};
+{% if example_tests %}
 #[cfg(test)]
 mod tests;
-
-const TEST_ENV_VAR: &str = "CAPSULE_TEST_ENV";
-
-pub enum TestEnv {
-    Debug,
-    Release,
-}
-
-impl FromStr for TestEnv {
-    type Err = &'static str;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "debug" => Ok(TestEnv::Debug),
-            "release" => Ok(TestEnv::Release),
-            _ => Err("no match"),
-        }
-    }
-}
-
-pub struct Loader(PathBuf);
-
-impl Default for Loader {
-    fn default() -> Self {
-        let test_env = match env::var(TEST_ENV_VAR) {
-            Ok(val) => val.parse().expect("test env"),
-            Err(_) => TestEnv::Debug,
-        };
-        Self::with_test_env(test_env)
-    }
-}
-
-impl Loader {
-    fn with_test_env(env: TestEnv) -> Self {
-        let load_prefix = match env {
-            TestEnv::Debug => "debug",
-            TestEnv::Release => "release",
-        };
-        let dir = env::current_dir().unwrap();
-        let mut base_path = PathBuf::new();
-        // cargo may use a different cwd when running tests, for example:
-        // when running debug in vscode, it will use workspace root as cwd by default,
-        // when running test by `cargo test`, it will use tests directory as cwd,
-        // so we need a fallback path
-        base_path.push("build");
-        if !base_path.exists() {
-            base_path.pop();
-            base_path.push("..");
-            base_path.push("build");
-        }
-        base_path.push(load_prefix);
-        Loader(base_path)
-    }
-
-    pub fn load_binary(&self, name: &str) -> Bytes {
-        let mut path = self.0.clone();
-        path.push(name);
-        fs::read(path).expect("binary").into()
-    }
-}
+{% endif -%}
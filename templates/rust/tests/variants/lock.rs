@@ -0,0 +1,121 @@
+use super::*;
+use ckb_testtool::ckb_crypto::secp::{Generator, Privkey};
+use ckb_testtool::ckb_error::Error;
+use ckb_testtool::ckb_hash::{blake2b_256, new_blake2b};
+use ckb_testtool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+    H256,
+};
+use ckb_testtool::context::Context;
+
+// recovering the public key costs more cycles than the default example
+const MAX_CYCLES: u64 = 100_000_000;
+const SIGNATURE_SIZE: usize = 65;
+
+// error numbers
+const ERROR_INVALID_SIGNATURE: i8 = 7;
+
+fn assert_script_error(err: Error, err_code: i8) {
+    let error_string = err.to_string();
+    assert!(
+        error_string.contains(format!("error code {} ", err_code).as_str()),
+        "error_string: {}, expected_error_code: {}",
+        error_string,
+        err_code
+    );
+}
+
+fn pubkey_hash(key: &Privkey) -> Bytes {
+    let pubkey = key.pubkey().expect("pubkey").serialize();
+    Bytes::from(blake2b_256(pubkey)[..20].to_vec())
+}
+
+/// Sign the transaction with a single input, the same way as the contract hashes it
+fn sign_tx(tx: TransactionView, key: &Privkey) -> TransactionView {
+    let zero_lock = Bytes::from(vec![0u8; SIGNATURE_SIZE]);
+    let witness = WitnessArgs::new_builder()
+        .lock(Some(zero_lock).pack())
+        .build();
+    let mut blake2b = new_blake2b();
+    blake2b.update(&tx.hash().raw_data());
+    blake2b.update(&(witness.as_bytes().len() as u64).to_le_bytes());
+    blake2b.update(&witness.as_bytes());
+    let mut message = [0u8; 32];
+    blake2b.finalize(&mut message);
+
+    let signature = key
+        .sign_recoverable(&H256::from(message))
+        .expect("sign")
+        .serialize();
+    let witness = witness
+        .as_builder()
+        .lock(Some(Bytes::from(signature)).pack())
+        .build();
+    tx.as_advanced_builder()
+        .set_witnesses(vec![witness.as_bytes().pack()])
+        .build()
+}
+
+fn build_tx(context: &mut Context, key: &Privkey) -> TransactionView {
+    // deploy contract
+    let contract_bin: Bytes = Loader::default().load_binary("{{ name }}");
+    let out_point = context.deploy_cell(contract_bin);
+
+    // prepare scripts
+    let lock_script = context
+        .build_script(&out_point, pubkey_hash(key))
+        .expect("script");
+
+    // prepare cells
+    let input_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(1000u64.pack())
+            .lock(lock_script.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let input = CellInput::new_builder()
+        .previous_output(input_out_point)
+        .build();
+    let outputs = vec![CellOutput::new_builder()
+        .capacity(1000u64.pack())
+        .lock(lock_script)
+        .build()];
+
+    // build transaction
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .outputs(outputs)
+        .outputs_data(vec![Bytes::new()].pack())
+        .build();
+    context.complete_tx(tx)
+}
+
+#[test]
+fn test_signed() {
+    let mut context = Context::default();
+    let key = Generator::random_privkey();
+    let tx = build_tx(&mut context, &key);
+    let tx = sign_tx(tx, &key);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_wrong_key() {
+    let mut context = Context::default();
+    let key = Generator::random_privkey();
+    let tx = build_tx(&mut context, &key);
+    let tx = sign_tx(tx, &Generator::random_privkey());
+
+    // run
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_SIGNATURE);
+}
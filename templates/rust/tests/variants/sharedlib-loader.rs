@@ -0,0 +1,75 @@
+use super::*;
+use ckb_testtool::ckb_error::Error;
+use ckb_testtool::ckb_types::{bytes::Bytes, core::TransactionBuilder, packed::*, prelude::*};
+use ckb_testtool::context::Context;
+
+const MAX_CYCLES: u64 = 10_000_000;
+
+// error numbers
+const ERROR_LOAD_LIBRARY: i8 = 6;
+
+fn assert_script_error(err: Error, err_code: i8) {
+    let error_string = err.to_string();
+    assert!(
+        error_string.contains(format!("error code {} ", err_code).as_str()),
+        "error_string: {}, expected_error_code: {}",
+        error_string,
+        err_code
+    );
+}
+
+/// Verify a transaction unlocked by the loader, which loads the library by the args
+fn verify(with_library_dep: bool) -> Result<u64, Error> {
+    // deploy contracts
+    let mut context = Context::default();
+    let loader = Loader::default();
+    let contract_bin: Bytes = loader.load_binary("{{ name }}");
+    let out_point = context.deploy_cell(contract_bin);
+    let (library_dep, library_hash) = context.deploy_library(loader.load_binary("{{ library }}"));
+
+    // prepare scripts
+    let lock_script = context
+        .build_script(&out_point, library_hash.as_bytes())
+        .expect("script");
+
+    // prepare cells
+    let input_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(1000u64.pack())
+            .lock(lock_script.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let input = CellInput::new_builder()
+        .previous_output(input_out_point)
+        .build();
+    let outputs = vec![CellOutput::new_builder()
+        .capacity(1000u64.pack())
+        .lock(lock_script)
+        .build()];
+
+    // build transaction, the library is referenced in the cell deps
+    let mut builder = TransactionBuilder::default()
+        .input(input)
+        .outputs(outputs)
+        .outputs_data(vec![Bytes::new()].pack());
+    if with_library_dep {
+        builder = builder.cell_dep(library_dep);
+    }
+    let tx = context.complete_tx(builder.build());
+
+    // run
+    context.verify_tx(&tx, MAX_CYCLES)
+}
+
+#[test]
+fn test_load_library() {
+    let cycles = verify(true).expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_missing_library() {
+    let err = verify(false).unwrap_err();
+    assert_script_error(err, ERROR_LOAD_LIBRARY);
+}
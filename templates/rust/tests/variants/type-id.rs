@@ -0,0 +1,109 @@
+use super::*;
+use ckb_testtool::builtin::ALWAYS_SUCCESS;
+use ckb_testtool::ckb_error::Error;
+use ckb_testtool::ckb_hash::new_blake2b;
+use ckb_testtool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
+use ckb_testtool::context::Context;
+
+const MAX_CYCLES: u64 = 10_000_000;
+
+// error numbers
+const ERROR_INVALID_TYPE_ID: i8 = 6;
+const ERROR_INVALID_TYPE_ID_CELLS: i8 = 7;
+
+fn assert_script_error(err: Error, err_code: i8) {
+    let error_string = err.to_string();
+    assert!(
+        error_string.contains(format!("error code {} ", err_code).as_str()),
+        "error_string: {}, expected_error_code: {}",
+        error_string,
+        err_code
+    );
+}
+
+/// Type id of the cell created by the output with the index
+fn type_id(first_input: &CellInput, output_index: u64) -> Bytes {
+    let mut blake2b = new_blake2b();
+    blake2b.update(first_input.as_slice());
+    blake2b.update(&output_index.to_le_bytes());
+    let mut type_id = [0u8; 32];
+    blake2b.finalize(&mut type_id);
+    Bytes::from(type_id.to_vec())
+}
+
+/// Create cells of the type with the args, return the transaction
+fn build_create_tx(context: &mut Context, args: Option<Bytes>, outputs: usize) -> TransactionView {
+    // deploy contracts
+    let contract_bin: Bytes = Loader::default().load_binary("{{ name }}");
+    let out_point = context.deploy_cell(contract_bin);
+    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+
+    // prepare cells
+    let lock_script = context
+        .build_script(&always_success_out_point, Default::default())
+        .expect("script");
+    let input_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(1000u64.pack())
+            .lock(lock_script.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let input = CellInput::new_builder()
+        .previous_output(input_out_point)
+        .build();
+
+    // the type id is computed from the first input, unless the args is given
+    let args = args.unwrap_or_else(|| type_id(&input, 0));
+    let type_script = context.build_script(&out_point, args).expect("script");
+    let cell = CellOutput::new_builder()
+        .capacity(500u64.pack())
+        .lock(lock_script)
+        .type_(Some(type_script).pack())
+        .build();
+
+    // build transaction
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .outputs(vec![cell; outputs])
+        .outputs_data(vec![Bytes::new(); outputs].pack())
+        .build();
+    context.complete_tx(tx)
+}
+
+#[test]
+fn test_create() {
+    let mut context = Context::default();
+    let tx = build_create_tx(&mut context, None, 1);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_invalid_type_id() {
+    let mut context = Context::default();
+    let tx = build_create_tx(&mut context, Some(Bytes::from(vec![0u8; 32])), 1);
+
+    // run
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_TYPE_ID);
+}
+
+#[test]
+fn test_duplicated_cells() {
+    let mut context = Context::default();
+    let tx = build_create_tx(&mut context, None, 2);
+
+    // run
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    assert_script_error(err, ERROR_INVALID_TYPE_ID_CELLS);
+}
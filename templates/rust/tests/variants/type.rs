@@ -0,0 +1,77 @@
+use super::*;
+use ckb_testtool::builtin::ALWAYS_SUCCESS;
+use ckb_testtool::ckb_types::{bytes::Bytes, core::TransactionBuilder, packed::*, prelude::*};
+use ckb_testtool::context::Context;
+
+const MAX_CYCLES: u64 = 10_000_000;
+
+/// Build a transaction with the cells of the type in the inputs and the outputs
+fn verify(inputs: usize, outputs: usize) {
+    // deploy contracts
+    let mut context = Context::default();
+    let contract_bin: Bytes = Loader::default().load_binary("{{ name }}");
+    let out_point = context.deploy_cell(contract_bin);
+    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+
+    // prepare scripts
+    let lock_script = context
+        .build_script(&always_success_out_point, Default::default())
+        .expect("script");
+    let type_script = context
+        .build_script(&out_point, Default::default())
+        .expect("script");
+
+    // prepare cells, a cell without the type pays for the created cells
+    let cell = CellOutput::new_builder()
+        .capacity(1000u64.pack())
+        .lock(lock_script.clone())
+        .type_(Some(type_script).pack())
+        .build();
+    let mut input_cells = vec![(
+        CellOutput::new_builder()
+            .capacity(1000u64.pack())
+            .lock(lock_script)
+            .build(),
+        Bytes::new(),
+    )];
+    input_cells.extend((0..inputs).map(|_| (cell.clone(), Bytes::from(vec![0u8; 8]))));
+    let inputs: Vec<_> = input_cells
+        .into_iter()
+        .map(|(output, data)| {
+            CellInput::new_builder()
+                .previous_output(context.create_cell(output, data))
+                .build()
+        })
+        .collect();
+    let outputs = vec![cell; outputs];
+    let outputs_data = vec![Bytes::from(vec![0u8; 8]); outputs.len()];
+
+    // build transaction
+    let tx = TransactionBuilder::default()
+        .inputs(inputs)
+        .outputs(outputs)
+        .outputs_data(outputs_data.pack())
+        .build();
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_create() {
+    verify(0, 1);
+}
+
+#[test]
+fn test_update() {
+    verify(1, 1);
+}
+
+#[test]
+fn test_destroy() {
+    verify(1, 0);
+}